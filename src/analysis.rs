@@ -0,0 +1,541 @@
+//! analysis.rs: Library entry point tying all phases together
+
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+use fnv::FnvHashMap;
+use crate::Sub;
+use crate::boilerplate;
+use crate::cluster::{self, Cluster, Group};
+use crate::db::{self, Database};
+use crate::error::Result;
+use crate::cli::{OptArgs, SubFileMode};
+use crate::file_io;
+use crate::fingerprint::{HashWidth, KgramMode};
+use crate::lsh::LshParams;
+use crate::normalize::NormOptions;
+use crate::phase_i::{self, FpParams, MaxFrequency};
+use crate::phase_ii::{self, SubPair};
+
+/// Analysis holds the parameters of a single run of the pipeline.
+///
+/// Typical usage:
+///
+/// ```no_run
+/// use std::path::Path;
+/// use pyret_moss::{Analysis, phase_iii};
+///
+/// let analysis = Analysis { k: 10, t: 15, ..Analysis::default() };
+/// let mut subs = analysis.load_subs(Path::new("./submissions"))?;
+/// let overlaps = analysis.run(&mut subs)?;
+///
+/// for pair in overlaps.pairs.iter() {
+///     let matches = phase_iii::analyze_pair(pair)?;
+///     println!("{} matches", matches.len());
+/// }
+/// # Ok::<(), pyret_moss::MossError>(())
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Analysis {
+    pub sub_mode: SubFileMode,                  // whether subs are files or dirs
+    pub k: i32,                                 // noise threshold
+    pub t: i32,                                 // guarantee threshold
+    pub norm: NormOptions,                      // normalizations applied before fingerprinting
+    pub kgrams: KgramMode,                      // whether k-grams (and k, t) are made of chars or tokens
+    pub hash_width: HashWidth,                  // range of fingerprint hash values
+    pub verify: bool,                           // check shared hashes stand for equal k-grams before pairing
+    pub match_threshold: f64,                   // keep sub pairs whose percentile is at least this
+    pub max_frequency: Option<MaxFrequency>,    // suppress fingerprints shared by more subs than this
+    pub lsh: Option<LshParams>,                 // if given, only pair up candidates chosen by MinHash LSH
+    pub cluster_similarity: Option<f64>,        // if given, cluster subs linked by pairs at least this similar
+    pub group_size: Option<usize>,              // if given, group subs by fingerprints shared by this many or more
+    pub ignore_content_dir: Option<PathBuf>,    // dir of files indicating expected overlap to ignore
+    pub archive_dir: Option<PathBuf>,           // dir of prior submissions to compare current ones against
+    pub db_file: Option<PathBuf>,               // database of stored submissions to compare current ones against
+    pub ignore_files: HashSet<String>,          // filenames of files to ignore
+    pub jobs: usize,                            // number of threads used to fingerprint documents
+    pub cache_dir: Option<PathBuf>,             // if given, cache fingerprints of documents in this dir
+    pub verbose: bool                           // option to increase intensity of logging
+}
+
+/// Overlaps is the result of running phases I & II over a set of submissions.
+/// Pairs are ordered by amount of overlap, descending.
+#[derive(Debug)]
+pub struct Overlaps<'a> {
+    pub hash_to_subs: FnvHashMap<i64, HashSet<&'a Sub>>,   // fingerprint hash => subs containing it
+    pub pairs: Vec<SubPair<'a>>,                           // pairs at or above the match threshold
+    pub total_pairs: usize,                                // number of pairs (only candidates, with lsh) before thresholding
+    pub suppressed: usize,                                 // number of hashes dropped by max_frequency
+    pub clusters: Vec<Cluster<'a>>,                        // clusters of subs, if cluster_similarity is given
+    pub groups: Vec<Group<'a>>                             // groups of subs, if group_size is given
+}
+
+/// analysis parameters default to those of the command-line interface
+impl Default for Analysis {
+    fn default() -> Self {
+        Analysis::from(&OptArgs::default())
+    }
+}
+
+/// build an analysis from parsed command-line arguments
+impl From<&OptArgs<'_>> for Analysis {
+    fn from(opts: &OptArgs) -> Self {
+        Analysis {
+            sub_mode: opts.sub_mode,
+            k: opts.k,
+            t: opts.t,
+            norm: opts.norm,
+            kgrams: opts.kgrams,
+            hash_width: opts.hash_width,
+            verify: opts.verify_matches,
+            match_threshold: opts.match_threshold,
+            max_frequency: opts.max_frequency,
+            lsh: opts.lsh,
+            cluster_similarity: opts.cluster_similarity,
+            group_size: opts.group_size,
+            ignore_content_dir: opts.ignore_content_dir.map(Path::to_path_buf),
+            archive_dir: opts.archive_dir.map(Path::to_path_buf),
+            db_file: opts.db_file.map(Path::to_path_buf),
+            ignore_files: opts.ignore_files.clone().unwrap_or_default(),
+            jobs: opts.jobs,
+            cache_dir: opts.cache_dir.map(Path::to_path_buf),
+            verbose: opts.verbose
+        }
+    }
+}
+
+impl Analysis {
+    /// Construct (unprocessed) submissions by traversing the given directory,
+    /// followed by (archived) submissions in the archive directory, if any
+    pub fn load_subs(&self, sub_dir: &Path) -> Result<Vec<Sub>> {
+        if self.verbose && !self.ignore_files.is_empty() {
+            println!("Ignoring files: {:?}", self.ignore_files);
+        }
+
+        let mut subs = file_io::construct_subs(sub_dir, &self.sub_mode,
+            &self.ignore_files, self.verbose)?;
+
+        // archived submissions are structured the same way as current ones
+        if let Some(ref archive_dir) = self.archive_dir {
+            if self.verbose { println!("\nLoading archived submissions..."); }
+
+            let mut archived = file_io::construct_subs(archive_dir, &self.sub_mode,
+                &self.ignore_files, self.verbose)?;
+
+            for sub in archived.iter_mut() { sub.archived = true; }
+            subs.append(&mut archived);
+        }
+
+        // stored submissions are already fingerprinted, & are treated like archived ones
+        if let Some(ref db_file) = self.db_file {
+            if self.verbose { println!("\nLoading stored submissions from {}", db_file.display()); }
+
+            let db = Database::load(db_file)?;
+            db.check_params(db_file, &self.params(), self.sub_mode)?;
+
+            // don't compare a current submission against its own stored copy
+            let current = subs.iter().map(db::sub_id).collect::<Result<HashSet<PathBuf>>>()?;
+
+            for stored in db.subs.into_iter() {
+                let id = db::sub_id(&stored)?;
+                if current.contains(&id) { continue; }
+
+                if self.verbose { println!("\tadding stored submission {}", id.display()); }
+                subs.push(Sub { archived: true, ..stored });
+            }
+        }
+
+        Ok(subs)
+    }
+
+    /// Fingerprint the submissions in the given directory & add them to the
+    /// database file (creating it if necessary). Submissions that are already
+    /// stored are skipped. Returns the number of submissions added & skipped.
+    pub fn index(&self, sub_dir: &Path, db_file: &Path) -> Result<(usize, usize)> {
+        let mut db = if db_file.exists() {
+            let db = Database::load(db_file)?;
+            db.check_params(db_file, &self.params(), self.sub_mode)?;
+            db
+        } else {
+            Database::new(self.params(), self.sub_mode)
+        };
+
+        let found = file_io::construct_subs(sub_dir, &self.sub_mode,
+            &self.ignore_files, self.verbose)?;
+
+        // only fingerprint submissions that aren't stored yet
+        let stored = db.stored_ids()?;
+        let total = found.len();
+        let mut subs = Vec::with_capacity(total);
+        for sub in found.into_iter() {
+            if !stored.contains(&db::sub_id(&sub)?) { subs.push(sub); }
+        }
+        let skipped = total - subs.len();
+
+        // store with absolute paths, so documents can be found from anywhere
+        for sub in subs.iter_mut() { db::make_absolute(sub); }
+
+        phase_i::analyze_subs(&mut subs, self.ignore_set()?, &self.params(),
+            self.jobs, self.cache_dir.as_deref(), self.verbose)?;
+
+        let added = subs.len();
+        db.subs.append(&mut subs);
+        db.save(db_file)?;
+
+        Ok((added, skipped))
+    }
+
+    /// Fingerprint the submissions in the given directory (only, not archived or stored
+    /// ones) & find the fingerprints shared by more of them than max_frequency allows
+    /// (50% by default). These are written as .arr snippets of the lines they came from
+    /// to snippets_dir, and/or as a set of hashes to hashes_file, either of which can be
+    /// given to --ignore-content in later runs. Returns the number of fingerprints & snippets suggested.
+    pub fn suggest_boilerplate(&self, sub_dir: &Path, snippets_dir: Option<&Path>,
+        hashes_file: Option<&Path>) -> Result<(usize, usize)> {
+        // only the given submissions (not archived or stored ones) suggest boilerplate
+        let mut subs = file_io::construct_subs(sub_dir, &self.sub_mode, &self.ignore_files, self.verbose)?;
+
+        // snippets come from the first documents containing each hash, so visit
+        // documents in order of path (unprocessed subs compare by path alone)
+        for sub in subs.iter_mut() { sub.documents.sort(); }
+        subs.sort();
+        let max_subs = self.max_frequency.unwrap_or(MaxFrequency::Percent(50.0)).limit(subs.len());
+
+        let hash_to_subs = phase_i::analyze_subs(&mut subs, self.ignore_set()?, &self.params(),
+            self.jobs, self.cache_dir.as_deref(), self.verbose)?;
+        let hashes = boilerplate::common_hashes(&hash_to_subs, max_subs);
+
+        if let Some(hashes_file) = hashes_file {
+            boilerplate::write_hashes(hashes_file, &self.params(), &hashes)?;
+        }
+
+        let mut num_snippets = 0;
+        if let Some(snippets_dir) = snippets_dir {
+            let snippets = boilerplate::snippets(&subs, &hashes)?;
+
+            if self.verbose {
+                for s in snippets.iter() {
+                    println!("\tlines {}-{} of {} ({} fingerprints)", s.lines.0, s.lines.1,
+                        s.path.display(), s.hashes);
+                }
+            }
+
+            boilerplate::write_snippets(snippets_dir, &snippets)?;
+            num_snippets = snippets.len();
+        }
+
+        Ok((hashes.len(), num_snippets))
+    }
+
+    /// The params with which documents are normalized/fingerprinted
+    fn params(&self) -> FpParams {
+        FpParams { k: self.k, t: self.t, norm: self.norm, kgrams: self.kgrams, hash_width: self.hash_width }
+    }
+
+    /// Construct the set of fingerprints to ignore when considering matches, if a
+    /// directory of files to ignore (or a file of hashes to ignore) is given
+    fn ignore_set(&self) -> Result<Option<HashSet<i64>>> {
+        match self.ignore_content_dir {
+            Some(ref p) => {
+                if self.verbose {
+                    println!("Ignoring content from {}", p.display());
+                }
+
+                if p.is_file() {
+                    Ok(Some(boilerplate::load_hashes(p, &self.params())?))
+                } else {
+                    Ok(Some(phase_i::make_ignore_set(p, &self.params())?))
+                }
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Normalize/fingerprint the given submissions (phase I) and pair
+    /// them up based on shared fingerprints (phase II). The submissions'
+    /// documents are Processed once this returns (stored submissions
+    /// are Processed already, & aren't refingerprinted).
+    ///
+    /// Matches within each pair can then be found with phase_iii::analyze_pair.
+    pub fn run<'a>(&self, subs: &'a mut [Sub]) -> Result<Overlaps<'a>> {
+        // fingerprints to ignore when considering matches, if any
+        let ignore_set = self.ignore_set()?;
+
+        // process all documents in each submission
+        phase_i::process_subs(subs, ignore_set, &self.params(), self.jobs,
+            self.cache_dir.as_deref(), self.verbose)?;
+
+        // drop fingerprints so common that they must be boilerplate, if limited
+        // (a percentage is of the current submissions only)
+        let suppressed = match self.max_frequency {
+            Some(max) => {
+                let num_current = subs.iter().filter(|sub| !sub.archived).count();
+                phase_i::suppress_frequent(subs, max.limit(num_current), self.verbose)
+            },
+            None => 0,
+        };
+
+        // map fingerprints to all submissions in which they appeared
+        let subs: &'a [Sub] = subs;
+        let hash_to_subs = phase_i::map_hashes(subs);
+
+        // hashes shared by submissions whose k-grams differ, if verifying
+        let collisions = if self.verify {
+            Some(phase_i::verify_hashes(&hash_to_subs, &self.params(), self.verbose)?)
+        } else {
+            None
+        };
+
+        // group submissions into pairs based on shared fingerprints, and
+        // order according to the number of fingerprints shared
+        let (pairs, total_pairs) = phase_ii::find_overlaps(&hash_to_subs, collisions.as_ref(),
+            self.lsh.as_ref(), self.match_threshold, self.verbose)?;
+
+        // group submissions connected by sufficiently similar pairs, if clustering
+        let clusters = match self.cluster_similarity {
+            Some(min_similarity) => cluster::find_clusters(&pairs, min_similarity)?,
+            None => Vec::new(),
+        };
+
+        // group submissions by fingerprints that several of them share, if grouping
+        let groups = match self.group_size {
+            Some(min_subs) => cluster::find_groups(&hash_to_subs, min_subs)?,
+            None => Vec::new(),
+        };
+
+        Ok(Overlaps { hash_to_subs, pairs, total_pairs, suppressed, clusters, groups })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Doc;
+    use crate::phase_iii;
+
+    #[test]
+    fn test_run_multi_file() {
+        let analysis = Analysis { k: 5, t: 15, ..Analysis::default() };
+        let mut subs = analysis.load_subs(Path::new("test-dirs/test/multi-file")).unwrap();
+        let overlaps = analysis.run(&mut subs).unwrap();
+
+        // sub1 & sub2 form the only pair, sharing 7 hashes
+        assert_eq!(overlaps.total_pairs, 1);
+        assert_eq!(overlaps.pairs.len(), 1);
+        assert_eq!(overlaps.pairs[0].matches.len(), 7);
+        assert_eq!(overlaps.hash_to_subs.len(), 15);
+
+        let matches = phase_iii::analyze_pair(&overlaps.pairs[0]).unwrap();
+        assert!(!matches.is_empty());
+    }
+
+    #[test]
+    fn test_run_ast() {
+        // structural fingerprints flow through phases II & III like any others
+        let analysis = Analysis { k: 5, t: 5, kgrams: KgramMode::Ast, ..Analysis::default() };
+        let mut subs = analysis.load_subs(Path::new("test-dirs/test/multi-file")).unwrap();
+        let overlaps = analysis.run(&mut subs).unwrap();
+
+        assert_eq!(overlaps.pairs.len(), 1);
+        assert!(!phase_iii::analyze_pair(&overlaps.pairs[0]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_run_verified() {
+        use crate::normalize::{NormOptions, IdentifierMode};
+
+        // the only shared hash is a collision between unrelated k-grams
+        let norm = NormOptions { identifiers: IdentifierMode::Preserved, ..NormOptions::default() };
+        let analysis = Analysis { sub_mode: SubFileMode::Single, k: 5, t: 5, norm, ..Analysis::default() };
+        let sub_dir = Path::new("test-dirs/test/collision");
+
+        let mut subs = analysis.load_subs(sub_dir).unwrap();
+        assert_eq!(analysis.run(&mut subs).unwrap().total_pairs, 1);
+
+        let verified = Analysis { verify: true, ..analysis.clone() };
+        let mut subs = verified.load_subs(sub_dir).unwrap();
+        assert_eq!(verified.run(&mut subs).unwrap().total_pairs, 0);
+
+        let wide = Analysis { hash_width: HashWidth::Bits64, ..analysis.clone() };
+        let mut subs = wide.load_subs(sub_dir).unwrap();
+        assert_eq!(wide.run(&mut subs).unwrap().total_pairs, 0);
+    }
+
+    #[test]
+    fn test_run_max_frequency() {
+        let analysis = Analysis { k: 5, t: 15, ..Analysis::default() };
+        let mut subs = analysis.load_subs(Path::new("test-dirs/test/multi-file")).unwrap();
+        assert_eq!(analysis.run(&mut subs).unwrap().suppressed, 0);
+
+        // the 7 hashes shared by sub1 & sub2 are all too common
+        let limited = Analysis { max_frequency: Some(MaxFrequency::Count(1)), ..analysis.clone() };
+        let mut subs = limited.load_subs(Path::new("test-dirs/test/multi-file")).unwrap();
+        let overlaps = limited.run(&mut subs).unwrap();
+
+        assert_eq!(overlaps.suppressed, 7);
+        assert_eq!(overlaps.hash_to_subs.len(), 8);
+        assert_eq!(overlaps.total_pairs, 0);
+    }
+
+    #[test]
+    fn test_run_max_frequency_percent() {
+        let analysis = Analysis { k: 5, t: 15, max_frequency: Some(MaxFrequency::Percent(50.0)),
+            ..Analysis::default() };
+        let mut subs = analysis.load_subs(Path::new("test-dirs/test/multi-file")).unwrap();
+
+        // archived submissions don't raise the limit (50% of 2 current subs is 1)
+        subs.extend((1..=2).map(|i| Sub {
+            dir_name: Some(PathBuf::from(format!("archive/sub{}", i))),
+            archived: true,
+            documents: vec![]
+        }));
+
+        let overlaps = analysis.run(&mut subs).unwrap();
+        assert_eq!(overlaps.suppressed, 7);
+        assert_eq!(overlaps.total_pairs, 0);
+
+        // suppressed hashes are gone from every document, not just the map
+        let subs: HashSet<&Sub> = overlaps.hash_to_subs.values().flatten().cloned().collect();
+        for sub in subs {
+            for doc in sub.documents.iter() {
+                if let Doc::Processed(_, fps) = doc {
+                    assert!(fps.iter().all(|fp| overlaps.hash_to_subs.contains_key(&fp.hash)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_clusters() {
+        let analysis = Analysis { sub_mode: SubFileMode::Single, k: 10, t: 60, ..Analysis::default() };
+        let sub_dir = Path::new("test-dirs/test/single-file-subpairs");
+
+        let mut subs = analysis.load_subs(sub_dir).unwrap();
+        assert!(analysis.run(&mut subs).unwrap().clusters.is_empty());
+
+        // sub1 is linked to sub2 (100% of sub2) & sub4 (40% of sub4)
+        let clustering = Analysis { cluster_similarity: Some(0.4), ..analysis.clone() };
+        let mut subs = clustering.load_subs(sub_dir).unwrap();
+        let overlaps = clustering.run(&mut subs).unwrap();
+
+        assert_eq!(overlaps.clusters.len(), 1);
+        let cluster = &overlaps.clusters[0];
+        assert_eq!(cluster.members.len(), 3);
+        assert_eq!(cluster.pairs.len(), 3);
+
+        // only one fingerprint appears in all three
+        assert_eq!(cluster.fragments.len(), 1);
+        assert_eq!(cluster.fragments[0].size, 1);
+        assert!(cluster.fragments[0].entries.iter().all(|entries| entries.len() == 1));
+    }
+
+    #[test]
+    fn test_run_groups() {
+        let analysis = Analysis { sub_mode: SubFileMode::Single, k: 10, t: 60, ..Analysis::default() };
+        let sub_dir = Path::new("test-dirs/test/single-file-subpairs");
+
+        let mut subs = analysis.load_subs(sub_dir).unwrap();
+        assert!(analysis.run(&mut subs).unwrap().groups.is_empty());
+
+        // the fingerprint in all of sub1, sub2 & sub4 is shared by no other submission
+        let grouping = Analysis { group_size: Some(3), ..analysis.clone() };
+        let mut subs = grouping.load_subs(sub_dir).unwrap();
+        let overlaps = grouping.run(&mut subs).unwrap();
+
+        assert_eq!(overlaps.groups.len(), 1);
+        let group = &overlaps.groups[0];
+        assert_eq!(group.members.len(), 3);
+        assert_eq!(group.fragments.len(), 1);
+        assert_eq!(group.fragments[0].size, 1);
+        assert!(group.fragments[0].entries.iter().all(|entries| entries.len() == 1));
+    }
+
+    #[test]
+    fn test_suggest_boilerplate() {
+        let out = std::env::temp_dir()
+            .join(format!("pyret-moss-suggest-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&out);
+        std::fs::create_dir_all(&out).unwrap();
+        let (snippets_dir, hashes_file) = (out.join("snippets"), out.join("hashes.json"));
+
+        // both submissions share 7 hashes, which 50% of 2 submissions can't
+        let analysis = Analysis { k: 5, t: 15, ..Analysis::default() };
+        let sub_dir = Path::new("test-dirs/test/multi-file");
+        let (hashes, snippets) = analysis.suggest_boilerplate(sub_dir, Some(&snippets_dir),
+            Some(&hashes_file)).unwrap();
+        assert_eq!(hashes, 7);
+        assert_eq!(snippets, 3);
+
+        // snippets are the lines of the first submission that the hashes came from
+        let lines = |path: &str, (start, end): (usize, usize)| -> String {
+            let text = std::fs::read_to_string(sub_dir.join(path)).unwrap();
+            text.lines().skip(start - 1).take(end - start + 1).collect::<Vec<_>>().join("\n") + "\n"
+        };
+        let snippet = |i: usize| std::fs::read_to_string(snippets_dir.join(format!("boilerplate-{:03}.arr", i))).unwrap();
+
+        assert_eq!(snippet(1), lines("sub1/common.arr", (7, 7)));
+        assert_eq!(snippet(1), "\t| my-link(first :: T, rest :: List<T>)\n");
+        assert_eq!(snippet(2), lines("sub1/main.arr", (1, 5)));
+        assert_eq!(snippet(3), lines("sub1/main.arr", (7, 9)));
+        assert_eq!(snippet(3), "\ttesting(5) is 25\n\ttesting(7) is 49\n\ttesting(-1) is 1\n");
+
+        // archived submissions neither count toward the limit nor supply snippets
+        let archiving = Analysis { archive_dir: Some(PathBuf::from("test-dirs/test/multi-file-subpairs")),
+            ..analysis.clone() };
+        let archived_snippets = out.join("archived-snippets");
+        assert_eq!(archiving.suggest_boilerplate(sub_dir, Some(&archived_snippets), None).unwrap(), (7, 3));
+        assert_eq!(std::fs::read_to_string(archived_snippets.join("boilerplate-002.arr")).unwrap(), snippet(2));
+
+        // ignoring the suggested hashes leaves no overlap
+        let ignoring = Analysis { ignore_content_dir: Some(hashes_file.clone()), ..analysis.clone() };
+        let mut subs = ignoring.load_subs(sub_dir).unwrap();
+        assert_eq!(ignoring.run(&mut subs).unwrap().total_pairs, 0);
+
+        // ignoring the snippets they came from removes most of it (fingerprints
+        // near a snippet's edges may be winnowed differently on their own)
+        let ignoring = Analysis { ignore_content_dir: Some(snippets_dir.clone()), ..analysis.clone() };
+        let mut subs = ignoring.load_subs(sub_dir).unwrap();
+        let overlaps = ignoring.run(&mut subs).unwrap();
+        assert!(overlaps.pairs.iter().all(|p| p.matches.len() < hashes));
+
+        std::fs::remove_dir_all(&out).unwrap();
+    }
+
+    #[test]
+    fn test_index_against_db() {
+        let db_file = std::env::temp_dir()
+            .join(format!("pyret-moss-analysis-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&db_file);
+
+        let analysis = Analysis { sub_mode: SubFileMode::Single, k: 10, t: 60, ..Analysis::default() };
+        let prior_dir = Path::new("test-dirs/test/single-file");
+
+        // only new submissions are stored
+        assert_eq!(analysis.index(prior_dir, &db_file).unwrap(), (2, 0));
+        assert_eq!(analysis.index(prior_dir, &db_file).unwrap(), (0, 2));
+
+        let against = Analysis { db_file: Some(db_file.clone()), ..analysis.clone() };
+
+        // stored submissions are compared against current ones, but not each other
+        let mut subs = against.load_subs(Path::new("test-dirs/test/single-file-subpairs")).unwrap();
+        assert_eq!(subs.iter().filter(|sub| sub.archived).count(), 2);
+
+        let overlaps = against.run(&mut subs).unwrap();
+        assert!(overlaps.pairs.iter().any(|sp| sp.a.archived || sp.b.archived));
+        assert!(overlaps.pairs.iter().all(|sp| !(sp.a.archived && sp.b.archived)));
+
+        // stored copies of current submissions are left out
+        let mut subs = against.load_subs(prior_dir).unwrap();
+        assert!(subs.iter().all(|sub| !sub.archived));
+
+        let overlaps = against.run(&mut subs).unwrap();
+        assert_eq!(overlaps.total_pairs, 1);
+
+        // stored fingerprints must be comparable
+        let mismatched = Analysis { k: 11, ..against.clone() };
+        assert!(matches!(mismatched.load_subs(prior_dir),
+            Err(crate::MossError::Database(_, _))));
+
+        std::fs::remove_file(&db_file).unwrap();
+    }
+}
@@ -0,0 +1,528 @@
+//! ast.rs: Structural fingerprinting of Pyret programs. Programs are parsed into a
+//! (coarse) syntax tree, which is canonicalized so that reordering definitions,
+//! negating an if's condition & swapping its branches, or writing `lam` for `fun`
+//! doesn't change it. Hashes of its subtrees then serve as the program's fingerprints.
+
+use std::hash::Hasher;
+use fnv::FnvHasher;
+use crate::error::{MossError, Result};
+use crate::fingerprint::{Fingerprint, HashWidth};
+use crate::normalize::{self, NormOptions, SCOPE_OPENERS};
+use crate::tokenize::{tokenize, TokenKind};
+
+// keywords (and symbols) separating the sections of a construct (i.e. the branches of an if)
+const SECTION_SEPARATORS: [&str; 8] = [
+    "else if", "else:", "where:", "then:", "otherwise:", "sharing:", "with:", "|"
+];
+
+// constructs whose `|` sections (variants, branches) may be freely reordered
+const UNORDERED_SECTIONS: [&str; 2] = ["data", "cases"];
+
+/// A Leaf is a token of the program that survived normalization
+#[derive(Debug)]
+struct Leaf<'a> {
+    kind: TokenKind,
+    raw: &'a str,       // original text of the token
+    text: String,       // normalized text of the token
+    start: (i32, i32),  // line & column of the token's first char in the original program
+    end: (i32, i32)     // line & column of the token's last char
+}
+
+/// A Node is a subtree of the canonicalized syntax tree
+#[derive(Debug)]
+struct Node {
+    label: String,          // what kind of subtree this is (leaf, function, if, etc.)
+    hash: u64,              // hash of the subtree's (canonical) structure
+    size: usize,            // number of leaves in the subtree
+    start: (i32, i32),      // line & column at which the subtree begins in the original program
+    end: (i32, i32),        // line & column at which the subtree ends (inclusive)
+    leaf: Option<usize>,    // index of the leaf, if this node is one
+    children: Vec<Node>
+}
+
+impl Node {
+    /// A node for a single leaf
+    fn leaf(leaves: &[Leaf], i: usize) -> Self {
+        let mut hasher = FnvHasher::default();
+        hasher.write(b"leaf:");
+        hasher.write(leaves[i].text.as_bytes());
+
+        Node {
+            label: String::from("leaf"),
+            hash: hasher.finish(),
+            size: 1,
+            start: leaves[i].start,
+            end: leaves[i].end,
+            leaf: Some(i),
+            children: Vec::new()
+        }
+    }
+
+    /// A node with the given label & children. Its hash depends on
+    /// the label & the order of the children, but not their positions
+    fn new(label: &str, children: Vec<Node>) -> Self {
+        let mut hasher = FnvHasher::default();
+        hasher.write(label.as_bytes());
+        for child in children.iter() { hasher.write_u64(child.hash); }
+
+        // empty subtrees don't span any of the program
+        let spanning = || children.iter().filter(|c| c.size > 0);
+
+        Node {
+            label: String::from(label),
+            hash: hasher.finish(),
+            size: children.iter().map(|c| c.size).sum(),
+            start: spanning().map(|c| c.start).min().unwrap_or((0, 0)),
+            end: spanning().map(|c| c.end).max().unwrap_or((0, 0)),
+            leaf: None,
+            children
+        }
+    }
+
+    /// A node with the given label & children, or the only child if there's just one
+    fn wrap(label: &str, mut children: Vec<Node>) -> Self {
+        if children.len() == 1 {
+            children.pop().unwrap()
+        } else {
+            Node::new(label, children)
+        }
+    }
+
+    /// The fingerprint of this subtree, with a hash of the given width
+    fn fingerprint(&self, width: HashWidth) -> Fingerprint {
+        Fingerprint {
+            hash: (self.hash % width.modulus() as u64) as i64,
+            lines: (self.start.0, self.end.0),
+            cols: (self.start.1, self.end.1)
+        }
+    }
+
+    /// A rendering of this subtree's canonical structure (which its hash summarizes),
+    /// so that subtrees' structures can be compared exactly
+    fn canonical_text(&self, leaves: &[Leaf]) -> String {
+        match self.as_leaf(leaves) {
+            Some(leaf) => leaf.text.to_owned(),
+            None => {
+                let children: Vec<String> = self.children.iter()
+                    .map(|c| c.canonical_text(leaves))
+                    .collect();
+                format!("{}({})", self.label, children.join("\u{0}"))
+            },
+        }
+    }
+
+    /// The leaf this node consists of, if it is one
+    fn as_leaf<'l, 'a>(&self, leaves: &'l [Leaf<'a>]) -> Option<&'l Leaf<'a>> {
+        self.leaf.map(|i| &leaves[i])
+    }
+}
+
+/// computes the Fingerprints of a program by hashing the subtrees of its
+/// canonicalized syntax tree, after normalizing its tokens according to opts.
+/// Every subtree of at least k tokens is fingerprinted (in canonical order),
+/// so shared subtrees of at least k tokens are guaranteed to be caught. t
+/// has no further effect, but may not be less than k.
+///
+/// Since fingerprints are in canonical rather than source order, consecutive
+/// fingerprints needn't be adjacent in the program: a run of them found by
+/// phase III may span (& so report) code between reordered definitions
+pub fn fingerprint(program: &str, opts: &NormOptions, k: i32, t: i32, width: HashWidth)
+    -> Result<Vec<Fingerprint>> {
+    let (_, root) = parse(program, opts, k, t)?;
+
+    let mut subtrees = Vec::new();
+    collect_subtrees(&root, k.max(1) as usize, &mut subtrees);
+
+    Ok(subtrees.iter().map(|node| node.fingerprint(width)).collect())
+}
+
+/// computes the hash of each fingerprint of a program (as fingerprint would), paired
+/// with a rendering of the canonical structure of its subtree. Two subtrees' texts are
+/// equal exactly when their structures are, so the texts can be compared to tell
+/// whether a shared hash is a real match or a collision
+pub fn subtree_texts(program: &str, opts: &NormOptions, k: i32, t: i32, width: HashWidth)
+    -> Result<Vec<(i64, String)>> {
+    let (leaves, root) = parse(program, opts, k, t)?;
+
+    let mut subtrees = Vec::new();
+    collect_subtrees(&root, k.max(1) as usize, &mut subtrees);
+
+    Ok(subtrees.iter()
+        .map(|node| (node.fingerprint(width).hash, node.canonical_text(&leaves)))
+        .collect())
+}
+
+/// Parse a program into its leaves & canonicalized syntax tree
+fn parse<'a>(program: &'a str, opts: &NormOptions, k: i32, t: i32) -> Result<(Vec<Leaf<'a>>, Node)> {
+    if t < k {
+        return Err(MossError::InvalidParams(String::from("`t` may not be less than `k`.")));
+    }
+
+    let leaves = make_leaves(program, opts);
+    let root = parse_program(&leaves);
+
+    Ok((leaves, root))
+}
+
+/// Tokenize & normalize a program, keeping the tokens that remain
+/// (other than whitespace, which never affects structure)
+fn make_leaves<'a>(program: &'a str, opts: &NormOptions) -> Vec<Leaf<'a>> {
+    let tokens = tokenize(program);
+    let normalized = normalize::normalize_tokens(&tokens, opts);
+    let positions = normalize::source_positions(program);
+
+    tokens.iter().zip(normalized)
+        .filter_map(|(token, text)| {
+            let text = text.filter(|t| !t.is_empty())?;
+            if token.kind == TokenKind::Whitespace { return None; }
+
+            let last = token.text.char_indices().last().map_or(0, |(i, _)| i);
+            let (first, last) = (positions[token.span.start], positions[token.span.start + last]);
+
+            Some(Leaf {
+                kind: token.kind,
+                raw: token.text,
+                text,
+                start: (first.line, first.col),
+                end: (last.line, last.col)
+            })
+        })
+        .collect()
+}
+
+/// Collect the given subtree & all of its subtrees with at least
+/// min_size leaves (those to be fingerprinted), in (canonical) pre-order
+fn collect_subtrees<'n>(node: &'n Node, min_size: usize, out: &mut Vec<&'n Node>) {
+    if node.leaf.is_some() || node.size < min_size { return; }
+
+    out.push(node);
+
+    for child in node.children.iter() {
+        collect_subtrees(child, min_size, out);
+    }
+}
+
+/// Parse all leaves into a tree whose top-level statements are in canonical order
+fn parse_program(leaves: &[Leaf]) -> Node {
+    let mut pos = 0;
+
+    // (stray closing brackets & ends become leaves)
+    let items = parse_items(leaves, &mut pos, &|_| false);
+
+    // reordering definitions shouldn't matter
+    let mut statements = split_statements(leaves, items);
+    statements.sort_by_key(|s| s.hash);
+
+    Node::wrap("program", statements)
+}
+
+/// Parse nodes until reaching a leaf at which to stop (which isn't consumed)
+fn parse_items(leaves: &[Leaf], pos: &mut usize, stop: &dyn Fn(&Leaf) -> bool) -> Vec<Node> {
+    let mut items = Vec::new();
+
+    while *pos < leaves.len() && !stop(&leaves[*pos]) {
+        items.push(parse_one(leaves, pos));
+    }
+
+    items
+}
+
+/// Parse a single node: a bracketed group, a construct ending in `end`, or a leaf
+fn parse_one(leaves: &[Leaf], pos: &mut usize) -> Node {
+    let i = *pos;
+    let leaf = &leaves[i];
+    *pos += 1;
+
+    // ------- Brackets -------
+    if let Some(closer) = closing_bracket(leaf) {
+        let children = parse_items(leaves, pos, &|l| is_symbol(l, closer) || is_keyword(l, "end"));
+        if leaves.get(*pos).is_some_and(|l| is_symbol(l, closer)) { *pos += 1; }
+
+        return spanning(Node::new(leaf.raw, children), leaves, i, *pos);
+    }
+
+    // ------- Constructs -------
+    if leaf.kind == TokenKind::Keyword && SCOPE_OPENERS.contains(&leaf.text.as_str()) {
+        let mut sections = Vec::new();
+        let mut label = leaf.text.as_str();   // separator that began the current section
+
+        loop {
+            let items = parse_items(leaves, pos, &|l| is_keyword(l, "end") || is_separator(l));
+            sections.push((label, items));
+
+            match leaves.get(*pos) {
+                // a new section begins
+                Some(l) if is_separator(l) => {
+                    label = l.text.as_str();
+                    *pos += 1;
+                },
+                // construct is complete
+                Some(_) => { *pos += 1; break; },
+                // unterminated construct
+                None => break,
+            }
+        }
+
+        return spanning(canonical_construct(leaves, leaf.text.as_str(), sections), leaves, i, *pos);
+    }
+
+    // ------- Leaves -------
+    Node::leaf(leaves, i)
+}
+
+/// Make a node (for a bracketed group or construct) account for all of the
+/// leaves it was parsed from, leaves[start..end], including brackets, keywords, etc.
+fn spanning(mut node: Node, leaves: &[Leaf], start: usize, end: usize) -> Node {
+    node.size = end - start;
+    node.start = leaves[start].start;
+    node.end = leaves[end - 1].end;
+    node
+}
+
+/// Build the canonical node for a construct, given its sections (each labeled
+/// with the keyword/separator it began with):
+///     `fun f(x): ... end` becomes the same as `f = lam(x): ... end` (see make_statement)
+///     `if not(c): a else: b end` becomes the same as `if c: b else: a end`
+///     the variants of data & cases are sorted
+fn canonical_construct(leaves: &[Leaf], keyword: &str, sections: Vec<(&str, Vec<Node>)>) -> Node {
+    let mut sections: Vec<(&str, Vec<Node>)> = sections.into_iter()
+        .filter(|(_, items)| !items.is_empty())
+        .collect();
+
+    match keyword {
+        // ------- Functions -------
+        // a named function binds its name to a lambda
+        "fun" if sections.first().is_some_and(|(_, items)| is_name(leaves, &items[0])) => {
+            let name = sections[0].1.remove(0);
+            Node::new("bind", vec![name, make_function(leaves, sections)])
+        },
+        "fun" | "lam" => make_function(leaves, sections),
+
+        // ------- Ifs -------
+        "if" if sections.len() == 2 && sections[1].0 == "else:" => {
+            let other = sections.pop().unwrap().1;
+            let mut cond = sections.pop().unwrap().1;
+
+            // the condition ends at the first `:`
+            let body = match cond.iter().position(|n| n.as_leaf(leaves).is_some_and(|l| is_symbol(l, ":"))) {
+                Some(colon) => cond.split_off(colon).split_off(1),
+                None => Vec::new(),
+            };
+
+            let mut branches = vec![
+                Node::wrap("body", split_statements(leaves, body)),
+                Node::wrap("body", split_statements(leaves, other))];
+
+            // `not(c)` is c with the branches swapped
+            if cond.len() == 2 && is_name_raw(leaves, &cond[0], "not") && cond[1].label == "(" {
+                cond = cond.pop().unwrap().children;
+                branches.swap(0, 1);
+            }
+
+            Node::new("if", vec![Node::wrap("cond", cond), Node::new("branches", branches)])
+        },
+
+        // ------- Everything else -------
+        _ => {
+            let mut nodes: Vec<Node> = sections.into_iter()
+                .map(|(label, items)| Node::wrap(label, split_statements(leaves, items)))
+                .collect();
+
+            // variants/branches can be reordered (but not what precedes them)
+            if UNORDERED_SECTIONS.contains(&keyword) && nodes.len() > 1 {
+                nodes[1..].sort_by_key(|n| n.hash);
+            }
+
+            Node::new(keyword, nodes)
+        },
+    }
+}
+
+/// A function (from either fun or lam) with the given sections
+fn make_function(leaves: &[Leaf], sections: Vec<(&str, Vec<Node>)>) -> Node {
+    let nodes = sections.into_iter()
+        .map(|(label, items)| {
+            let label = if label == "lam" { "fun" } else { label };
+            Node::wrap(label, split_statements(leaves, items))
+        })
+        .collect();
+
+    Node::new("function", nodes)
+}
+
+/// Group a sequence of nodes into statements. A statement only continues
+/// onto a later line if an operator ends the previous line or begins the next
+fn split_statements(leaves: &[Leaf], items: Vec<Node>) -> Vec<Node> {
+    let mut statements = Vec::new();
+    let mut current: Vec<Node> = Vec::new();
+
+    for item in items {
+        let continues = current.last().is_none_or(|prev| item.start.0 <= prev.end.0
+            || is_operator(leaves, prev) || is_operator(leaves, &item));
+
+        if !continues {
+            statements.push(make_statement(leaves, std::mem::take(&mut current)));
+        }
+        current.push(item);
+    }
+
+    if !current.is_empty() {
+        statements.push(make_statement(leaves, current));
+    }
+
+    statements
+}
+
+/// A statement made of the given nodes. `f = lam(x): ... end`
+/// binds f to a lambda, the same way `fun f(x): ... end` does
+fn make_statement(leaves: &[Leaf], mut items: Vec<Node>) -> Node {
+    if items.len() == 3 && is_name(leaves, &items[0]) && items[2].label == "function"
+        && items[1].as_leaf(leaves).is_some_and(|l| is_symbol(l, "=")) {
+        let function = items.pop().unwrap();
+        items.pop();
+
+        return Node::new("bind", vec![items.pop().unwrap(), function]);
+    }
+
+    Node::wrap("statement", items)
+}
+
+/// The closing bracket matching a leaf, if it's an opening bracket
+fn closing_bracket(leaf: &Leaf) -> Option<&'static str> {
+    match leaf.raw {
+        "(" if leaf.kind == TokenKind::Symbol => Some(")"),
+        "[" if leaf.kind == TokenKind::Symbol => Some("]"),
+        "{" if leaf.kind == TokenKind::Symbol => Some("}"),
+        _ => None,
+    }
+}
+
+fn is_symbol(leaf: &Leaf, sym: &str) -> bool {
+    leaf.kind == TokenKind::Symbol && leaf.raw == sym
+}
+
+fn is_keyword(leaf: &Leaf, keyword: &str) -> bool {
+    leaf.kind == TokenKind::Keyword && leaf.text == keyword
+}
+
+fn is_separator(leaf: &Leaf) -> bool {
+    (leaf.kind == TokenKind::Keyword || leaf.kind == TokenKind::Symbol)
+        && SECTION_SEPARATORS.contains(&leaf.text.as_str())
+}
+
+fn is_operator(leaves: &[Leaf], node: &Node) -> bool {
+    node.as_leaf(leaves).is_some_and(|l| l.kind == TokenKind::Symbol)
+}
+
+fn is_name(leaves: &[Leaf], node: &Node) -> bool {
+    node.as_leaf(leaves).is_some_and(|l| l.kind == TokenKind::Name)
+}
+
+// whether a node is an identifier originally written as the given name
+fn is_name_raw(leaves: &[Leaf], node: &Node, name: &str) -> bool {
+    is_name(leaves, node) && node.as_leaf(leaves).is_some_and(|l| l.raw == name)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // fingerprint a program with default normalization, with all subtrees of at least k tokens
+    fn fps(program: &str, k: i32) -> Vec<Fingerprint> {
+        fingerprint(program, &NormOptions::default(), k, k, HashWidth::Bits31).unwrap()
+    }
+
+    fn hashes(program: &str, k: i32) -> Vec<i64> {
+        fps(program, k).iter().map(|fp| fp.hash).collect()
+    }
+
+    #[test]
+    fn reordered_definitions() {
+        let a = "fun f(x):\n  x + 1\nend\n\nfun g(y):\n  y * 2\nend";
+        let b = "fun g(y):\n  y * 2\nend\n\nfun f(x):\n  x + 1\nend";
+
+        assert_eq!(hashes(a, 3), hashes(b, 3));
+
+        // fingerprints still point to where each definition actually is
+        let line_ranges = |p| fps(p, 3).iter().map(|fp| fp.lines).collect::<Vec<_>>();
+        assert_eq!(line_ranges(a)[0], (1, 7));
+        assert_ne!(line_ranges(a)[1..], line_ranges(b)[1..]);
+
+        // fingerprints are in canonical order, so in one of the programs
+        // consecutive fingerprints jump back to an earlier definition
+        let in_source_order = |p| fps(p, 3).windows(2).all(|w| w[0].lines.0 <= w[1].lines.0);
+        assert_ne!(in_source_order(a), in_source_order(b));
+    }
+
+    #[test]
+    fn lam_same_as_fun() {
+        assert_eq!(
+            hashes("fun add(a, b): a + b end", 2),
+            hashes("add = lam(a, b): a + b end", 2));
+        assert_ne!(
+            hashes("fun add(a, b): a + b end", 2),
+            hashes("add = lam(a, b): a - b end", 2));
+    }
+
+    #[test]
+    fn swapped_if_branches() {
+        let a = "fun f(x):\n  if x > 5:\n    x * 2\n  else:\n    x + 1\n  end\nend";
+        let b = "fun f(x):\n  if not(x > 5):\n    x + 1\n  else:\n    x * 2\n  end\nend";
+
+        assert_eq!(hashes(a, 2), hashes(b, 2));
+
+        // without negating the condition, swapping the branches changes the program
+        let c = "fun f(x):\n  if x > 5:\n    x + 1\n  else:\n    x * 2\n  end\nend";
+        assert_ne!(hashes(a, 2), hashes(c, 2));
+        assert_ne!(hashes(b, 2), hashes(c, 2));
+    }
+
+    #[test]
+    fn spaced_out_keywords() {
+        assert_eq!(
+            hashes("if x: 1 else if y: 2 else: 3 end", 2),
+            hashes("if x: 1 else  if y: 2 else: 3 end", 2));
+    }
+
+    #[test]
+    fn reordered_variants() {
+        assert_eq!(
+            hashes("data Shape:\n  | circle(r)\n  | square(s)\nend", 2),
+            hashes("data Shape:\n  | square(s)\n  | circle(r)\nend", 2));
+    }
+
+    #[test]
+    fn subtree_sizes() {
+        // every subtree of at least k tokens, & nothing smaller
+        // (brackets count as tokens of the subtree they enclose)
+        let program = "x = (1 + 2) * 3";
+        assert_eq!(fps(program, 1).len(), 2);
+        assert_eq!(fps(program, 5).len(), 2);
+        assert_eq!(fps(program, 6).len(), 1);
+        assert_eq!(fps(program, 9).len(), 1);
+        assert_eq!(fps(program, 10).len(), 0);
+
+        assert!(fps("", 1).is_empty());
+        assert!(matches!(fingerprint(program, &NormOptions::default(), 5, 4, HashWidth::Bits31),
+            Err(MossError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn unbalanced_programs() {
+        // parsing never fails, even on malformed programs
+        assert!(!fps("fun f(x: x + (1 end end )", 1).is_empty());
+        assert!(!fps("if x: y else: z", 1).is_empty());
+    }
+
+    #[test]
+    fn canonical_texts() {
+        let texts = |p| subtree_texts(p, &NormOptions::default(), 2, 2, HashWidth::Bits31).unwrap();
+
+        // equal structures render equally, & texts line up with fingerprints
+        let a = texts("fun add(a, b): a + b end");
+        assert_eq!(a, texts("add = lam(a, b): a + b end"));
+        assert_ne!(a, texts("fun add(a, b): a - b end"));
+        assert_eq!(a.iter().map(|(h, _)| *h).collect::<Vec<i64>>(), hashes("fun add(a, b): a + b end", 2));
+    }
+}
@@ -0,0 +1,257 @@
+//! boilerplate.rs: Suggesting content to ignore (e.g. starter code) from the
+//! fingerprints shared most widely across submissions, so that later runs can
+//! ignore it through --ignore-content without clean starter code on hand
+
+use fnv::FnvHashMap;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use crate::{Sub, Doc};
+use crate::error::{MossError, Result};
+use crate::fingerprint;
+use crate::normalize;
+use crate::phase_i::FpParams;
+
+/// IgnoreHashes is a serialized set of fingerprint hashes to ignore, along
+/// with the params they were made with (hashes are meaningless otherwise)
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct IgnoreHashes {
+    normalizer_version: u32,    // normalize::VERSION when fingerprints were made
+    fingerprint_version: u32,   // fingerprint::VERSION when fingerprints were made
+    params: FpParams,           // params the hashes were made with
+    hashes: Vec<i64>            // hashes to ignore, most widely shared first
+}
+
+/// Snippet identifies a range of lines (inclusive) of a document that
+/// contains some of the suggested boilerplate fingerprints
+#[derive(Debug, PartialEq, Clone)]
+pub struct Snippet {
+    pub path: PathBuf,
+    pub lines: (i32, i32),
+    pub hashes: usize           // number of suggested hashes within these lines
+}
+
+/// Find the hashes shared by more than max_subs submissions, ordered
+/// from most to least widely shared (ties broken by hash value)
+pub fn common_hashes(hash_to_subs: &FnvHashMap<i64, HashSet<&Sub>>, max_subs: usize) -> Vec<i64> {
+    let mut common: Vec<(i64, usize)> = hash_to_subs.iter()
+        .filter(|(_, subs)| subs.len() > max_subs)
+        .map(|(&hash, subs)| (hash, subs.len()))
+        .collect();
+
+    common.sort_by(|(h1, n1), (h2, n2)| n2.cmp(n1).then(h1.cmp(h2)));
+    common.into_iter().map(|(hash, _)| hash).collect()
+}
+
+/// Map the given hashes back to the source lines they came from. Submissions are
+/// visited in order, & each hash is only attributed to the first document found to
+/// contain it, so snippets concentrate in as few documents as possible. Overlapping
+/// or adjacent line ranges within a document are merged into one snippet.
+pub fn snippets(subs: &[Sub], hashes: &[i64]) -> Result<Vec<Snippet>> {
+    let mut remaining: HashSet<i64> = hashes.iter().cloned().collect();
+    let mut snippets = Vec::new();
+
+    for sub in subs.iter() {
+        for doc in sub.documents.iter() {
+            if remaining.is_empty() { return Ok(snippets); }
+
+            let (path, fps) = match doc {
+                Doc::Processed(path, fps) => (path, fps),
+                Doc::Unprocessed(path) => return Err(MossError::UnprocessedDoc(path.clone())),
+            };
+
+            // line ranges of this document's fingerprints that haven't been attributed yet
+            let mut ranges: Vec<(i32, i32)> = fps.iter()
+                .filter(|fp| remaining.remove(&fp.hash))
+                .map(|fp| fp.lines)
+                .collect();
+            ranges.sort_unstable();
+
+            let mut doc_snippets: Vec<Snippet> = Vec::new();
+            for (start, end) in ranges.into_iter() {
+                match doc_snippets.last_mut() {
+                    Some(last) if start <= last.lines.1 + 1 => {
+                        last.lines.1 = last.lines.1.max(end);
+                        last.hashes += 1;
+                    },
+                    _ => doc_snippets.push(Snippet { path: path.clone(), lines: (start, end), hashes: 1 }),
+                }
+            }
+
+            snippets.append(&mut doc_snippets);
+        }
+    }
+
+    Ok(snippets)
+}
+
+/// Write each snippet's lines to its own .arr file in the given directory
+/// (creating it if necessary), which can then be passed to --ignore-content
+pub fn write_snippets(dir: &Path, snippets: &[Snippet]) -> Result<()> {
+    let write_err = |e| MossError::Io(format!("failed to write boilerplate snippets to {}",
+        dir.display()), e);
+
+    fs::create_dir_all(dir).map_err(write_err)?;
+
+    // read each document only once, no matter how many snippets it contains
+    let mut contents: BTreeMap<&Path, String> = BTreeMap::new();
+    for snippet in snippets.iter() {
+        if !contents.contains_key(snippet.path.as_path()) {
+            let text = fs::read_to_string(&snippet.path).map_err(|e| {
+                MossError::Io(format!("failed to read {}", snippet.path.display()), e)
+            })?;
+            contents.insert(&snippet.path, text);
+        }
+    }
+
+    for (i, snippet) in snippets.iter().enumerate() {
+        let (start, end) = snippet.lines;
+        let lines: Vec<&str> = contents[snippet.path.as_path()].lines()
+            .skip((start - 1) as usize)
+            .take((end - start + 1) as usize)
+            .collect();
+
+        let file = dir.join(format!("boilerplate-{:03}.arr", i + 1));
+        fs::write(&file, lines.join("\n") + "\n").map_err(write_err)?;
+    }
+
+    Ok(())
+}
+
+/// Write the given hashes to a file, which can then be passed to --ignore-content
+/// in later runs that fingerprint with the same params
+pub fn write_hashes(path: &Path, params: &FpParams, hashes: &[i64]) -> Result<()> {
+    let ignore = IgnoreHashes {
+        normalizer_version: normalize::VERSION,
+        fingerprint_version: fingerprint::VERSION,
+        params: *params,
+        hashes: hashes.to_vec()
+    };
+
+    let write_err = |e| MossError::Io(format!("failed to write ignore hashes to {}", path.display()), e);
+
+    let file = File::create(path).map_err(write_err)?;
+    serde_json::to_writer(BufWriter::new(file), &ignore).map_err(|e| {
+        MossError::json(format!("failed to write ignore hashes to {}", path.display()), e)
+    })
+}
+
+/// Read a set of hashes to ignore from a file written by write_hashes,
+/// ensuring they were made the same way as fingerprints with the given params
+pub fn load_hashes(path: &Path, params: &FpParams) -> Result<HashSet<i64>> {
+    let file = File::open(path).map_err(|e| {
+        MossError::Io(format!("failed to open ignore hashes {}", path.display()), e)
+    })?;
+
+    let ignore: IgnoreHashes = serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+        MossError::json(format!("failed to read ignore hashes {}", path.display()), e)
+    })?;
+
+    if ignore.normalizer_version != normalize::VERSION || ignore.fingerprint_version != fingerprint::VERSION
+        || ignore.params != *params {
+        return Err(MossError::InvalidParams(format!(
+            "ignore hashes in `{}` were fingerprinted differently than this analysis (regenerate them \
+            with suggest-boilerplate using the same options)", path.display())));
+    }
+
+    Ok(ignore.hashes.into_iter().collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fingerprint::Fingerprint;
+
+    fn fp(hash: i64, lines: (i32, i32)) -> Fingerprint {
+        Fingerprint { hash, lines, cols: (1, 1) }
+    }
+
+    fn sub(name: &str, fps: Vec<Fingerprint>) -> Sub {
+        Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![Doc::Processed(PathBuf::from(name), fps)]
+        }
+    }
+
+    #[test]
+    fn test_common_hashes() {
+        let subs = [
+            sub("a.arr", vec![fp(1, (1, 1)), fp(2, (2, 2)), fp(3, (3, 3))]),
+            sub("b.arr", vec![fp(1, (1, 1)), fp(2, (2, 2))]),
+            sub("c.arr", vec![fp(1, (1, 1)), fp(4, (2, 2))])
+        ];
+
+        let mut hash_to_subs: FnvHashMap<i64, HashSet<&Sub>> = FnvHashMap::default();
+        for s in subs.iter() {
+            for fp in s.documents.iter().flat_map(|d| match d {
+                Doc::Processed(_, fps) => fps.clone(),
+                Doc::Unprocessed(_) => vec![],
+            }) {
+                hash_to_subs.entry(fp.hash).or_default().insert(s);
+            }
+        }
+
+        assert_eq!(common_hashes(&hash_to_subs, 1), vec![1, 2]);
+        assert_eq!(common_hashes(&hash_to_subs, 2), vec![1]);
+        assert!(common_hashes(&hash_to_subs, 3).is_empty());
+    }
+
+    #[test]
+    fn test_snippets() {
+        let subs = vec![
+            sub("a.arr", vec![fp(1, (1, 2)), fp(2, (2, 4)), fp(3, (5, 5)), fp(4, (9, 10))]),
+            sub("b.arr", vec![fp(1, (3, 3)), fp(5, (7, 8))])
+        ];
+
+        // overlapping & adjacent ranges merge, & hashes are attributed to their first document
+        let exp = vec![
+            Snippet { path: PathBuf::from("a.arr"), lines: (1, 5), hashes: 3 },
+            Snippet { path: PathBuf::from("b.arr"), lines: (7, 8), hashes: 1 }
+        ];
+        assert_eq!(snippets(&subs, &[1, 2, 3, 5]).unwrap(), exp);
+
+        let unprocessed = vec![Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![Doc::Unprocessed(PathBuf::from("a.arr"))]
+        }];
+        assert!(matches!(snippets(&unprocessed, &[1]), Err(MossError::UnprocessedDoc(_))));
+    }
+
+    #[test]
+    fn test_write_snippets() {
+        let dir = std::env::temp_dir()
+            .join(format!("pyret-moss-boilerplate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let doc = PathBuf::from("test-dirs/test/single-file/sub1.arr");
+        let snippets = vec![Snippet { path: doc.clone(), lines: (2, 3), hashes: 1 }];
+        write_snippets(&dir, &snippets).unwrap();
+
+        let text = fs::read_to_string(&doc).unwrap();
+        let exp: Vec<&str> = text.lines().skip(1).take(2).collect();
+        let written = fs::read_to_string(dir.join("boilerplate-001.arr")).unwrap();
+        assert_eq!(written, exp.join("\n") + "\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hashes_round_trip() {
+        let path = std::env::temp_dir()
+            .join(format!("pyret-moss-ignore-hashes-{}.json", std::process::id()));
+        let params = FpParams::new(5, 15);
+
+        write_hashes(&path, &params, &[3, 1, 2]).unwrap();
+        assert_eq!(load_hashes(&path, &params).unwrap(), [1, 2, 3].iter().cloned().collect());
+
+        // hashes made with other params can't be used
+        assert!(matches!(load_hashes(&path, &FpParams::new(6, 15)), Err(MossError::InvalidParams(_))));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+//! cache.rs: On-disk cache of document fingerprints, so that unchanged
+//! files are not renormalized/refingerprinted on subsequent runs
+
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use fnv::FnvHasher;
+use crate::error::{MossError, Result};
+use crate::fingerprint::{self, Fingerprint};
+use crate::normalize;
+use crate::phase_i::FpParams;
+
+// distinguishes temporary files written concurrently by this process
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Determine the key under which the fingerprints of a file with the
+/// given contents are cached. The key changes whenever the contents,
+/// the fingerprint params, or the normalizer/fingerprinter change.
+/// Different contents may share a key: entries store the contents they
+/// were made from, & are only used for exactly those contents.
+pub fn cache_key(contents: &str, params: &FpParams) -> String {
+    let mut hasher = FnvHasher::default();
+    hasher.write(contents.as_bytes());
+
+    // normalization options, k-gram mode & hash width are summarized by a hash of their description
+    let mut norm_hasher = FnvHasher::default();
+    norm_hasher.write(format!("{:?}-{:?}-{:?}", params.norm, params.kgrams, params.hash_width).as_bytes());
+
+    // include the length to make collisions between different contents less likely
+    format!("{:016x}-{}-k{}-t{}-o{:016x}-n{}-f{}", hasher.finish(), contents.len(), params.k, params.t,
+        norm_hasher.finish(), normalize::VERSION, fingerprint::VERSION)
+}
+
+/// The file in which the fingerprints for a key are stored
+fn entry_path(cache_dir: &Path, key: &str) -> PathBuf {
+    cache_dir.join(format!("{}.json", key))
+}
+
+/// Create the cache directory if it doesn't exist yet
+pub fn init(cache_dir: &Path) -> Result<()> {
+    fs::create_dir_all(cache_dir).map_err(|e| {
+        MossError::Io(format!("failed to create cache directory {}", cache_dir.display()), e)
+    })
+}
+
+/// Look up the fingerprints stored under a key for a file with the given
+/// contents, if any. Entries that can't be read or parsed, or that were
+/// made from other contents, are treated as missing (and will be overwritten).
+pub fn load(cache_dir: &Path, key: &str, contents: &str) -> Option<Vec<Fingerprint>> {
+    let entry = fs::read(entry_path(cache_dir, key)).ok()?;
+    let (stored, fps): (String, Vec<Fingerprint>) = serde_json::from_slice(&entry).ok()?;
+
+    if stored == contents { Some(fps) } else { None }
+}
+
+/// Store the fingerprints of a file with the given contents under a key.
+/// The entry is written to a temporary file first, so concurrent readers
+/// never see a partially written entry.
+pub fn store(cache_dir: &Path, key: &str, contents: &str, fps: &[Fingerprint]) -> Result<()> {
+    let path = entry_path(cache_dir, key);
+    let tmp_path = cache_dir.join(format!("{}.{}-{}.tmp", key, std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+
+    // an entry is stored as [contents, fingerprints]
+    let entry = serde_json::to_vec(&(contents, fps))
+        .map_err(|e| MossError::json(String::from("failed to serialize fingerprints"), e))?;
+
+    fs::write(&tmp_path, entry)
+        .and_then(|_| fs::rename(&tmp_path, &path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&tmp_path);
+            MossError::Io(format!("failed to write cache entry {}", path.display()), e)
+        })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::normalize::{NormOptions, IdentifierMode};
+    use crate::fingerprint::{HashWidth, KgramMode};
+
+    // a fresh directory for a test to cache into
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("pyret-moss-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        init(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let key = cache_key("fun f(x): x end", &FpParams::new(5, 10));
+
+        assert_eq!(key, cache_key("fun f(x): x end", &FpParams::new(5, 10)));
+        assert_ne!(key, cache_key("fun f(y): y end", &FpParams::new(5, 10)));
+        assert_ne!(key, cache_key("fun f(x): x end", &FpParams::new(6, 10)));
+        assert_ne!(key, cache_key("fun f(x): x end", &FpParams::new(5, 11)));
+
+        let renamed = NormOptions { identifiers: IdentifierMode::Renamed, ..NormOptions::default() };
+        assert_ne!(key, cache_key("fun f(x): x end", &FpParams { norm: renamed, ..FpParams::new(5, 10) }));
+        assert_ne!(key, cache_key("fun f(x): x end", &FpParams { kgrams: KgramMode::Tokens, ..FpParams::new(5, 10) }));
+        assert_ne!(key, cache_key("fun f(x): x end", &FpParams { hash_width: HashWidth::Bits64, ..FpParams::new(5, 10) }));
+    }
+
+    #[test]
+    fn test_store_load() {
+        let dir = test_dir("store-load");
+        let fps = vec![
+            Fingerprint { hash: 28, lines: (4, 5), cols: (1, 1) },
+            Fingerprint { hash: 17, lines: (6, 7), cols: (1, 1) }
+        ];
+
+        assert_eq!(load(&dir, "missing", "x"), None);
+
+        store(&dir, "present", "x = 1", &fps).unwrap();
+        assert_eq!(load(&dir, "present", "x = 1"), Some(fps));
+
+        // an entry made from other contents (under a colliding key) is a miss
+        assert_eq!(load(&dir, "present", "y = 1"), None);
+
+        // corrupt entries are a miss
+        fs::write(entry_path(&dir, "corrupt"), "[\"x\", [{\"hash\":").unwrap();
+        assert_eq!(load(&dir, "corrupt", "x"), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
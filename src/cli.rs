@@ -1,21 +1,58 @@
-/// cli.rs: Functions for providing the command-line interface
+//! cli.rs: Functions for providing the command-line interface
 
 use std::path::Path;
 use std::collections::HashSet;
+use serde::{Serialize, Deserialize};
+use crate::error::{MossError, Result};
+use crate::cluster;
+use crate::fingerprint::{HashWidth, KgramMode};
+use crate::normalize::{NormOptions, IdentifierMode};
+use crate::lsh::LshParams;
+use crate::phase_i::MaxFrequency;
 
 /// OptArgs encodes important system parameters that have default values
 /// but can be set via the command line interface
 #[derive(Debug, PartialEq)]
 pub struct OptArgs<'a> {
+    pub command: Command,                       // what to do with the submissions
     pub sub_mode: SubFileMode,                  // indicates whether subs are files or dirs
     pub k: i32,                                 // noise threshold
     pub t: i32,                                 // guarantee threshold
+    pub norm: NormOptions,                      // normalizations applied before fingerprinting
+    pub kgrams: KgramMode,                      // whether k-grams (and k, t) are made of chars or tokens
+    pub hash_width: HashWidth,                  // range of fingerprint hash values
+    pub verify_matches: bool,                   // check shared hashes stand for equal k-grams before pairing
     pub match_threshold: f64,                   // include sub pairs whose match percentage is at least this big
+    pub max_frequency: Option<MaxFrequency>,    // suppress fingerprints shared by more submissions than this
+    pub lsh: Option<LshParams>,                 // if given, only pair up candidates chosen by MinHash LSH
+    pub cluster_similarity: Option<f64>,        // if given, cluster subs linked by pairs at least this similar
+    pub group_size: Option<usize>,              // if given, group subs by fingerprints shared by this many or more
     pub ignore_content_dir: Option<&'a Path>,   // dir of files indicating expected overlap to ignore
+    pub archive_dir: Option<&'a Path>,          // dir of prior submissions to compare current ones against
+    pub db_file: Option<&'a Path>,              // fingerprint database to store subs in / compare subs against
+    pub snippets_dir: Option<&'a Path>,         // dir to write suggested boilerplate snippets to
+    pub hashes_file: Option<&'a Path>,          // file to write suggested boilerplate hashes to
     pub ignore_files: Option<HashSet<String>>,  // filenames of files to ignore
     pub out_file: Option<&'a Path>,             // where the program's result summary will be written (default stdout)
     pub verbose: bool,                          // option to increase intensity of logging
-    pub no_pauses: bool                         // if true, don't pause to confirm when rendering output pairs
+    pub no_pauses: bool,                        // if true, don't pause to confirm when rendering output pairs
+    pub jobs: usize,                            // number of threads used to fingerprint documents
+    pub cache_dir: Option<&'a Path>,            // if given, cache fingerprints of documents in this dir
+    pub format: OutputFormat,                   // how the result summary should be rendered
+    pub html_dir: Option<&'a Path>,             // if given, also write an HTML report to this dir
+    pub csv_file: Option<&'a Path>              // if given, also write a CSV summary of pairs to this file
+}
+
+/// Command indicates what the program should do with the submissions:
+///  1) Analyze reports overlap between submissions (default)
+///  2) Index fingerprints submissions & stores them in a database file
+///  3) SuggestBoilerplate finds fingerprints shared by many submissions &
+///     writes them out as content to ignore in later runs
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Command {
+    Analyze,
+    Index,
+    SuggestBoilerplate
 }
 
 /// SubFileMode indicates how submissions should be found within
@@ -24,25 +61,52 @@ pub struct OptArgs<'a> {
 ///     will construct one Sub for each .arr file in the given dir
 ///  2) Multi assumes submissions are directories with multiple .arr files
 ///     within them, and will construct one Sub for each dir in the given dir.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum SubFileMode {
     Single,
     Multi
 }
 
+/// OutputFormat indicates how results should be rendered:
+///  1) Table renders a human-readable table for each submission pair
+///  2) Json renders all pairs & their matches as a single JSON document
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OutputFormat {
+    Table,
+    Json
+}
+
 /// default values of all system parameters
 impl Default for OptArgs<'_> {
     fn default() -> Self {
         OptArgs {
+            command: Command::Analyze,
             sub_mode: SubFileMode::Multi,
             k: 15,
             t: 20,
+            norm: NormOptions::default(),
+            kgrams: KgramMode::Chars,
+            hash_width: HashWidth::Bits31,
+            verify_matches: false,
             match_threshold: 0.0f64,
+            max_frequency: None,
+            lsh: None,
+            cluster_similarity: None,
+            group_size: None,
             ignore_content_dir: None,
+            archive_dir: None,
+            db_file: None,
+            snippets_dir: None,
+            hashes_file: None,
             ignore_files: None,
             out_file: None,
             verbose: false,
-            no_pauses: false
+            no_pauses: false,
+            jobs: 1,
+            cache_dir: None,
+            format: OutputFormat::Table,
+            html_dir: None,
+            csv_file: None
         }
     }
 }
@@ -51,29 +115,43 @@ impl Default for OptArgs<'_> {
 /// submissions dir, and a struct with optional arg values.
 /// If the help flag is included, print_help() will be called
 /// and the program will exit.
-pub fn parse_args(args: &Vec<String>) -> (&Path, OptArgs) {
+pub fn parse_args(args: &[String]) -> Result<(&Path, OptArgs<'_>)> {
     let argc = args.len();
 
     // handle invalid arity
     if argc == 0 {
         panic!("program received 0 arguments, somehow");
     } else if argc == 1 {
-        err!("usage: {} [options] <submission-dir>. See --help for more.", &args[0]);
+        return Err(MossError::Usage(
+            format!("usage: {} [options] <submission-dir>. See --help for more.", &args[0])));
     }
 
     let mut options = OptArgs::default();   // start with default options
     let mut sub_dir: Option<&Path> = None;
 
     // unwrap the next argument or give a specific error if none available
-    fn unwrap_next<'a>(flag: &str, next: Option<&'a String>) -> &'a String {
-        if let Some(arg) = next {
-            arg
-        } else {
-            err!("expected an argument for {}", flag);
+    fn unwrap_next<'a>(flag: &str, next: Option<&'a String>) -> Result<&'a String> {
+        match next {
+            Some(arg) => Ok(arg),
+            None => Err(MossError::Usage(format!("expected an argument for {}", flag))),
         }
     }
 
-    let mut iter = args.iter().skip(1); // skip first arg (path to program)
+    let mut iter = args.iter().skip(1).peekable(); // skip first arg (path to program)
+
+    // an optional command may precede all other arguments
+    match iter.peek().map(|arg| arg.as_str()) {
+        Some("analyze") => { iter.next(); },
+        Some("index") => {
+            options.command = Command::Index;
+            iter.next();
+        },
+        Some("suggest-boilerplate") => {
+            options.command = Command::SuggestBoilerplate;
+            iter.next();
+        },
+        _ => {},
+    }
 
     while let Some(arg) = iter.next() {
         let arg = arg.as_str();
@@ -81,7 +159,7 @@ pub fn parse_args(args: &Vec<String>) -> (&Path, OptArgs) {
             "--help" | "-h" => print_help(&args[0]),
             "--single-file-mode" | "-s" => options.sub_mode = SubFileMode::Single,
             "--noise" | "-k" => {
-                let k_str = unwrap_next(arg, iter.next());
+                let k_str = unwrap_next(arg, iter.next())?;
 
                 // only accept integer k > 0
                 if let Ok(k) = k_str.parse::<i32>() {
@@ -90,10 +168,11 @@ pub fn parse_args(args: &Vec<String>) -> (&Path, OptArgs) {
                         continue;
                     }
                 }
-                err!("invalid value for noise threshold (k): `{}`", k_str);
+                return Err(MossError::InvalidParams(
+                    format!("invalid value for noise threshold (k): `{}`", k_str)));
             },
             "--guarantee" | "-t" => {
-                let t_str = unwrap_next(arg, iter.next());
+                let t_str = unwrap_next(arg, iter.next())?;
 
                 // only accept integer t > 0
                 if let Ok(t) = t_str.parse::<i32>() {
@@ -102,57 +181,236 @@ pub fn parse_args(args: &Vec<String>) -> (&Path, OptArgs) {
                         continue;
                     }
                 }
-                err!("invalid value for guarantee threshold (t): `{}`", t_str);
+                return Err(MossError::InvalidParams(
+                    format!("invalid value for guarantee threshold (t): `{}`", t_str)));
+            },
+            "--rename-identifiers" | "--keep-identifiers" => {
+                let mode = if arg == "--rename-identifiers" {
+                    IdentifierMode::Renamed
+                } else {
+                    IdentifierMode::Preserved
+                };
+
+                // identifiers can be renamed or kept, but not both
+                if options.norm.identifiers != IdentifierMode::Uniform && options.norm.identifiers != mode {
+                    return Err(MossError::Usage(String::from(
+                        "--rename-identifiers and --keep-identifiers cannot be combined")));
+                }
+                options.norm.identifiers = mode;
+            },
+            "--kgrams" => {
+                let kgrams_str = unwrap_next(arg, iter.next())?;
+
+                options.kgrams = match kgrams_str.as_str() {
+                    "chars" => KgramMode::Chars,
+                    "tokens" => KgramMode::Tokens,
+                    "ast" => KgramMode::Ast,
+                    _ => {
+                        return Err(MossError::Usage(
+                            format!("invalid value for --kgrams: `{}` (expected chars, tokens, or ast)", kgrams_str)));
+                    },
+                };
+            },
+            "--hash-bits" => {
+                let bits_str = unwrap_next(arg, iter.next())?;
+
+                options.hash_width = match bits_str.as_str() {
+                    "31" => HashWidth::Bits31,
+                    "64" => HashWidth::Bits64,
+                    _ => {
+                        return Err(MossError::Usage(
+                            format!("invalid value for --hash-bits: `{}` (expected 31 or 64)", bits_str)));
+                    },
+                };
+            },
+            "--verify-matches" => options.verify_matches = true,
+            "--keep-types" => options.norm.types = false,
+            "--keep-whitespace" => options.norm.whitespace = false,
+            "--keep-docstrings" => options.norm.docstrings = false,
+            "--keep-comments" => options.norm.comments = false,
+            "--normalize-numbers" => options.norm.numbers = true,
+            "--normalize-strings" => options.norm.strings = true,
+            "--jobs" | "-j" => {
+                let jobs_str = unwrap_next(arg, iter.next())?;
+
+                // only accept integer jobs > 0
+                if let Ok(jobs) = jobs_str.parse::<usize>() {
+                    if jobs > 0 {
+                        options.jobs = jobs;
+                        continue;
+                    }
+                }
+                return Err(MossError::InvalidParams(
+                    format!("invalid value for --jobs: `{}`", jobs_str)));
+            },
+            "--cache" => {
+                let cache_dir = unwrap_next(arg, iter.next())?;
+                options.cache_dir = Some(Path::new(cache_dir));
             },
             "--output" | "-o" => {
-                let out_file = unwrap_next(arg, iter.next());
-                options.out_file = Some(&Path::new(out_file));
+                let out_file = unwrap_next(arg, iter.next())?;
+                options.out_file = Some(Path::new(out_file));
             },
             "--ignore-content" => {
-                let ignore_content_dir = unwrap_next(arg, iter.next());
-                options.ignore_content_dir = Some(&Path::new(ignore_content_dir));
+                let ignore_content_dir = unwrap_next(arg, iter.next())?;
+                options.ignore_content_dir = Some(Path::new(ignore_content_dir));
+            },
+            "--archive" => {
+                let archive_dir = unwrap_next(arg, iter.next())?;
+                options.archive_dir = Some(Path::new(archive_dir));
+            },
+            "--db" | "--against-db" => {
+                // --db names the database to index into, --against-db the one to analyze against
+                let (flag, command) = match options.command {
+                    Command::Index => ("--db", "index"),
+                    Command::Analyze => ("--against-db", "analyze"),
+                    Command::SuggestBoilerplate => ("", "suggest-boilerplate"),
+                };
+                if arg != flag {
+                    return Err(MossError::Usage(format!("{} can't be used with the {} command", arg, command)));
+                }
+
+                let db_file = unwrap_next(arg, iter.next())?;
+                options.db_file = Some(Path::new(db_file));
+            },
+            "--snippets" | "--hashes" => {
+                if options.command != Command::SuggestBoilerplate {
+                    return Err(MossError::Usage(
+                        format!("{} can only be used with the suggest-boilerplate command", arg)));
+                }
+
+                let out = Path::new(unwrap_next(arg, iter.next())?);
+                if arg == "--snippets" {
+                    options.snippets_dir = Some(out);
+                } else {
+                    options.hashes_file = Some(out);
+                }
             },
             "--ignore-files" => {
-                let arg_string = unwrap_next(arg, iter.next());
+                let arg_string = unwrap_next(arg, iter.next())?;
                 let mut ignore_files = HashSet::new();
 
                 // add each filename to the set
                 for file_name in arg_string.split(" ") {
                     if file_name.is_empty() {
-                        err!("invalid argument to --ignore-files: `{}`", arg_string);
+                        return Err(MossError::Usage(
+                            format!("invalid argument to --ignore-files: `{}`", arg_string)));
                     }
 
                     ignore_files.insert(String::from(file_name));
                 }
 
-                if ignore_files.len() == 0 {
-                    err!("--ignore-files expected at least 1 filename to ignore");
+                if ignore_files.is_empty() {
+                    return Err(MossError::Usage(
+                        String::from("--ignore-files expected at least 1 filename to ignore")));
                 }
 
                 options.ignore_files = Some(ignore_files);
             },
             "--match-threshold" => {
-                let thresh_str = unwrap_next(arg, iter.next());
+                let thresh_str = unwrap_next(arg, iter.next())?;
 
                 if let Ok(match_threshold) = thresh_str.parse::<f64>() {
                     let match_threshold = match_threshold / 100.0f64;
                     options.match_threshold = match_threshold;
                 } else {
-                    err!("invalid value for --match-threshold: `{}`", thresh_str);
+                    return Err(MossError::InvalidParams(
+                        format!("invalid value for --match-threshold: `{}`", thresh_str)));
                 }
             },
+            "--max-frequency" | "-m" => {
+                let max_str = unwrap_next(arg, iter.next())?;
+
+                // accept an integer count > 0, or a percentage in (0, 100]
+                let max = match max_str.strip_suffix('%') {
+                    Some(perc_str) => perc_str.parse::<f64>().ok()
+                        .filter(|p| *p > 0.0 && *p <= 100.0)
+                        .map(MaxFrequency::Percent),
+                    None => max_str.parse::<usize>().ok()
+                        .filter(|n| *n > 0)
+                        .map(MaxFrequency::Count),
+                };
+
+                match max {
+                    Some(max) => options.max_frequency = Some(max),
+                    None => {
+                        return Err(MossError::InvalidParams(
+                            format!("invalid value for --max-frequency: `{}` (expected N or N%)", max_str)));
+                    },
+                };
+            },
+            "--clusters" => {
+                let sim_str = unwrap_next(arg, iter.next())?;
+
+                if let Ok(similarity) = sim_str.parse::<f64>() {
+                    options.cluster_similarity = Some(similarity / 100.0f64);
+                } else {
+                    return Err(MossError::InvalidParams(
+                        format!("invalid value for --clusters: `{}`", sim_str)));
+                }
+            },
+            "--groups" => {
+                let size_str = unwrap_next(arg, iter.next())?;
+
+                // groups of two are just pairs
+                match size_str.parse::<usize>().ok().filter(|&n| n >= cluster::MIN_CLUSTER_SIZE) {
+                    Some(size) => options.group_size = Some(size),
+                    None => {
+                        return Err(MossError::InvalidParams(
+                            format!("invalid value for --groups: `{}` (expected an integer N >= {})",
+                                size_str, cluster::MIN_CLUSTER_SIZE)));
+                    },
+                };
+            },
+            "--lsh" => {
+                let lsh_str = unwrap_next(arg, iter.next())?;
+
+                // accept BANDSxROWS, both integers > 0
+                let lsh = lsh_str.split_once('x')
+                    .and_then(|(bands, rows)| Some((bands.parse::<usize>().ok()?, rows.parse::<usize>().ok()?)))
+                    .filter(|&(bands, rows)| bands > 0 && rows > 0)
+                    .map(|(bands, rows)| LshParams { bands, rows });
+
+                match lsh {
+                    Some(lsh) => options.lsh = Some(lsh),
+                    None => {
+                        return Err(MossError::InvalidParams(
+                            format!("invalid value for --lsh: `{}` (expected BANDSxROWS, e.g. 32x4)", lsh_str)));
+                    },
+                };
+            },
+            "--format" => {
+                let format_str = unwrap_next(arg, iter.next())?;
+
+                options.format = match format_str.as_str() {
+                    "table" => OutputFormat::Table,
+                    "json" => OutputFormat::Json,
+                    _ => {
+                        return Err(MossError::Usage(
+                            format!("invalid value for --format: `{}` (expected table or json)", format_str)));
+                    },
+                };
+            },
+            "--html" => {
+                let html_dir = unwrap_next(arg, iter.next())?;
+                options.html_dir = Some(Path::new(html_dir));
+            },
+            "--csv" => {
+                let csv_file = unwrap_next(arg, iter.next())?;
+                options.csv_file = Some(Path::new(csv_file));
+            },
             "--verbose" | "-v" => options.verbose = true,
             "--no-pauses" | "-p" => options.no_pauses = true,
             _ => {
                 // check for unrecognized flags
                 if arg.starts_with('-') {
-                    err!("unrecognized flag `{}`", arg);
-                } else if let None = sub_dir {
+                    return Err(MossError::Usage(format!("unrecognized flag `{}`", arg)));
+                } else if sub_dir.is_none() {
                     // assume this is the submissions directory
-                    sub_dir = Some(&Path::new(arg));
+                    sub_dir = Some(Path::new(arg));
                 } else {
                     // we already have a sub dir, this is just unexpected
-                    err!("unexpected argument: `{}`", arg);
+                    return Err(MossError::Usage(format!("unexpected argument: `{}`", arg)));
                 }
             },
         };
@@ -161,31 +419,53 @@ pub fn parse_args(args: &Vec<String>) -> (&Path, OptArgs) {
     /// Check a predicate on a value, and give an informative error message
     /// in the case of failure. Call display() on value to prep it for printing
     fn validate<F, T, D>(flag: &str, value: &T, valid: F, display: D, reminder: &str)
+        -> Result<()>
         where
             F: Fn(&T) -> bool,
             T: std::fmt::Display,
             D: Fn(&T) -> T {
         if !valid(value) {
-            err!("invalid value for {}: `{}` (remember: {})", flag, display(value), reminder);
+            return Err(MossError::InvalidParams(format!("invalid value for {}: `{}` (remember: {})",
+                flag, display(value), reminder)));
         }
+
+        Ok(())
     }
 
     // validate both k & t: must be positive and 0 < k <= t
     let kt_remind = "0 < k <= t";
     validate("noise threshold (k)", &options.k, |&k| k > 0 && k <= options.t,
-        |&k| k, kt_remind);
+        |&k| k, kt_remind)?;
     validate("guarantee threshold (t)", &options.t, |&t| t > 0 && t >= options.k,
-        |&t| t, kt_remind);
+        |&t| t, kt_remind)?;
 
     // validate match threshold
-    validate("--match-threshold", &options.match_threshold, |&t| t >= 0.0 && t <= 1.0,
-        |&m| m * 100.0, "must be a percentage value (0-100)");
+    validate("--match-threshold", &options.match_threshold, |&t| (0.0..=1.0).contains(&t),
+        |&m| m * 100.0, "must be a percentage value (0-100)")?;
+
+    // validate cluster similarity
+    if let Some(similarity) = options.cluster_similarity {
+        validate("--clusters", &similarity, |&s| (0.0..=1.0).contains(&s),
+            |&s| s * 100.0, "must be a percentage value (0-100)")?;
+    }
+
+    // indexing requires a database to index into
+    if options.command == Command::Index && options.db_file.is_none() {
+        return Err(MossError::Usage(String::from("index expected a database file (--db <FILE>)")));
+    }
+
+    // suggestions must be written somewhere
+    if options.command == Command::SuggestBoilerplate
+        && options.snippets_dir.is_none() && options.hashes_file.is_none() {
+        return Err(MossError::Usage(String::from(
+            "suggest-boilerplate expected somewhere to write suggestions (--snippets <DIR> and/or --hashes <FILE>)")));
+    }
 
     if let Some(dir) = sub_dir {
         // return the submissions directory & updated options
-        return (dir, options);
+        Ok((dir, options))
     } else {
-        err!("no submission directory given");
+        Err(MossError::Usage(String::from("no submission directory given")))
     }
 }
 
@@ -199,10 +479,20 @@ fn print_help(exec: &String) {
 Copy-detection for Pyret
 
 Usage:
-    {} <SUBMISSIONS-DIR> [OPTIONS]
+    {0} [analyze] <SUBMISSIONS-DIR> [OPTIONS]
+    {0} index <SUBMISSIONS-DIR> --db <FILE> [OPTIONS]
+    {0} suggest-boilerplate <SUBMISSIONS-DIR> [--snippets <DIR>] [--hashes <FILE>] [OPTIONS]
 
 SUBMISSIONS-DIR indicates a directory containing submissions.
 
+Commands:
+    analyze     Report overlap between submissions (default)
+    index       Fingerprint submissions & store them in a database FILE, to
+                compare later submissions against (see --against-db)
+    suggest-boilerplate
+                Find fingerprints shared by more submissions than --max-frequency
+                allows (default 50%), & write them out as content to ignore
+
 Submissions can be either
     1) individual .arr files (single-file mode)
     2) subdirectories of .arr files (multi-file mode (default))
@@ -212,10 +502,39 @@ OPTIONS:
     -s, --single-file-mode                  Submissions are assumed to be single .arr files
     -k, --noise <VALUE>                     Sets the noise threshold
     -t, --guarantee <VALUE>                 Sets the guarantee threshold
-        --ignore-content <DIR>              Ignore portions of submissions that match any file's content in DIR
+        --kgrams <UNIT>                     Make k-grams of `chars` (default) or `tokens` (k & t count UNITs),
+                                            or fingerprint syntax tree subtrees of k+ tokens (`ast`)
+        --hash-bits <BITS>                  Use `31` (default) or `64`-bit fingerprint hashes (fewer collisions)
+        --verify-matches                    Only count shared fingerprints whose normalized k-grams are equal
+        --rename-identifiers                Rename identifiers consistently (v0, v1, ...) instead of all to `v`
+        --keep-identifiers                  Don't normalize identifiers (e.g. to find verbatim copying)
+        --keep-types                        Don't remove type annotations
+        --keep-whitespace                   Don't remove whitespace
+        --keep-docstrings                   Don't remove docstrings
+        --keep-comments                     Don't remove comments (e.g. to catch copied comments)
+        --normalize-numbers                 Replace all numeric literals with the same value
+        --normalize-strings                 Replace all string literals with the empty string
+        --ignore-content <DIR>|<FILE>       Ignore portions of submissions that match any file's content in DIR,
+                                            or hashes in FILE (see suggest-boilerplate --hashes)
         --ignore-files "<FILE>[ <FILE>]"    Ignore submission files with the given names
+        --archive <DIR>                     Also compare submissions against prior submissions in DIR
+        --against-db <FILE>                 Also compare submissions against those stored in database FILE
+        --db <FILE>                         (index only) Database FILE to store submissions in
+        --snippets <DIR>                    (suggest-boilerplate only) Write suggested boilerplate as .arr files to DIR
+        --hashes <FILE>                     (suggest-boilerplate only) Write suggested boilerplate hashes to FILE
         --match-threshold <VALUE>           Only report submission pairs with pair percentile at least VALUE (0-100)
+    -m, --max-frequency <N>|<N%>            Ignore fingerprints shared by more than N (or N% of) submissions
+        --clusters <VALUE>                  Also report clusters of 3+ submissions linked by pairs in which either
+                                            submission's content match percentage is at least VALUE (0-100)
+        --groups <N>                        Also report runs of fingerprints shared by exactly the same N+ submissions (N >= 3)
+        --lsh <BANDS>x<ROWS>                Only pair submissions whose MinHash signatures agree on all ROWS
+                                            of some band (approximate, for very large corpora)
+    -j, --jobs <N>                          Fingerprint documents using N threads (default 1)
+        --cache <DIR>                       Reuse fingerprints of unchanged files across runs, stored in DIR
     -o, --output <FILE>                     Write analysis to FILE instead of stdout
+        --format <FORMAT>                   Render analysis as `table` (default) or `json`
+        --html <DIR>                        Also write an HTML report with side-by-side source to DIR
+        --csv <FILE>                        Also write a CSV summary of submission pairs to FILE
     -v, --verbose                           More logging
     -p, --no-pauses                         Don't pause for confirmation to continue when rendering results
 
@@ -230,6 +549,7 @@ For more detailed info see https://github.com/rpaul48/pyret-moss
 
 
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod tests {
     use super::*;
 
@@ -246,7 +566,7 @@ mod tests {
                 "/home/user/Desktop/submissions"
             ]);
 
-            let (sub_dir, opt_args) = parse_args(&args);
+            let (sub_dir, opt_args) = parse_args(&args).unwrap();
 
             assert_eq!(sub_dir, Path::new("/home/user/Desktop/submissions"));
             assert_eq!(opt_args, OptArgs::default());
@@ -257,7 +577,7 @@ mod tests {
                 "./here/are/the/submissions"
             ]);
 
-            let (sub_dir, opt_args) = parse_args(&args);
+            let (sub_dir, opt_args) = parse_args(&args).unwrap();
 
             assert_eq!(sub_dir, Path::new("./here/are/the/submissions"));
             assert_eq!(opt_args, OptArgs::default());
@@ -276,7 +596,7 @@ mod tests {
                 "./subs"
             ]);
 
-            let (sub_dir, opt_args) = parse_args(&args);
+            let (sub_dir, opt_args) = parse_args(&args).unwrap();
 
             assert_eq!(sub_dir, Path::new("./subs"));
             assert_eq!(opt_args, OptArgs {
@@ -288,7 +608,8 @@ mod tests {
                 match_threshold: 0.0,
                 out_file: None,
                 verbose: false,
-                no_pauses: false
+                no_pauses: false,
+                ..OptArgs::default()
             });
         }
         {
@@ -310,7 +631,7 @@ mod tests {
                 "--no-pauses"
             ]);
 
-            let (sub_dir, opt_args) = parse_args(&args);
+            let (sub_dir, opt_args) = parse_args(&args).unwrap();
 
             assert_eq!(sub_dir, Path::new("~/submissions"));
             assert_eq!(opt_args, OptArgs {
@@ -322,7 +643,8 @@ mod tests {
                 match_threshold: 0.168,
                 out_file: Some(&Path::new("~/Desktop/analysis.txt")),
                 verbose: true,
-                no_pauses: true
+                no_pauses: true,
+                ..OptArgs::default()
             });
         }
         {
@@ -335,7 +657,7 @@ mod tests {
                 "--no-pauses"
             ]);
 
-            let (sub_dir, opt_args) = parse_args(&args);
+            let (sub_dir, opt_args) = parse_args(&args).unwrap();
 
             let mut ignore_files = HashSet::new();
             ignore_files.insert(String::from("common.arr"));
@@ -351,8 +673,294 @@ mod tests {
                 match_threshold: 0.0,
                 out_file: None,
                 verbose: true,
-                no_pauses: true
+                no_pauses: true,
+                ..OptArgs::default()
             });
         }
     }
+
+    #[test]
+    fn parse_args_jobs() {
+        for flag in ["--jobs", "-j"] {
+            let args = to_vec_string(vec!["./pyret-moss", "./submissions", flag, "4"]);
+            let (_, opt_args) = parse_args(&args).unwrap();
+            assert_eq!(opt_args, OptArgs { jobs: 4, ..OptArgs::default() });
+        }
+
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions"]);
+        assert_eq!(parse_args(&args).unwrap().1.jobs, 1);
+
+        for bad in ["0", "-2", "all"] {
+            let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--jobs", bad]);
+            assert!(matches!(parse_args(&args), Err(MossError::InvalidParams(_))), "accepted {}", bad);
+        }
+    }
+
+    #[test]
+    fn parse_args_cache() {
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--cache", "./.moss-cache"]);
+        let (_, opt_args) = parse_args(&args).unwrap();
+        assert_eq!(opt_args, OptArgs { cache_dir: Some(Path::new("./.moss-cache")), ..OptArgs::default() });
+
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions"]);
+        assert_eq!(parse_args(&args).unwrap().1.cache_dir, None);
+
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--cache"]);
+        assert!(parse_args(&args).is_err());
+    }
+
+    #[test]
+    fn parse_args_rename_identifiers() {
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--rename-identifiers"]);
+        let (_, opt_args) = parse_args(&args).unwrap();
+
+        assert_eq!(opt_args, OptArgs {
+            norm: NormOptions { identifiers: IdentifierMode::Renamed, ..NormOptions::default() },
+            ..OptArgs::default()
+        });
+    }
+
+    #[test]
+    fn parse_args_format() {
+        {
+            let args = to_vec_string(vec![
+                "./pyret-moss",
+                "./submissions",
+                "--format",
+                "json",
+                "--html",
+                "./report",
+                "--csv",
+                "./pairs.csv",
+                "--archive",
+                "./fall-2019"
+            ]);
+
+            let (_, opt_args) = parse_args(&args).unwrap();
+
+            assert_eq!(opt_args.format, OutputFormat::Json);
+            assert_eq!(opt_args.html_dir, Some(Path::new("./report")));
+            assert_eq!(opt_args.csv_file, Some(Path::new("./pairs.csv")));
+            assert_eq!(opt_args.archive_dir, Some(Path::new("./fall-2019")));
+        }
+        {
+            let args = to_vec_string(vec![
+                "./pyret-moss",
+                "./submissions",
+                "--format",
+                "xml"
+            ]);
+
+            assert!(matches!(parse_args(&args), Err(MossError::Usage(_))));
+        }
+    }
+
+    #[test]
+    fn parse_args_commands() {
+        {
+            let args = to_vec_string(vec![
+                "./pyret-moss",
+                "index",
+                "./submissions",
+                "--db",
+                "./fall.db"
+            ]);
+
+            let (sub_dir, opt_args) = parse_args(&args).unwrap();
+
+            assert_eq!(sub_dir, Path::new("./submissions"));
+            assert_eq!(opt_args.command, Command::Index);
+            assert_eq!(opt_args.db_file, Some(Path::new("./fall.db")));
+        }
+        {
+            let args = to_vec_string(vec![
+                "./pyret-moss",
+                "analyze",
+                "./submissions",
+                "--against-db",
+                "./fall.db"
+            ]);
+
+            let (sub_dir, opt_args) = parse_args(&args).unwrap();
+
+            assert_eq!(sub_dir, Path::new("./submissions"));
+            assert_eq!(opt_args.command, Command::Analyze);
+            assert_eq!(opt_args.db_file, Some(Path::new("./fall.db")));
+        }
+        {
+            // index requires --db, & each db flag belongs to one command
+            let missing_db = to_vec_string(vec!["./pyret-moss", "index", "./submissions"]);
+            let wrong_flag = to_vec_string(vec!["./pyret-moss", "./submissions", "--db", "./fall.db"]);
+            let no_db = to_vec_string(vec!["./pyret-moss", "suggest-boilerplate", "./submissions",
+                "--against-db", "./fall.db"]);
+
+            assert!(matches!(parse_args(&missing_db), Err(MossError::Usage(_))));
+            assert!(matches!(parse_args(&wrong_flag), Err(MossError::Usage(_))));
+            assert_eq!(parse_args(&no_db).unwrap_err().to_string(),
+                "--against-db can't be used with the suggest-boilerplate command");
+        }
+        {
+            let args = to_vec_string(vec![
+                "./pyret-moss",
+                "suggest-boilerplate",
+                "./submissions",
+                "--snippets",
+                "./starter",
+                "--hashes",
+                "./starter.json",
+                "-m",
+                "5"
+            ]);
+
+            let (sub_dir, opt_args) = parse_args(&args).unwrap();
+
+            assert_eq!(sub_dir, Path::new("./submissions"));
+            assert_eq!(opt_args.command, Command::SuggestBoilerplate);
+            assert_eq!(opt_args.snippets_dir, Some(Path::new("./starter")));
+            assert_eq!(opt_args.hashes_file, Some(Path::new("./starter.json")));
+            assert_eq!(opt_args.max_frequency, Some(MaxFrequency::Count(5)));
+        }
+        {
+            // suggestions need an output, & outputs belong to suggest-boilerplate
+            let missing_out = to_vec_string(vec!["./pyret-moss", "suggest-boilerplate", "./submissions"]);
+            let wrong_flag = to_vec_string(vec!["./pyret-moss", "./submissions", "--snippets", "./starter"]);
+
+            assert!(matches!(parse_args(&missing_out), Err(MossError::Usage(_))));
+            assert!(matches!(parse_args(&wrong_flag), Err(MossError::Usage(_))));
+        }
+    }
+
+    #[test]
+    fn parse_args_normalization() {
+        {
+            let args = to_vec_string(vec![
+                "./pyret-moss",
+                "./submissions",
+                "--keep-identifiers",
+                "--keep-comments",
+                "--keep-docstrings",
+                "--normalize-numbers",
+                "--kgrams",
+                "tokens"
+            ]);
+
+            let (_, opt_args) = parse_args(&args).unwrap();
+
+            assert_eq!(opt_args.norm, NormOptions {
+                identifiers: IdentifierMode::Preserved,
+                comments: false,
+                docstrings: false,
+                numbers: true,
+                ..NormOptions::default()
+            });
+            assert_eq!(opt_args.kgrams, KgramMode::Tokens);
+        }
+        {
+            let args = to_vec_string(vec![
+                "./pyret-moss",
+                "./submissions",
+                "--keep-types",
+                "--keep-whitespace",
+                "--normalize-strings"
+            ]);
+
+            let (_, opt_args) = parse_args(&args).unwrap();
+
+            assert_eq!(opt_args.norm, NormOptions {
+                types: false,
+                whitespace: false,
+                strings: true,
+                ..NormOptions::default()
+            });
+        }
+        {
+            // identifiers can't be both renamed & kept
+            let args = to_vec_string(vec![
+                "./pyret-moss",
+                "./submissions",
+                "--rename-identifiers",
+                "--keep-identifiers"
+            ]);
+
+            assert!(matches!(parse_args(&args), Err(MossError::Usage(_))));
+
+            let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--kgrams", "lines"]);
+            assert!(matches!(parse_args(&args), Err(MossError::Usage(_))));
+        }
+    }
+
+    #[test]
+    fn parse_args_hashing() {
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--hash-bits", "64", "--verify-matches"]);
+        let (_, opt_args) = parse_args(&args).unwrap();
+
+        assert_eq!(opt_args.hash_width, HashWidth::Bits64);
+        assert!(opt_args.verify_matches);
+
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions"]);
+        let (_, opt_args) = parse_args(&args).unwrap();
+        assert_eq!(opt_args.hash_width, HashWidth::Bits31);
+        assert!(!opt_args.verify_matches);
+
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--hash-bits", "32"]);
+        assert!(matches!(parse_args(&args), Err(MossError::Usage(_))));
+    }
+
+    #[test]
+    fn parse_args_max_frequency() {
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--max-frequency", "10"]);
+        let (_, opt_args) = parse_args(&args).unwrap();
+        assert_eq!(opt_args.max_frequency, Some(MaxFrequency::Count(10)));
+
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions", "-m", "12.5%"]);
+        let (_, opt_args) = parse_args(&args).unwrap();
+        assert_eq!(opt_args.max_frequency, Some(MaxFrequency::Percent(12.5)));
+
+        for bad in ["0", "-3", "0%", "101%", "ten"] {
+            let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--max-frequency", bad]);
+            assert!(parse_args(&args).is_err(), "accepted {}", bad);
+        }
+    }
+
+    #[test]
+    fn parse_args_clusters() {
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--clusters", "60"]);
+        let (_, opt_args) = parse_args(&args).unwrap();
+        assert_eq!(opt_args.cluster_similarity, Some(0.6));
+
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions"]);
+        assert_eq!(parse_args(&args).unwrap().1.cluster_similarity, None);
+
+        for bad in ["150", "-5", "most"] {
+            let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--clusters", bad]);
+            assert!(matches!(parse_args(&args), Err(MossError::InvalidParams(_))), "accepted {}", bad);
+        }
+    }
+
+    #[test]
+    fn parse_args_groups() {
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--groups", "4"]);
+        let (_, opt_args) = parse_args(&args).unwrap();
+        assert_eq!(opt_args.group_size, Some(4));
+
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions"]);
+        assert_eq!(parse_args(&args).unwrap().1.group_size, None);
+
+        for bad in ["2", "-3", "many"] {
+            let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--groups", bad]);
+            assert!(matches!(parse_args(&args), Err(MossError::InvalidParams(_))), "accepted {}", bad);
+        }
+    }
+
+    #[test]
+    fn parse_args_lsh() {
+        let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--lsh", "32x4"]);
+        let (_, opt_args) = parse_args(&args).unwrap();
+        assert_eq!(opt_args.lsh, Some(LshParams { bands: 32, rows: 4 }));
+
+        for bad in ["32", "0x4", "32x0", "x4", "32x4x2", "ax4"] {
+            let args = to_vec_string(vec!["./pyret-moss", "./submissions", "--lsh", bad]);
+            assert!(matches!(parse_args(&args), Err(MossError::InvalidParams(_))), "accepted {}", bad);
+        }
+    }
 }
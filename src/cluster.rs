@@ -0,0 +1,348 @@
+//! cluster.rs: Group submissions connected by highly similar pairs into
+//! clusters, & submissions sharing fingerprints no others have into groups,
+//! so that rings of students sharing code are reported together
+
+use std::collections::HashSet;
+use fnv::FnvHashMap;
+use crate::{Doc, Sub};
+use crate::error::Result;
+use crate::phase_ii::SubPair;
+use crate::phase_iii::{self, Entry};
+
+// the fewest submissions that form a cluster (two are just a pair)
+pub const MIN_CLUSTER_SIZE: usize = 3;
+
+/// A Cluster is a group of submissions connected (directly or through other members)
+/// by pairs at or above a similarity. pairs holds the indices of all given pairs
+/// between members, & fragments the runs of fingerprints shared by every member.
+#[derive(Debug, PartialEq)]
+pub struct Cluster<'a> {
+    pub members: Vec<&'a Sub>,
+    pub pairs: Vec<usize>,
+    pub fragments: Vec<Fragment>
+}
+
+/// A Group is a set of (at least min_subs) submissions that are the only ones to
+/// share some runs of fingerprints, & fragments are those runs
+#[derive(Debug, PartialEq)]
+pub struct Group<'a> {
+    pub members: Vec<&'a Sub>,
+    pub fragments: Vec<Fragment>
+}
+
+/// A Fragment is a maximal run of fingerprints shared by every member of a cluster
+/// or group (as it appears in the first member), where entries[i] locates it in the
+/// ith member
+#[derive(Debug, PartialEq)]
+pub struct Fragment {
+    pub size: usize,
+    pub entries: Vec<Vec<Entry>>
+}
+
+/// The similarity of a pair: the larger of its content match percentages, so that
+/// a submission copied wholesale into a larger one counts as similar
+pub fn similarity(pair: &SubPair) -> f64 {
+    pair.a_percent.max(pair.b_percent)
+}
+
+/// Find the root of x's set, halving paths along the way
+fn find(parents: &mut [usize], mut x: usize) -> usize {
+    while parents[x] != x {
+        parents[x] = parents[parents[x]];
+        x = parents[x];
+    }
+    x
+}
+
+/// Group the submissions of the given pairs into clusters: the connected components
+/// (of at least MIN_CLUSTER_SIZE submissions) of the graph whose edges are the pairs
+/// with similarity at least min_similarity. Clusters are ordered by descending size.
+pub fn find_clusters<'a>(pairs: &[SubPair<'a>], min_similarity: f64) -> Result<Vec<Cluster<'a>>> {
+    // identify each submission by the order in which it's first seen
+    let mut ids: FnvHashMap<*const Sub, usize> = FnvHashMap::default();
+    let mut subs: Vec<&'a Sub> = Vec::new();
+    for pair in pairs.iter() {
+        for sub in [pair.a, pair.b] {
+            ids.entry(sub as *const Sub).or_insert_with(|| {
+                subs.push(sub);
+                subs.len() - 1
+            });
+        }
+    }
+    let id = |sub: &Sub| ids[&(sub as *const Sub)];
+
+    // union the submissions of every sufficiently similar pair
+    let mut parents: Vec<usize> = (0..subs.len()).collect();
+    for pair in pairs.iter().filter(|p| similarity(p) >= min_similarity) {
+        let (a, b) = (find(&mut parents, id(pair.a)), find(&mut parents, id(pair.b)));
+        if a != b { parents[a] = b; }
+    }
+
+    let mut components: FnvHashMap<usize, Vec<usize>> = FnvHashMap::default();
+    for sub_id in 0..subs.len() {
+        let root = find(&mut parents, sub_id);
+        components.entry(root).or_default().push(sub_id);
+    }
+
+    let mut clusters: Vec<Cluster<'a>> = components.into_iter()
+        .filter(|(_, members)| members.len() >= MIN_CLUSTER_SIZE)
+        .map(|(root, _)| {
+            let mut members: Vec<&'a Sub> = Vec::new();
+            let mut member_pairs: Vec<usize> = Vec::new();
+
+            for (i, pair) in pairs.iter().enumerate() {
+                if find(&mut parents, id(pair.a)) == root && find(&mut parents, id(pair.b)) == root {
+                    member_pairs.push(i);
+                }
+            }
+            for sub in subs.iter() {
+                if find(&mut parents, id(sub)) == root { members.push(sub); }
+            }
+            members.sort();
+
+            let fragments = shared_fragments(&members)?;
+            Ok(Cluster { members, pairs: member_pairs, fragments })
+        })
+        .collect::<Result<_>>()?;
+
+    clusters.sort_by(|x, y| y.members.len().cmp(&x.members.len()).then(x.members.cmp(&y.members)));
+    Ok(clusters)
+}
+
+/// Group submissions by the runs of fingerprints they share: each maximal run of
+/// fingerprints (within any submission) whose hashes are all contained by the same
+/// at least min_subs submissions is keyed by exactly those submissions, & every set
+/// of submissions keying some runs forms a group, whose fragments are those runs.
+/// This way content passed around many submissions is reported once, rather than
+/// once for every pair, & a hash of a run that also appears elsewhere doesn't split it.
+/// Groups are ordered by descending size, then by descending number of fingerprints.
+pub fn find_groups<'a>(hash_to_subs: &FnvHashMap<i64, HashSet<&'a Sub>>, min_subs: usize)
+    -> Result<Vec<Group<'a>>> {
+    let frequent: HashSet<i64> = hash_to_subs.iter()
+        .filter(|(_, subs)| subs.len() >= min_subs)
+        .map(|(&hash, _)| hash)
+        .collect();
+
+    // every submission containing a frequent hash, once each
+    let mut subs: Vec<&'a Sub> = frequent.iter().flat_map(|hash| hash_to_subs[hash].iter().cloned()).collect();
+    subs.sort_unstable_by_key(|&sub| sub as *const Sub);
+    subs.dedup_by_key(|sub| *sub as *const Sub);
+
+    // hashes keyed by exactly which submissions contain the runs they're in
+    let mut by_members: FnvHashMap<Vec<*const Sub>, (Vec<&'a Sub>, HashSet<i64>)> = FnvHashMap::default();
+    for sub in subs {
+        for (_, run) in phase_iii::runs(sub, &frequent)? {
+            for (members, hashes) in common_segments(hash_to_subs, &run, min_subs) {
+                let mut key: Vec<*const Sub> = members.iter().map(|&sub| sub as *const Sub).collect();
+                key.sort_unstable();
+
+                by_members.entry(key)
+                    .or_insert_with(|| (members, HashSet::new()))
+                    .1.extend(hashes);
+            }
+        }
+    }
+
+    let mut groups: Vec<(usize, Group<'a>)> = by_members.into_values()
+        .map(|(mut members, hashes)| {
+            members.sort();
+            let fragments = fragments(&members, &hashes)?;
+            Ok((hashes.len(), Group { members, fragments }))
+        })
+        .collect::<Result<_>>()?;
+
+    groups.sort_by(|(x_size, x), (y_size, y)| y.members.len().cmp(&x.members.len())
+        .then(y_size.cmp(x_size))
+        .then(x.members.cmp(&y.members)));
+    Ok(groups.into_iter().map(|(_, group)| group).collect())
+}
+
+/// Split a run of hashes (each contained by at least min_subs submissions) into maximal
+/// segments whose hashes are all contained by the same at least min_subs submissions,
+/// each paired with the submissions common to all of its hashes
+fn common_segments<'a>(hash_to_subs: &FnvHashMap<i64, HashSet<&'a Sub>>, run: &[i64], min_subs: usize)
+    -> Vec<(Vec<&'a Sub>, Vec<i64>)> {
+    let mut segments: Vec<(Vec<&'a Sub>, Vec<i64>)> = Vec::new();
+
+    for &hash in run.iter() {
+        let subs = &hash_to_subs[&hash];
+        let containing: HashSet<*const Sub> = subs.iter().map(|&sub| sub as *const Sub).collect();
+
+        // extend the current segment if enough of its submissions also contain this hash
+        if let Some((common, hashes)) = segments.last_mut() {
+            let narrowed: Vec<&'a Sub> = common.iter()
+                .filter(|&&sub| containing.contains(&(sub as *const Sub)))
+                .cloned()
+                .collect();
+
+            if narrowed.len() >= min_subs {
+                *common = narrowed;
+                hashes.push(hash);
+                continue;
+            }
+        }
+
+        segments.push((subs.iter().cloned().collect(), vec![hash]));
+    }
+
+    segments
+}
+
+/// Find the runs of fingerprints whose hashes appear in every one of the given
+/// submissions, located within each submission
+fn shared_fragments(members: &[&Sub]) -> Result<Vec<Fragment>> {
+    let hashes = |sub: &Sub| -> HashSet<i64> {
+        sub.documents.iter()
+            .flat_map(|doc| match doc {
+                Doc::Processed(_, fps) => fps.iter().map(|fp| fp.hash).collect(),
+                Doc::Unprocessed(_) => Vec::new(),
+            })
+            .collect()
+    };
+
+    // hashes shared by every member
+    let mut common = hashes(members[0]);
+    for sub in members[1..].iter() {
+        let sub_hashes = hashes(sub);
+        common.retain(|h| sub_hashes.contains(h));
+    }
+
+    fragments(members, &common)
+}
+
+/// Find the runs of fingerprints with hashes in keep as they appear in the first
+/// of the given submissions, located within each submission. Fragments are
+/// ordered by descending size.
+fn fragments(members: &[&Sub], keep: &HashSet<i64>) -> Result<Vec<Fragment>> {
+    let mut fragments: Vec<Fragment> = phase_iii::runs(members[0], keep)?.into_iter()
+        .map(|(_, run)| {
+            let run: HashSet<i64> = run.into_iter().collect();
+            let entries = members.iter()
+                .map(|sub| Ok(phase_iii::runs(sub, &run)?.into_iter().map(|(entry, _)| entry).collect()))
+                .collect::<Result<_>>()?;
+
+            Ok(Fragment { size: run.len(), entries })
+        })
+        .collect::<Result<_>>()?;
+
+    fragments.sort_by_key(|f| std::cmp::Reverse(f.size));
+    Ok(fragments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::fingerprint::Fingerprint;
+
+    fn sub(name: &str, hashes: &[i64]) -> Sub {
+        Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![Doc::Processed(PathBuf::from(name), hashes.iter().enumerate()
+                .map(|(i, &hash)| Fingerprint { hash, lines: (i as i32 + 1, i as i32 + 1), cols: (1, 5) })
+                .collect())]
+        }
+    }
+
+    fn pair<'a>(a: &'a Sub, b: &'a Sub, a_percent: f64, b_percent: f64) -> SubPair<'a> {
+        SubPair { a, a_percent, b, b_percent, matches: HashSet::new(), percentile: 1.0, doc_pairs: vec![] }
+    }
+
+    #[test]
+    fn test_find_clusters() {
+        let (s1, s2, s3) = (sub("s1.arr", &[1, 2, 3, 9]), sub("s2.arr", &[8, 1, 2, 3]), sub("s3.arr", &[1, 2, 7, 3]));
+        let (s4, s5) = (sub("s4.arr", &[4, 5]), sub("s5.arr", &[4, 5]));
+
+        let pairs = vec![
+            pair(&s1, &s2, 0.75, 0.75),
+            pair(&s4, &s5, 1.0, 1.0),
+            pair(&s2, &s3, 0.5, 0.75),
+            pair(&s1, &s3, 0.2, 0.3)
+        ];
+
+        // s1-s2-s3 are connected, s4 & s5 are only a pair
+        let clusters = find_clusters(&pairs, 0.6).unwrap();
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].members, vec![&s1, &s2, &s3]);
+        assert_eq!(clusters[0].pairs, vec![0, 2, 3]);
+
+        // 1, 2 & 3 are consecutive in s1, but s3 has them in two places
+        assert_eq!(clusters[0].fragments, vec![
+            Fragment { size: 3, entries: vec![
+                vec![Entry { doc_idx: 0, lines: (1, 3), cols: (1, 5) }],
+                vec![Entry { doc_idx: 0, lines: (2, 4), cols: (1, 5) }],
+                vec![Entry { doc_idx: 0, lines: (1, 2), cols: (1, 5) }, Entry { doc_idx: 0, lines: (4, 4), cols: (1, 5) }]
+            ]}
+        ]);
+
+        assert!(find_clusters(&pairs, 0.8).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_find_groups() {
+        let (s1, s2, s3) = (sub("s1.arr", &[1, 2, 3, 4]), sub("s2.arr", &[5, 1, 2, 4]), sub("s3.arr", &[4, 1, 2, 6]));
+        let s4 = sub("s4.arr", &[3, 6, 4]);
+
+        let mut hash_to_subs: FnvHashMap<i64, HashSet<&Sub>> = FnvHashMap::default();
+        for s in [&s1, &s2, &s3, &s4] {
+            if let Doc::Processed(_, fps) = &s.documents[0] {
+                for fp in fps.iter() { hash_to_subs.entry(fp.hash).or_default().insert(s); }
+            }
+        }
+
+        // 4 is in every sub, & 1, 2 only in the first three (which
+        // also share 4 as part of the run 1, 2, 4 in s2 & 4, 1, 2 in s3)
+        let groups = find_groups(&hash_to_subs, 3).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].members, vec![&s1, &s2, &s3, &s4]);
+        assert_eq!(groups[0].fragments.len(), 1);
+        assert_eq!(groups[0].fragments[0].size, 1);
+
+        assert_eq!(groups[1].members, vec![&s1, &s2, &s3]);
+        assert_eq!(groups[1].fragments, vec![
+            Fragment { size: 2, entries: vec![
+                vec![Entry { doc_idx: 0, lines: (1, 2), cols: (1, 5) }],
+                vec![Entry { doc_idx: 0, lines: (2, 3), cols: (1, 5) }],
+                vec![Entry { doc_idx: 0, lines: (2, 3), cols: (1, 5) }]
+            ]},
+            Fragment { size: 1, entries: vec![
+                vec![Entry { doc_idx: 0, lines: (4, 4), cols: (1, 5) }],
+                vec![Entry { doc_idx: 0, lines: (4, 4), cols: (1, 5) }],
+                vec![Entry { doc_idx: 0, lines: (1, 1), cols: (1, 5) }]
+            ]}
+        ]);
+
+        // no fingerprint is in all of five submissions
+        assert!(find_groups(&hash_to_subs, 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_find_groups_overlapping_run() {
+        let (s1, s2, s3) = (sub("s1.arr", &[1, 2, 3]), sub("s2.arr", &[1, 2, 3]), sub("s3.arr", &[1, 2, 3]));
+        let s4 = sub("s4.arr", &[7, 2, 8]);
+
+        let mut hash_to_subs: FnvHashMap<i64, HashSet<&Sub>> = FnvHashMap::default();
+        for s in [&s1, &s2, &s3, &s4] {
+            if let Doc::Processed(_, fps) = &s.documents[0] {
+                for fp in fps.iter() { hash_to_subs.entry(fp.hash).or_default().insert(s); }
+            }
+        }
+
+        // 2 is also in a fourth sub, but the run 1, 2, 3 isn't split by it
+        let groups = find_groups(&hash_to_subs, 3).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].members, vec![&s1, &s2, &s3, &s4]);
+        assert_eq!(groups[0].fragments.len(), 1);
+        assert_eq!(groups[0].fragments[0].size, 1);
+
+        assert_eq!(groups[1].members, vec![&s1, &s2, &s3]);
+        assert_eq!(groups[1].fragments, vec![
+            Fragment { size: 3, entries: vec![
+                vec![Entry { doc_idx: 0, lines: (1, 3), cols: (1, 5) }],
+                vec![Entry { doc_idx: 0, lines: (1, 3), cols: (1, 5) }],
+                vec![Entry { doc_idx: 0, lines: (1, 3), cols: (1, 5) }]
+            ]}
+        ]);
+    }
+}
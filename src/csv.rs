@@ -0,0 +1,143 @@
+//! csv.rs: Render a flat summary of submission pairs as CSV, one row per pair
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use crate::{Sub, Doc};
+use crate::cli::OptArgs;
+use crate::error::{MossError, Result};
+use crate::phase_ii::SubPair;
+use crate::phase_iii::Match;
+use crate::results::sub_name;
+
+// column names, in the order values appear in each row
+const HEADER: [&str; 9] = ["sub_a", "sub_b", "shared_fingerprints", "a_percent", "b_percent",
+    "percentile", "longest_match", "a_fingerprints", "b_fingerprints"];
+
+/// Write a CSV summary of the given submission pairs (& the matches of each) to the given file
+pub fn render_csv(csv_file: &Path, sub_pairs: &[SubPair], matches: &[Vec<Match>], opts: &OptArgs)
+    -> Result<()> {
+    if opts.verbose { println!("\nWriting CSV summary to {}", csv_file.display()); }
+
+    let contents = pairs_csv(sub_pairs, matches, opts)?;
+
+    fs::write(csv_file, contents).map_err(|e| {
+        MossError::Io(format!("failed to write {}", csv_file.display()), e)
+    })
+}
+
+/// Construct the CSV text (header included) for the given pairs & their matches
+fn pairs_csv(sub_pairs: &[SubPair], matches: &[Vec<Match>], opts: &OptArgs) -> Result<String> {
+    let mut csv = HEADER.join(",");
+    csv.push('\n');
+
+    for (pair, matches) in sub_pairs.iter().zip(matches.iter()) {
+        // matches are ordered by size, so the first is the longest
+        let longest = matches.first().map_or(0, |m| m.size);
+
+        let row = [
+            field(&sub_name(pair.a, &opts.sub_mode)),
+            field(&sub_name(pair.b, &opts.sub_mode)),
+            pair.matches.len().to_string(),
+            pair.a_percent.to_string(),
+            pair.b_percent.to_string(),
+            pair.percentile.to_string(),
+            longest.to_string(),
+            num_fingerprints(pair.a)?.to_string(),
+            num_fingerprints(pair.b)?.to_string()
+        ];
+
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    Ok(csv)
+}
+
+/// Count the distinct fingerprint hashes across all documents of a
+/// submission (the same quantity its match percent is relative to)
+fn num_fingerprints(sub: &Sub) -> Result<usize> {
+    let mut hashes = HashSet::new();
+
+    for doc in sub.documents.iter() {
+        match doc {
+            Doc::Processed(_, fps) => hashes.extend(fps.iter().map(|fp| fp.hash)),
+            Doc::Unprocessed(path) => return Err(MossError::UnprocessedDoc(path.to_path_buf())),
+        }
+    }
+
+    Ok(hashes.len())
+}
+
+/// Quote a field if it contains a delimiter, quote, or newline,
+/// doubling any quotes within it
+fn field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        String::from(s)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::cli::SubFileMode;
+    use crate::fingerprint::Fingerprint;
+    use crate::phase_iii;
+
+    #[test]
+    fn test_field() {
+        assert_eq!(field("sub1/"), "sub1/");
+        assert_eq!(field("a,b"), "\"a,b\"");
+        assert_eq!(field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_pairs_csv() {
+        let a = Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![
+                Doc::Processed(PathBuf::from("subs/sub1.arr"), vec![
+                    Fingerprint { hash: 28, lines: (4, 5), cols: (1, 1) },
+                    Fingerprint { hash: 17, lines: (6, 7), cols: (1, 1) },
+                    Fingerprint { hash: 12, lines: (9, 9), cols: (1, 1) },
+                    Fingerprint { hash: 40, lines: (10, 10), cols: (1, 1) }
+                ])
+            ]
+        };
+        let b = Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![
+                Doc::Processed(PathBuf::from("subs/sub,2.arr"), vec![
+                    Fingerprint { hash: 12, lines: (1, 2), cols: (1, 1) },
+                    Fingerprint { hash: 28, lines: (10, 11), cols: (1, 1) },
+                    Fingerprint { hash: 17, lines: (11, 13), cols: (1, 1) }
+                ])
+            ]
+        };
+        let matches: HashSet<i64> = [12, 17, 28].iter().cloned().collect();
+
+        let sp = SubPair {
+            a: &a,
+            a_percent: 0.75,
+            b: &b,
+            b_percent: 1.0,
+            matches,
+            percentile: 1.0,
+            doc_pairs: vec![]
+        };
+
+        let opts = OptArgs { sub_mode: SubFileMode::Single, ..OptArgs::default() };
+
+        let matches = phase_iii::analyze_pair(&sp).unwrap();
+        assert_eq!(pairs_csv(&[sp], &[matches], &opts).unwrap(),
+            "sub_a,sub_b,shared_fingerprints,a_percent,b_percent,percentile,\
+            longest_match,a_fingerprints,b_fingerprints\n\
+            sub1.arr,\"sub,2.arr\",3,0.75,1,1,2,4,3\n");
+    }
+}
@@ -0,0 +1,228 @@
+//! db.rs: Persistent database of fingerprinted submissions, so that
+//! submissions from past terms can be compared against without
+//! refingerprinting them
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use crate::{Sub, Doc};
+use crate::cli::SubFileMode;
+use crate::error::{MossError, Result};
+use crate::fingerprint;
+use crate::normalize;
+use crate::phase_i::FpParams;
+
+// version of the database format: bump whenever its structure changes
+const FORMAT_VERSION: u32 = 2;
+
+/// A Database stores fingerprinted (Processed) submissions along with
+/// the parameters they were fingerprinted with. Phase I's map from hashes
+/// to submissions is rebuilt from the stored fingerprints when analyzing
+/// against the database.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Database {
+    format_version: u32,        // version of the structure of this database
+    normalizer_version: u32,    // normalize::VERSION when fingerprints were made
+    fingerprint_version: u32,   // fingerprint::VERSION when fingerprints were made
+    pub params: FpParams,       // params of stored fingerprints
+    pub sub_mode: SubFileMode,  // whether stored subs are files or dirs
+    pub subs: Vec<Sub>          // stored submissions, with absolute paths
+}
+
+impl Database {
+    /// An empty database for submissions fingerprinted with the given params
+    pub fn new(params: FpParams, sub_mode: SubFileMode) -> Self {
+        Database {
+            format_version: FORMAT_VERSION,
+            normalizer_version: normalize::VERSION,
+            fingerprint_version: fingerprint::VERSION,
+            params,
+            sub_mode,
+            subs: Vec::new()
+        }
+    }
+
+    /// Read a database from a file, ensuring it was made by a compatible
+    /// version of the normalizer/fingerprinter
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(|e| {
+            MossError::Io(format!("failed to open fingerprint database {}", path.display()), e)
+        })?;
+
+        let db: Database = serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+            MossError::json(format!("failed to read fingerprint database {}", path.display()), e)
+        })?;
+
+        if db.format_version != FORMAT_VERSION || db.normalizer_version != normalize::VERSION
+            || db.fingerprint_version != fingerprint::VERSION {
+            return Err(MossError::Database(path.to_path_buf(),
+                String::from("created by an incompatible version of pyret-moss (rebuild it with `index`)")));
+        }
+
+        Ok(db)
+    }
+
+    /// Write this database to a file. The database is written to a temporary
+    /// file first, so an interrupted write leaves the old database intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        let write_err = |e| MossError::Io(format!("failed to write fingerprint database {}",
+            path.display()), e);
+
+        let file = File::create(&tmp_path).map_err(write_err)?;
+        serde_json::to_writer(BufWriter::new(file), self)
+            .map_err(|e| MossError::json(format!("failed to write fingerprint database {}", path.display()), e))?;
+
+        fs::rename(&tmp_path, path).map_err(write_err)
+    }
+
+    /// Ensure the stored fingerprints can be compared against
+    /// fingerprints made with the given params
+    pub fn check_params(&self, path: &Path, params: &FpParams, sub_mode: SubFileMode) -> Result<()> {
+        let (k, t) = (params.k, params.t);
+
+        if (self.params.k, self.params.t, self.sub_mode) != (k, t, sub_mode) {
+            return Err(MossError::Database(path.to_path_buf(), format!(
+                "built with k = {}, t = {} ({:?} mode), but analysis uses k = {}, t = {} ({:?} mode)",
+                self.params.k, self.params.t, self.sub_mode, k, t, sub_mode)));
+        }
+
+        if self.params.kgrams != params.kgrams {
+            return Err(MossError::Database(path.to_path_buf(), format!(
+                "built with {:?} k-grams, but analysis uses {:?} k-grams",
+                self.params.kgrams, params.kgrams)));
+        }
+
+        if self.params.hash_width != params.hash_width {
+            return Err(MossError::Database(path.to_path_buf(), format!(
+                "built with {:?} hashes, but analysis uses {:?} hashes",
+                self.params.hash_width, params.hash_width)));
+        }
+
+        if self.params.norm != params.norm {
+            return Err(MossError::Database(path.to_path_buf(), format!(
+                "built with normalization ({}), but analysis uses normalization ({})",
+                self.params.norm, params.norm)));
+        }
+
+        Ok(())
+    }
+
+    /// The identities (see sub_id) of all stored submissions
+    pub fn stored_ids(&self) -> Result<HashSet<PathBuf>> {
+        self.subs.iter().map(sub_id).collect()
+    }
+}
+
+/// Identify a submission by the canonical path of its dir (multi-file)
+/// or its only document (single-file), so that the same submission is
+/// recognized regardless of the working directory. Fails if a single-file
+/// submission has no documents to identify it by.
+pub fn sub_id(sub: &Sub) -> Result<PathBuf> {
+    let path = match (&sub.dir_name, sub.documents.first()) {
+        (Some(dir), _) => dir,
+        (None, Some(Doc::Unprocessed(p))) | (None, Some(Doc::Processed(p, _))) => p,
+        (None, None) => return Err(MossError::EmptySub),
+    };
+
+    Ok(absolute(path))
+}
+
+/// Make all of a submission's paths absolute, so that its
+/// documents can be located from any working directory
+pub fn make_absolute(sub: &mut Sub) {
+    if let Some(dir) = &sub.dir_name {
+        sub.dir_name = Some(absolute(dir));
+    }
+
+    for doc in sub.documents.iter_mut() {
+        match doc {
+            Doc::Unprocessed(p) | Doc::Processed(p, _) => *p = absolute(p),
+        }
+    }
+}
+
+// canonicalize a path, if it exists
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fingerprint::{Fingerprint, HashWidth, KgramMode};
+    use crate::normalize::{NormOptions, IdentifierMode};
+
+    #[test]
+    fn test_save_load() {
+        let path = std::env::temp_dir()
+            .join(format!("pyret-moss-db-{}.json", std::process::id()));
+
+        let mut db = Database::new(FpParams::new(5, 15), SubFileMode::Single);
+        db.subs.push(Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![
+                Doc::Processed(PathBuf::from("/subs/sub1.arr"), vec![
+                    Fingerprint { hash: 28, lines: (4, 5), cols: (1, 1) },
+                    Fingerprint { hash: 17, lines: (6, 7), cols: (1, 1) }
+                ])
+            ]
+        });
+
+        db.save(&path).unwrap();
+        assert_eq!(Database::load(&path).unwrap(), db);
+        assert_eq!(db.stored_ids().unwrap(), [PathBuf::from("/subs/sub1.arr")].iter().cloned().collect());
+
+        // databases from other versions are rejected
+        db.format_version += 1;
+        db.save(&path).unwrap();
+        assert!(matches!(Database::load(&path), Err(MossError::Database(_, _))));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sub_id() {
+        let dir = Sub { dir_name: Some(PathBuf::from("/subs/sub1")), archived: false, documents: vec![] };
+        assert_eq!(sub_id(&dir).unwrap(), PathBuf::from("/subs/sub1"));
+
+        // a single-file submission without documents can't be identified
+        let empty = Sub { dir_name: None, archived: false, documents: vec![] };
+        assert!(matches!(sub_id(&empty), Err(MossError::EmptySub)));
+    }
+
+    #[test]
+    fn test_check_params() {
+        let db = Database::new(FpParams::new(5, 15), SubFileMode::Multi);
+        let path = Path::new("subs.db");
+
+        assert!(db.check_params(path, &FpParams::new(5, 15), SubFileMode::Multi).is_ok());
+        assert!(db.check_params(path, &FpParams::new(6, 15), SubFileMode::Multi).is_err());
+        assert!(db.check_params(path, &FpParams::new(5, 16), SubFileMode::Multi).is_err());
+
+        let e = db.check_params(path, &FpParams::new(5, 15), SubFileMode::Single).unwrap_err();
+        assert_eq!(e.to_string(), "fingerprint database `subs.db`: built with k = 5, t = 15 \
+            (Multi mode), but analysis uses k = 5, t = 15 (Single mode)");
+
+        // normalizations must match too
+        let renamed = NormOptions { identifiers: IdentifierMode::Renamed, ..NormOptions::default() };
+        let e = db.check_params(path, &FpParams { norm: renamed, ..FpParams::new(5, 15) },
+            SubFileMode::Multi).unwrap_err();
+        assert_eq!(e.to_string(), "fingerprint database `subs.db`: built with normalization \
+            (default), but analysis uses normalization (renamed identifiers)");
+
+        let e = db.check_params(path, &FpParams { kgrams: KgramMode::Tokens, ..FpParams::new(5, 15) },
+            SubFileMode::Multi).unwrap_err();
+        assert_eq!(e.to_string(), "fingerprint database `subs.db`: built with Chars k-grams, \
+            but analysis uses Tokens k-grams");
+
+        let e = db.check_params(path, &FpParams { hash_width: HashWidth::Bits64, ..FpParams::new(5, 15) },
+            SubFileMode::Multi).unwrap_err();
+        assert_eq!(e.to_string(), "fingerprint database `subs.db`: built with Bits31 hashes, \
+            but analysis uses Bits64 hashes");
+    }
+}
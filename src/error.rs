@@ -1,21 +1,103 @@
-/// error.rs: Graceful error handling
-
-/// print an error message to stderr & exit, without panicking
-#[macro_export]
-macro_rules! err {
-    // no args
-    () => {
-        eprintln!("An error occurred.");
-        std::process::exit(1);
-    };
-    // print error message
-    ($mes:expr) => {
-        eprintln!("Error: {}", $mes);
-        std::process::exit(1);
-    };
-    // print formatted error message
-    ($fmt:expr, $($args:tt)*) => {
-        eprintln!("Error: {}", std::format_args!($fmt, $($args)*));
-        std::process::exit(1);
-    };
+//! error.rs: Graceful error handling
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use crate::cli::SubFileMode;
+
+/// MossError enumerates the ways in which an analysis can fail.
+/// Errors are propagated up to the caller (ultimately main), which
+/// decides how to report them.
+#[derive(Debug)]
+pub enum MossError {
+    Usage(String),                      // malformed command-line arguments
+    InvalidParams(String),              // invalid analysis parameters (k, t, thresholds)
+    Io(String, io::Error),              // failed I/O, with a description of what was attempted
+    Json(String, serde_json::Error),    // malformed or unserializable JSON, with a description
+    NotADir(PathBuf),                   // submission directory is not a directory
+    EmptySubDir(PathBuf, SubFileMode),  // submission directory contains no submissions
+    NoIgnoreFiles(PathBuf),             // ignore directory contains no .arr files
+    UnprocessedDoc(PathBuf),            // document should have been fingerprinted already
+    EmptySub,                           // submission has neither a dir nor any documents
+    Database(PathBuf, String)           // fingerprint database is incompatible with this analysis
+}
+
+/// Result type for fallible operations in the pipeline
+pub type Result<T> = std::result::Result<T, MossError>;
+
+impl fmt::Display for MossError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MossError::Usage(mes) => write!(f, "{}", mes),
+            MossError::InvalidParams(mes) => write!(f, "{}", mes),
+            MossError::Io(mes, e) => write!(f, "{}: {}", mes, e),
+            MossError::Json(mes, e) => write!(f, "{}: {}", mes, e),
+            MossError::NotADir(p) => {
+                write!(f, "submission directory `{}` is not a dir", p.display())
+            },
+            MossError::EmptySubDir(p, SubFileMode::Single) => {
+                write!(f, "submission directory `{}` contains no .arr files (omit -s for multi-file mode)",
+                    p.display())
+            },
+            MossError::EmptySubDir(p, SubFileMode::Multi) => {
+                write!(f, "submission directory `{}` contains no subdirectories (use -s for single-file mode)",
+                    p.display())
+            },
+            MossError::NoIgnoreFiles(p) => write!(f, "no .arr files to ignore in `{}`", p.display()),
+            MossError::UnprocessedDoc(p) => write!(f, "an unprocessed document was found: {}", p.display()),
+            MossError::EmptySub => write!(f, "a submission with no documents was found"),
+            MossError::Database(p, mes) => write!(f, "fingerprint database `{}`: {}", p.display(), mes),
+        }
+    }
+}
+
+impl std::error::Error for MossError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MossError::Io(_, e) => Some(e),
+            MossError::Json(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl MossError {
+    /// Describe a failure to read or write JSON: as I/O if the underlying
+    /// reader/writer failed, or as malformed/unserializable JSON otherwise
+    pub(crate) fn json(mes: String, e: serde_json::Error) -> Self {
+        if e.is_io() {
+            MossError::Io(mes, e.into())
+        } else {
+            MossError::Json(mes, e)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let e = MossError::EmptySubDir(PathBuf::from("subs/"), SubFileMode::Single);
+        assert_eq!(e.to_string(),
+            "submission directory `subs/` contains no .arr files (omit -s for multi-file mode)");
+
+        let e = MossError::Io(String::from("failed to analyze file a.arr"),
+            io::Error::new(io::ErrorKind::NotFound, "not found"));
+        assert_eq!(e.to_string(), "failed to analyze file a.arr: not found");
+    }
+
+    #[test]
+    fn test_json() {
+        let parse_err = serde_json::from_str::<Vec<i64>>("[1, 2").unwrap_err();
+        let e = MossError::json(String::from("failed to read hashes.json"), parse_err);
+        assert!(matches!(e, MossError::Json(_, _)));
+        assert!(e.to_string().starts_with("failed to read hashes.json: EOF"));
+
+        let io_err = serde_json::Error::io(io::Error::new(io::ErrorKind::WriteZero, "disk full"));
+        let e = MossError::json(String::from("failed to write hashes.json"), io_err);
+        assert!(matches!(e, MossError::Io(_, _)));
+    }
 }
@@ -1,4 +1,4 @@
-/// file_io.rs: File I/O
+//! file_io.rs: File I/O
 
 use std::fs;
 use std::collections::HashSet;
@@ -6,6 +6,7 @@ use std::path::{Path, PathBuf};
 use std::io;
 use crate::{Sub, Doc};
 use crate::cli::SubFileMode;
+use crate::error::{MossError, Result};
 
 /// Construct a vector of PathBufs to all files in a given
 /// directory that pass the given predicate
@@ -26,39 +27,33 @@ fn paths_in_dir<F>(dir: &Path, keep: F) -> io::Result<Vec<PathBuf>>
 }
 
 /// Gets paths to all dirs in a given directory
-fn dirs_in_dir(dir: &Path) -> Vec<PathBuf> {
-    match paths_in_dir(dir, |p| p.is_dir()) {
-        Ok(paths) => paths,
-        Err(e) => {
-            err!("failed to read dirs in `{}`: {}", dir.display(), e);
-        },
-    }
+fn dirs_in_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    paths_in_dir(dir, |p| p.is_dir()).map_err(|e| {
+        MossError::Io(format!("failed to read dirs in `{}`", dir.display()), e)
+    })
 }
 
 /// Gets paths to all .arr files in a given directory
-pub fn arr_files_in_dir(dir: &Path) -> Vec<PathBuf> {
+pub fn arr_files_in_dir(dir: &Path) -> Result<Vec<PathBuf>> {
     let is_arr = |p: &PathBuf| {
         match p.extension() {
             Some(ext) => ext == "arr",
             None => false,
         }
     };
-    match paths_in_dir(dir, is_arr) {
-        Ok(paths) => paths,
-        Err(e) => {
-            err!("failed to read .arr files in `{}`: {}", dir.display(), e);
-        },
-    }
+    paths_in_dir(dir, is_arr).map_err(|e| {
+        MossError::Io(format!("failed to read .arr files in `{}`", dir.display()), e)
+    })
 }
 
 /// Build a vector of submissions by traversing the given directory
 /// in a manner specified by the sub_mode
 pub fn construct_subs(sub_dir: &Path, sub_mode: &SubFileMode,
-    ignore_files: &HashSet<String>, verbose: bool) -> Vec<Sub> {
+    ignore_files: &HashSet<String>, verbose: bool) -> Result<Vec<Sub>> {
     let mut subs = Vec::new();
 
     if !sub_dir.is_dir() {  // validate submission directory
-        err!("submission directory `{}` is not a dir", sub_dir.display());
+        return Err(MossError::NotADir(sub_dir.to_path_buf()));
     }
 
     if verbose {
@@ -76,10 +71,10 @@ pub fn construct_subs(sub_dir: &Path, sub_mode: &SubFileMode,
     match sub_mode {
         // treat submissions as individual .arr files within the sub_dir
         SubFileMode::Single => {
-            let sub_files = arr_files_in_dir(sub_dir);
+            let sub_files = arr_files_in_dir(sub_dir)?;
 
-            if sub_files.len() == 0 {
-                err!("submission directory `{}` contains no .arr files (omit -s for multi-file mode)", sub_dir.display());
+            if sub_files.is_empty() {
+                return Err(MossError::EmptySubDir(sub_dir.to_path_buf(), *sub_mode));
             }
 
             // for each submission (.arr file)
@@ -90,16 +85,17 @@ pub fn construct_subs(sub_dir: &Path, sub_mode: &SubFileMode,
 
                 subs.push(Sub {
                     dir_name: None,
+                    archived: false,
                     documents: vec![doc]
                 });
             }
         },
         // treat submissions as dirs of .arr files within sub_dir
         SubFileMode::Multi => {
-            let sub_dirs = dirs_in_dir(sub_dir);
+            let sub_dirs = dirs_in_dir(sub_dir)?;
 
-            if sub_dirs.len() == 0 {
-                err!("submission directory `{}` contains no subdirectories (use -s for single-file mode)", sub_dir.display());
+            if sub_dirs.is_empty() {
+                return Err(MossError::EmptySubDir(sub_dir.to_path_buf(), *sub_mode));
             }
 
             // for each submission (subdirectory)
@@ -107,7 +103,7 @@ pub fn construct_subs(sub_dir: &Path, sub_mode: &SubFileMode,
                 if verbose { println!("\tcreating submission {}", sub.display()); }
 
                 // read files for this submission
-                let files = arr_files_in_dir(sub.as_path());
+                let files = arr_files_in_dir(sub.as_path())?;
                 let mut docs = Vec::new();
 
                 // add an unprocessed document for each file in the submission
@@ -124,17 +120,19 @@ pub fn construct_subs(sub_dir: &Path, sub_mode: &SubFileMode,
 
                 subs.push(Sub {
                     dir_name: Some(sub.to_path_buf()),
+                    archived: false,
                     documents: docs
                 });
             }
         },
     };
 
-    subs    // return constructed submissions
+    Ok(subs)    // return constructed submissions
 }
 
 
 #[cfg(test)]
+#[allow(clippy::needless_borrow, clippy::manual_map)]
 mod tests {
     use super::*;
 
@@ -209,7 +207,7 @@ mod tests {
     fn test_dirs_in_dir() {
         let dir = "./test-dirs/test/read-dir-contents/";
 
-        let mut out = dirs_in_dir(Path::new(&dir));
+        let mut out = dirs_in_dir(Path::new(&dir)).unwrap();
         let mut exp_paths = vec![
             mk_pathb(dir, "dir"),
             mk_pathb(dir, "dir2")
@@ -225,7 +223,7 @@ mod tests {
     fn test_arr_files_in_dir() {
         let dir = "./test-dirs/test/read-dir-contents/";
 
-        let mut out = arr_files_in_dir(Path::new(&dir));
+        let mut out = arr_files_in_dir(Path::new(&dir)).unwrap();
         let mut exp_paths = vec![
             mk_pathb(dir, "pyret-file.arr"),
             mk_pathb(dir, "second-pyret.arr")
@@ -246,6 +244,7 @@ mod tests {
                     Some(name) => Some(PathBuf::from(name)),
                     None => None,
                 },
+                archived: false,
                 documents: docs.iter().map(|s| {
                     Doc::Unprocessed(PathBuf::from(s))
                 }).collect()
@@ -255,7 +254,7 @@ mod tests {
         // single-file subs
         {
             let sub_dir = Path::new("./test-dirs/test/single-file");
-            let mut out = construct_subs(sub_dir, &SubFileMode::Single, &HashSet::new(), false);
+            let mut out = construct_subs(sub_dir, &SubFileMode::Single, &HashSet::new(), false).unwrap();
             let mut exp_subs = vec![
                 mk_sub(None, vec![
                     "./test-dirs/test/single-file/sub1.arr"
@@ -271,7 +270,7 @@ mod tests {
         // multi-file subs
         {
             let sub_dir = Path::new("./test-dirs/test/multi-file");
-            let mut out = construct_subs(sub_dir, &SubFileMode::Multi, &HashSet::new(), false);
+            let mut out = construct_subs(sub_dir, &SubFileMode::Multi, &HashSet::new(), false).unwrap();
             let mut exp_subs = vec![
                 mk_sub(Some("./test-dirs/test/multi-file/sub1"), vec![
                     "./test-dirs/test/multi-file/sub1/common.arr",
@@ -297,7 +296,7 @@ mod tests {
             let mut ignore_files = HashSet::new();
             ignore_files.insert(String::from("common.arr"));
 
-            let mut out = construct_subs(sub_dir, &SubFileMode::Multi, &ignore_files, false);
+            let mut out = construct_subs(sub_dir, &SubFileMode::Multi, &ignore_files, false).unwrap();
             let mut exp_subs = vec![
                 mk_sub(Some("./test-dirs/test/multi-file/sub1"), vec![
                     "./test-dirs/test/multi-file/sub1/main.arr"
@@ -314,4 +313,22 @@ mod tests {
             assert_eq!(out, exp_subs);
         }
     }
+
+    #[test]
+    fn test_construct_subs_errors() {
+        // submission directory is a file
+        {
+            let sub_dir = Path::new("./test-dirs/test/single-file/sub1.arr");
+            let out = construct_subs(sub_dir, &SubFileMode::Single, &HashSet::new(), false);
+
+            assert!(matches!(out, Err(MossError::NotADir(_))));
+        }
+        // single-file mode in a dir containing only subdirectories
+        {
+            let sub_dir = Path::new("./test-dirs/test/multi-file");
+            let out = construct_subs(sub_dir, &SubFileMode::Single, &HashSet::new(), false);
+
+            assert!(matches!(out, Err(MossError::EmptySubDir(_, SubFileMode::Single))));
+        }
+    }
 }
@@ -1,52 +1,147 @@
-/// fingerprint.rs: Document fingerprinting using robust winnowing
+//! fingerprint.rs: Document fingerprinting using robust winnowing
 
+use std::collections::VecDeque;
+use serde::{Serialize, Deserialize};
 use crate::normalize::NormText;
+use crate::error::{MossError, Result};
+
+/// version of the fingerprinting algorithm: bump whenever the fingerprints
+/// produced for a given normalized text change (invalidates cached fingerprints)
+pub const VERSION: u32 = 2;
 
 // the base value used by the hash function, usually the size of the character set
 static BASE: i64 = 256;
 
 // the prime modulus for all hash calculations to be done under, which represents the range of
 // possible hash values (0, PRIME_MODULUS]
-static PRIME_MODULUS: i64 = 2147483647;
+pub(crate) static PRIME_MODULUS: i64 = 2147483647;
+
+// the prime modulus for 64-bit hashes (the Mersenne prime 2^61 - 1)
+pub(crate) static WIDE_PRIME_MODULUS: i64 = 2305843009213693951;
+
+// the base value used when hashing sequences of token symbols (see fingerprint_tokens)
+static TOKEN_BASE: i64 = 1000003;
+
+// separates the tokens of a token k-gram's text (see kgram_texts)
+const TOKEN_SEPARATOR: char = '\u{0}';
+
+/// KgramMode determines what the k-grams of a document are made of:
+///  1) Chars uses k consecutive characters of the normalized text
+///  2) Tokens uses k consecutive normalized tokens, each hashed to a single
+///     symbol, so the noise/guarantee thresholds count tokens, not characters
+///  3) Ast uses subtrees of the program's canonicalized syntax tree with at
+///     least k tokens, rather than k-grams (see ast::fingerprint)
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum KgramMode {
+    Chars,
+    Tokens,
+    Ast
+}
+
+/// HashWidth determines the range of fingerprint hash values:
+///  1) Bits31 hashes modulo 2^31 - 1 (default)
+///  2) Bits64 hashes modulo 2^61 - 1, making it far less likely that
+///     unrelated k-grams share a hash in large corpora
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize, Default)]
+pub enum HashWidth {
+    #[default]
+    Bits31,
+    Bits64
+}
+
+impl HashWidth {
+    /// the prime modulus under which hashes of this width are calculated
+    pub(crate) fn modulus(self) -> i64 {
+        match self {
+            HashWidth::Bits31 => PRIME_MODULUS,
+            HashWidth::Bits64 => WIDE_PRIME_MODULUS,
+        }
+    }
+}
 
 /// A Fingerprint contains a hash of a k-gram within a document,
-/// and the range of line numbers to which that k-gram corresponds, inclusive
-#[derive(Debug, Hash, Eq, PartialEq, Copy, Clone, PartialOrd, Ord)]
+/// the range of line numbers to which that k-gram corresponds, inclusive,
+/// and the columns at which it begins (on its first line) and ends
+/// (on its last line), inclusive
+#[derive(Debug, Hash, Eq, PartialEq, Copy, Clone, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Fingerprint {
     pub hash: i64,
-    pub lines: (i32, i32)
+    pub lines: (i32, i32),
+    pub cols: (i32, i32)
 }
 
 /// computes the Fingerprints of a normalized document using robust winnowing
 /// input k represents the noise threshold; matches shorter than it are not considered
 /// input t represents the the min substring length at which matches are guaranteed to be caught
-pub fn fingerprint(nt: NormText, k: i32, t: i32) -> Vec<Fingerprint> {
+/// input width determines the range of hash values
+pub fn fingerprint(nt: NormText, k: i32, t: i32, width: HashWidth) -> Result<Vec<Fingerprint>> {
+    let selected = winnow_chars(&nt, k, t, width)?;
+    Ok(selected.into_iter().map(|(fp, _)| fp).collect())
+}
+
+/// computes the Fingerprints of a normalized document using robust winnowing
+/// over k-grams of normalized tokens (rather than characters); each token is
+/// hashed to a single symbol, and k & t are measured in tokens
+pub fn fingerprint_tokens(nt: NormText, k: i32, t: i32, width: HashWidth) -> Result<Vec<Fingerprint>> {
+    let selected = winnow_tokens(&nt, k, t, width)?;
+    Ok(selected.into_iter().map(|(fp, _)| fp).collect())
+}
+
+/// computes the hash of each fingerprint of a normalized document (as fingerprint or
+/// fingerprint_tokens would, according to kgrams), paired with the text of the k-gram
+/// it was made from. Two k-grams' texts are equal exactly when the k-grams are, so the
+/// texts can be compared to tell whether a shared hash is a real match or a collision.
+/// (Texts are lowercased, as hashes are case insensitive.)
+pub fn kgram_texts(nt: &NormText, k: i32, t: i32, kgrams: KgramMode, width: HashWidth)
+    -> Result<Vec<(i64, String)>> {
+    let k_size = k as usize;
+
+    match kgrams {
+        KgramMode::Chars => {
+            // byte offset of each char, so k-grams (indexed by char) can be sliced out
+            let offsets: Vec<usize> = nt.value.char_indices().map(|(i, _)| i)
+                .chain(std::iter::once(nt.value.len()))
+                .collect();
+
+            Ok(winnow_chars(nt, k, t, width)?.into_iter()
+                .map(|(fp, i)| (fp.hash, nt.value[offsets[i]..offsets[i + k_size]].to_lowercase()))
+                .collect())
+        },
+        KgramMode::Tokens => {
+            let spans = &nt.token_spans;
+
+            Ok(winnow_tokens(nt, k, t, width)?.into_iter()
+                .map(|(fp, i)| {
+                    let tokens: Vec<&str> = spans[i..i + k_size].iter()
+                        .map(|&(start, end)| &nt.value[start as usize..end as usize])
+                        .collect();
+                    (fp.hash, tokens.join(&TOKEN_SEPARATOR.to_string()).to_lowercase())
+                })
+                .collect())
+        },
+        KgramMode::Ast => Err(MossError::InvalidParams(
+            String::from("structural fingerprints are not made of k-grams (see ast::subtree_texts)"))),
+    }
+}
+
+/// selects fingerprints from the k-grams of characters of a normalized document,
+/// pairing each with the index of its k-gram's first char
+fn winnow_chars(nt: &NormText, k: i32, t: i32, width: HashWidth) -> Result<Vec<(Fingerprint, usize)>> {
+    if t < k {
+        return Err(MossError::InvalidParams(String::from("`t` may not be less than `k`.")));
+    }
+
     // the text field of the input NormText
     let doc: &String = &nt.value;
     let len: i32 = doc.chars().count() as i32;
 
-    // the output Vec<Fingerprint>, to be populated if possible
-    let mut fingerprints: Vec<Fingerprint> = Vec::new();
+    // the output Vec<(Fingerprint, usize)>, to be populated if possible
+    let mut fingerprints: Vec<(Fingerprint, usize)> = Vec::new();
 
     // only attempt to fingerprint if the normalized string is greater than the noise threshold
     if len > k {
-        if t < k {
-            err!("`t` may not be less than `k`.");
-        }
-
-        // construct k-grams, a Vec<str>
-        let mut kgrams: Vec<&str> = Vec::new();
-        let mut start: usize = 0;
-        let mut end: usize = k as usize;
-
-        while end <= len as usize {
-            let next_kgram = &doc[start..end];
-            kgrams.push(next_kgram);
-            start += 1; end += 1;
-        }
-
-        // rolling hash each k-gram, constructing a vector of i32s
-        let hashed_kgrams = rolling_hash(kgrams);
+        // rolling hash each k-gram of characters, streaming over the text once
+        let hashed_kgrams = rolling_hash(doc, k as usize, width.modulus());
 
         // the window size for winnowing
         let w: i32 = t - k + 1;
@@ -59,14 +154,61 @@ pub fn fingerprint(nt: NormText, k: i32, t: i32) -> Vec<Fingerprint> {
             let hash: i64 = tuple.0;
             let start_line: i32 = nt.line_number(tuple.1 as i32);
             let end_line: i32 = nt.line_number(tuple.1 as i32 + k - 1);
+            let start_col: i32 = nt.start_col(tuple.1 as i32);
+            let end_col: i32 = nt.end_col(tuple.1 as i32 + k - 1);
             let fingerprint: Fingerprint = Fingerprint {
-                hash: hash,
-                lines: (start_line, end_line)
+                hash,
+                lines: (start_line, end_line),
+                cols: (start_col, end_col)
             };
-            fingerprints.push(fingerprint);
+            fingerprints.push((fingerprint, tuple.1));
         }
     }
-    fingerprints
+    Ok(fingerprints)
+}
+
+/// selects fingerprints from the k-grams of tokens of a normalized document,
+/// pairing each with the index of its k-gram's first token
+fn winnow_tokens(nt: &NormText, k: i32, t: i32, width: HashWidth) -> Result<Vec<(Fingerprint, usize)>> {
+    if t < k {
+        return Err(MossError::InvalidParams(String::from("`t` may not be less than `k`.")));
+    }
+
+    let spans = &nt.token_spans;
+    let len: i32 = spans.len() as i32;
+
+    // the output Vec<(Fingerprint, usize)>, to be populated if possible
+    let mut fingerprints: Vec<(Fingerprint, usize)> = Vec::new();
+
+    // only attempt to fingerprint if there are more tokens than the noise threshold
+    if len > k {
+        // hash each token's text to a symbol
+        let symbols: Vec<i64> = spans.iter()
+            .map(|&(start, end)| hash(&nt.value[start as usize..end as usize], width.modulus()))
+            .collect();
+
+        // rolling hash each k-gram of symbols
+        let k_size = k as usize;
+        let mut roller = RollingHash::new(TOKEN_BASE, k_size, width.modulus());
+        let hashed_kgrams: Vec<i64> = symbols.iter()
+            .filter_map(|&symbol| roller.push(symbol))
+            .collect();
+
+        // checks windows of hashes of length w, uses robust winnowing to select fingerprints
+        let w: i32 = t - k + 1;
+        let fingerprint_tuples: Vec<(i64, usize)> = robust_winnow(hashed_kgrams, w as usize);
+
+        // a k-gram's lines run from its first token's first char to its last token's last char
+        for &(hash, i) in fingerprint_tuples.iter() {
+            let (first, last) = (spans[i].0, spans[i + k_size - 1].1 - 1);
+            fingerprints.push((Fingerprint {
+                hash,
+                lines: (nt.line_number(first), nt.line_number(last)),
+                cols: (nt.start_col(first), nt.end_col(last))
+            }, i));
+        }
+    }
+    Ok(fingerprints)
 }
 
 /// The robust winnowing algorithm; takes in a Vec<i64> of hashes and returns the fingerprints,
@@ -74,143 +216,124 @@ pub fn fingerprint(nt: NormText, k: i32, t: i32) -> Vec<Fingerprint> {
 /// Algorithm: In each window select the minimum hash value. If possible break ties by selecting
 /// the same hash as the window one position to the left. If not, select the rightmost minimal hash.
 /// Save all selected hashes as the fingerprints of the document.
+///
+/// Rather than rescanning each window, a monotonic deque of candidate minima is maintained:
+/// its hashes strictly increase from front to back, so its front is always the rightmost
+/// minimal hash of the current window, and each hash is pushed & popped at most once.
 fn robust_winnow(hashed_kgrams: Vec<i64>, window_size: usize) -> Vec<(i64, usize)> {
-
-    let max_window_index: usize = hashed_kgrams.len() as usize;
-    let mut window_start: usize = 0;
-    let mut window_end: usize = window_size;
+    // if the window size is greater than the number of hashed kgrams, the
+    // whole input is a single window
+    let w: usize = window_size.min(hashed_kgrams.len()).max(1);
 
     // the output Vec, to be populated
     let mut fingerprint_tuples: Vec<(i64, usize)> = Vec::new();
     let mut prev_fingerprint: Option<(i64, usize)> = None;
 
-    // if the window size is greater than the number of hashed kgrams, return rightmost min kgram
-    if window_end > max_window_index {
-        // find the minimum hash(es) in hashed_kgrams
-        let mut cur_mins: Vec<(i64, usize)> = Vec::new();
-
-        for (i, hash) in hashed_kgrams.iter().enumerate() {
-            let potential_fingerprint: (i64, usize) = (*hash, i);
-            if cur_mins.is_empty() {
-                cur_mins.push(potential_fingerprint);
-            } else if hash < &cur_mins[0].0 {
-                cur_mins = vec![potential_fingerprint]
-            } else if hash == &cur_mins[0].0 {
-                cur_mins.push(potential_fingerprint);
-            }
+    // candidate minima of the current window, as (hash, index)
+    let mut candidates: VecDeque<(i64, usize)> = VecDeque::new();
+
+    for (i, &hash) in hashed_kgrams.iter().enumerate() {
+        // a hash can never again be the rightmost min once a hash <= it appears to its right
+        while candidates.back().is_some_and(|&(h, _)| h >= hash) {
+            candidates.pop_back();
         }
+        candidates.push_back((hash, i));
 
-        //add the rightmost minimum hash tuple to the output Vec
-        fingerprint_tuples.push(*cur_mins.last().unwrap());
+        // wait until the first window is full
+        if i + 1 < w { continue; }
 
-    } else {
-        // check all windows of size w in the hashed kgrams
-        while window_end <= max_window_index {
-            let window = &hashed_kgrams[window_start..window_end];
-
-            // find the minimum hash(es) of the current window
-            let mut cur_mins: Vec<(i64, usize)> = Vec::new();
-            let mut cur_window_index_counter: usize = 0;
-
-            for hash in window.iter() {
-                let index: usize = window_start + cur_window_index_counter;
-                let potential_fingerprint: (i64, usize) = (*hash, index);
-
-                if cur_mins.is_empty() {
-                    cur_mins.push(potential_fingerprint);
-                } else if hash < &cur_mins[0].0 {
-                    cur_mins = vec![potential_fingerprint]
-                } else if hash == &cur_mins[0].0 {
-                    cur_mins.push(potential_fingerprint);
-                }
-                cur_window_index_counter += 1;
-            }
-
-            // compare cur_mins and prev_fingerprint to see whether to update fingerprint_tuples
-            match prev_fingerprint {
-                // if no fingerprints have been identified Pyret
-                None => {
-                    let next_fingerprint_tuple: (i64, usize) = *cur_mins.last().unwrap();
-                    fingerprint_tuples.push(next_fingerprint_tuple);
-                    prev_fingerprint = Some(next_fingerprint_tuple);
-                }
-                Some(fp) => {
-                    // if none of cur_mins is the previous fingerprint, select the rightmost hash
-                    if !cur_mins.contains(&fp) {
-                        let next_fingerprint_tuple: (i64, usize) = *cur_mins.last().unwrap();
-                        fingerprint_tuples.push(next_fingerprint_tuple);
-                        prev_fingerprint = Some(next_fingerprint_tuple);
-                    }
-                }
+        // discard candidates which have slid out of the window
+        let window_start: usize = i + 1 - w;
+        while candidates.front().is_some_and(|&(_, idx)| idx < window_start) {
+            candidates.pop_front();
+        }
+        let rightmost_min: (i64, usize) = *candidates.front().unwrap();
+
+        // keep the previous fingerprint if it is still a minimal hash in this window,
+        // otherwise select the rightmost minimal hash
+        match prev_fingerprint {
+            Some((h, idx)) if h == rightmost_min.0 && idx >= window_start => (),
+            _ => {
+                fingerprint_tuples.push(rightmost_min);
+                prev_fingerprint = Some(rightmost_min);
             }
-            window_start += 1; window_end += 1;
         }
     }
     fingerprint_tuples
 }
 
-/// A rolling hash function for a vector of strings;
-/// assumes each "kgram" &str in the input Vec is of length k
-fn rolling_hash(mut kgrams: Vec<&str>) -> Vec<i64> {
-    let len = kgrams.len();
-
-    // the output vector of hashes, which is returned when the entirety of the input is hashed
-    let mut output: Vec<i64> = Vec::new();
-    let mut prev_first_char: Option<char> = None;
-
-    while output.len() < len {
-        let cur_str: &str = kgrams[0];
-        kgrams = kgrams[1..].to_vec();
-
-        let cur_first_char: char =
-            cur_str.chars().next().unwrap().to_lowercase().next().unwrap();
-        let cur_last_char: char =
-            cur_str.chars().last().unwrap().to_lowercase().next().unwrap();
-
-        match prev_first_char {
-            // if the current iteration is the first string being hashed
-            None => {
-                // appends the hash of the first string to the output vector
-                output.push(hash(cur_str));
-            }
-            // if at least one string has already been hashed
-            Some(c) => {
-                // calculates the hash of the current string using the previous hash
-                let str_len = cur_str.chars().count() as i64;
-                let prev_hash: &i64 = output.last().unwrap();
-
-                // calculates the portion represented by the previous hash's first character
-                let prev_first_char_component =
-                    (c as i64 * mod_exp(BASE, str_len - 1, PRIME_MODULUS)) % PRIME_MODULUS;
-
-                // calculates the current hash by removing the prev first character's component,
-                // multiplying the remaining k-1 chars by BASE, and adding the new character;
-                // PRIME_MODULUS is added to prev_hash to prevent underflow
-                let hash: i64 = ((prev_hash + PRIME_MODULUS - prev_first_char_component) * BASE
-                + cur_last_char as i64) % PRIME_MODULUS;
-
-                // appends the hash of the current string to the output vector
-                output.push(hash);
-            }
+/// A RollingHash streams over a sequence of symbols, hashing the window of the last
+/// k symbols in constant time per symbol: the first symbol's component is removed,
+/// and the remainder shifted over to make room for the next symbol
+struct RollingHash {
+    base: i64,
+    k: usize,
+    modulus: i64,
+    first_component_base: i64,      // base^(k - 1), the weight of the window's first symbol
+    window: VecDeque<i64>,          // the last (up to) k symbols
+    cur_hash: i64
+}
+
+impl RollingHash {
+    fn new(base: i64, k: usize, modulus: i64) -> RollingHash {
+        RollingHash {
+            base,
+            k,
+            modulus,
+            first_component_base: mod_exp(base, k as i64 - 1, modulus),
+            window: VecDeque::with_capacity(k + 1),
+            cur_hash: 0
+        }
+    }
+
+    /// add the next symbol, returning the hash of the k symbols ending
+    /// with it, if at least k symbols have been seen
+    fn push(&mut self, symbol: i64) -> Option<i64> {
+        let m = self.modulus;
+
+        if self.window.len() == self.k {
+            let first = self.window.pop_front().unwrap();
+            let first_component = mul_mod(first, self.first_component_base, m);
+
+            // the modulus is added to cur_hash to prevent underflow
+            self.cur_hash = (self.cur_hash + m - first_component) % m;
         }
-        //sets prev_first_char equal to the first character of the current string
-        prev_first_char = Some(cur_first_char);
+
+        self.window.push_back(symbol);
+        self.cur_hash = (mul_mod(self.cur_hash, self.base, m) + symbol) % m;
+
+        if self.window.len() == self.k { Some(self.cur_hash) } else { None }
     }
-    output
+}
+
+/// A rolling hash function over the k-grams of characters in a text; the output holds
+/// the hash of the k-gram starting at each index, and each matches hash() of that k-gram
+fn rolling_hash(text: &str, k: usize, modulus: i64) -> Vec<i64> {
+    if k == 0 { return Vec::new(); }
+
+    let mut roller = RollingHash::new(BASE, k, modulus);
+    text.chars()
+        .filter_map(|c| roller.push(lowered(c)))
+        .collect()
+}
+
+/// the symbol value of a character for hashing: its lowercase code point,
+/// so that hashes are case insensitive
+fn lowered(c: char) -> i64 {
+    c.to_lowercase().next().unwrap() as i64
 }
 
 /// a simple, non-rolling hash function for strings
 /// only matches the output of rolling_hash() when the input str is of length k
-fn hash(str: &str) -> i64 {
-    let len = str.chars().count() as usize;
+fn hash(str: &str, modulus: i64) -> i64 {
+    let len = str.chars().count();
     let mut hash_val: i64 = 0;
 
     // for each character c in the string, the value c multiplied by the modular exponent
     // of (length of string - index of character - 1) is added to hash_val
     for (i, c) in str.chars().enumerate() {
-        let char_lowered: i64 = c.to_lowercase().next().unwrap() as i64;
-        hash_val = (hash_val + (char_lowered * mod_exp(BASE, (len - i) as i64 - 1, PRIME_MODULUS))
-        % PRIME_MODULUS) % PRIME_MODULUS;
+        hash_val = (hash_val + mul_mod(lowered(c), mod_exp(BASE, (len - i) as i64 - 1, modulus), modulus))
+        % modulus;
     }
 
     hash_val
@@ -223,23 +346,33 @@ fn mod_exp(mut base: i64, mut exponent: i64, modulus: i64) -> i64 {
         0
     } else {
         let mut output = 1;
-        base = base % modulus;
+        base %= modulus;
         while exponent > 0 {
             if exponent % 2 == 1 {
-                output = output * base % modulus;
+                output = mul_mod(output, base, modulus);
             }
-            exponent = exponent >> 1;
-            base = base * base % modulus
+            exponent >>= 1;
+            base = mul_mod(base, base, modulus)
         }
         output
     }
 }
 
+/// modular multiplication, which can't overflow even when the modulus exceeds 2^32
+fn mul_mod(a: i64, b: i64, modulus: i64) -> i64 {
+    (a as i128 * b as i128 % modulus as i128) as i64
+}
+
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
     use crate::normalize::normalize;
 
+    // hash & rolling_hash at the default width
+    fn hash(str: &str) -> i64 { super::hash(str, PRIME_MODULUS) }
+    fn rolling_hash(text: &str, k: usize) -> Vec<i64> { super::rolling_hash(text, k, PRIME_MODULUS) }
+
     #[test]
     // tests that mod_exp performs modular exponentiation as expected
     fn mod_exp_test() {
@@ -255,10 +388,9 @@ mod tests {
     #[test]
     // tests hash() and rolling_hash() for empty inputs
     fn empty_input_hash() {
-        let empty: Vec<&str> = Vec::new();
-
         assert_eq!(hash(""), 0);
-        assert_eq!(rolling_hash(empty), vec![]);
+        assert_eq!(rolling_hash("", 3), Vec::<i64>::new());
+        assert_eq!(rolling_hash("ab", 3), Vec::<i64>::new(), "text shorter than k");
     }
 
     #[test]
@@ -269,27 +401,23 @@ mod tests {
         assert_eq!(hash("M"), 109);
         assert_eq!(hash("m"), 109);
         assert_eq!(hash("é"), 233);
-        assert_eq!(rolling_hash(vec!["!", "M", "m", "é"]), vec![33, 109, 109, 233]);
+        assert_eq!(rolling_hash("!Mmé", 1), vec![33, 109, 109, 233]);
     }
 
     #[test]
     // tests that hash() and rolling_hash() are case insensitive
     fn case_insensitive() {
-        let lowercase_kgrams: Vec<&str> = vec!["this is a te", "his is a tes", "is is a test"];
-        let mixcase_kgrams: Vec<&str> = vec!["tHis IS a TE", "his is a tes", "IS IS A TEST"];
-        let uppercase_kgrams: Vec<&str> = vec!["THIS IS A TE", "HIS IS A TES", "IS IS A TEST"];
-
         assert_eq!(hash("a"), hash("A"));
         assert_eq!(hash("hello"), hash("HeLLo"));
-        assert_eq!(rolling_hash(lowercase_kgrams), rolling_hash(mixcase_kgrams.to_owned()));
-        assert_eq!(rolling_hash(mixcase_kgrams.to_owned()), rolling_hash(uppercase_kgrams));
+        assert_eq!(rolling_hash("this is a test", 12), rolling_hash("tHis IS a TEST", 12));
+        assert_eq!(rolling_hash("tHis IS a TEST", 12), rolling_hash("THIS IS A TEST", 12));
     }
 
     #[test]
     // tests that hash() and rolling_hash() consider the order of characters
     fn rearranged_characters() {
-        let arrange_1: Vec<i64> = rolling_hash(vec!["aba", "bab", "abb"]);
-        let arrange_2: Vec<i64> = rolling_hash(vec!["aab", "abb", "bba"]);
+        let arrange_1: Vec<i64> = rolling_hash("ababb", 3);
+        let arrange_2: Vec<i64> = rolling_hash("aabba", 3);
 
         assert_ne!(hash("abc"), hash("bac"));
         assert_ne!(hash("this is a test"), hash("a test this is"));
@@ -301,13 +429,11 @@ mod tests {
     #[test]
     // tests that distinct calls to identical inputs to hash() and rolling_hash() yield identical outputs
     fn equal_input_equal_output() {
-        let three_kgrams: Vec<&str> = vec!["abcd", "bcde", "cdef"];
-        let three_kgrams_hashed: Vec<i64> = rolling_hash(three_kgrams);
-        let three_kgrams_copy: Vec<&str> = vec!["abcd", "bcde", "cdef"];
+        let three_kgrams_hashed: Vec<i64> = rolling_hash("abcdef", 4);
 
         assert_eq!(hash("abcdefg"), hash("abcdefg"));
         assert_eq!(hash("@ 3 df KM34,;."), hash("@ 3 df KM34,;."));
-        assert_eq!(rolling_hash(three_kgrams_copy), three_kgrams_hashed);
+        assert_eq!(rolling_hash("abcdef", 4), three_kgrams_hashed);
     }
 
     #[test]
@@ -315,8 +441,7 @@ mod tests {
     fn no_overflow() {
         let long_input: i64 = hash("The quick brown fox jumps over the lazy dog");
         let high_code_points: i64 = hash("ó { |~ û ÿ ©÷ ó { |~ û ÿ ©÷ ó { |~ û ÿ ©÷");
-        let large_kgrams: Vec<i64> = rolling_hash(vec!["each string is pretty long in this V",
-        "ach string is pretty long in this Ve", "ch string is pretty long in this Vec"]);
+        let large_kgrams: Vec<i64> = rolling_hash("each string is pretty long in this Vec", 36);
 
         assert_eq!(long_input < PRIME_MODULUS, true);
         assert_eq!(high_code_points < PRIME_MODULUS, true);
@@ -329,28 +454,25 @@ mod tests {
     // tests that rolling_hash() does not allow underflow when the first character component is
     // greater than the rest
     fn no_underflow() {
-        let underflow_test: Vec<i64> = rolling_hash(vec!["ÿ!0", "!0!"]);
+        let underflow_test: Vec<i64> = rolling_hash("ÿ!0!", 3);
 
         assert_eq!(underflow_test[1] > 0, true);
     }
 
     #[test]
-    // tests the output Vec of the rolling_hash() is the same length as the input Vec
+    // tests the output Vec of the rolling_hash() has one hash per k-gram of the input
     fn rolling_hash_output_length() {
-        assert_eq!(rolling_hash(vec!["ab", "bc", "cd", "de", "ef"]).len(), 5);
+        assert_eq!(rolling_hash("abcdef", 2).len(), 5);
     }
 
     #[test]
     // tests that the rolling hash produces the same hash values as the naive hash
     fn hash_vs_rolling_hash() {
-        let one_kgram: Vec<&str> = vec!["there is one kgram!"];
-        let three_kgrams: Vec<&str> = vec!["abcde", "bcdef", "cdefg"];
-        let special_chars: Vec<&str> = vec!["$ 1:", " 1:,", "1:,a", ":,aA"];
         let spec_indiv_hashes = vec![hash("$ 1:"), hash(" 1:,"), hash("1:,a"), hash(":,aA")];
 
-        assert_eq!(rolling_hash(one_kgram), vec![hash("there is one kgram!")]);
-        assert_eq!(rolling_hash(three_kgrams), vec![hash("abcde"), hash("bcdef"), hash("cdefg")]);
-        assert_eq!(rolling_hash(special_chars), spec_indiv_hashes);
+        assert_eq!(rolling_hash("there is one kgram!", 19), vec![hash("there is one kgram!")]);
+        assert_eq!(rolling_hash("abcdefg", 5), vec![hash("abcde"), hash("bcdef"), hash("cdefg")]);
+        assert_eq!(rolling_hash("$ 1:,aA", 4), spec_indiv_hashes);
     }
 
     #[test]
@@ -431,16 +553,56 @@ mod tests {
         assert_eq!(robust_winnow(paper_example, 4), expected_output);
     }
 
+    #[test]
+    // tests that robust_winnow() agrees with a direct window-by-window scan on inputs
+    // with many ties, across window sizes
+    fn winnow_matches_window_scan() {
+        // the previous definition of robust winnowing: find all mins of each window
+        fn window_scan(hashes: &[i64], w: usize) -> Vec<(i64, usize)> {
+            let mut out: Vec<(i64, usize)> = Vec::new();
+            for start in 0..=hashes.len().saturating_sub(w.min(hashes.len())) {
+                let window = &hashes[start..(start + w).min(hashes.len())];
+                let min = *window.iter().min().unwrap();
+                let mins: Vec<(i64, usize)> = window.iter().enumerate()
+                    .filter(|&(_, &h)| h == min)
+                    .map(|(i, &h)| (h, start + i))
+                    .collect();
+                if out.last().is_none_or(|fp| !mins.contains(fp)) {
+                    out.push(*mins.last().unwrap());
+                }
+            }
+            out
+        }
+
+        // a small range of pseudo-random values, so ties are frequent
+        let mut state: i64 = 7;
+        let hashes: Vec<i64> = (0..500).map(|_| {
+            state = (state * 1103515245 + 12345) % 2147483648;
+            state % 13
+        }).collect();
+
+        for w in vec![1, 2, 3, 4, 7, 16, 100, 499, 500, 1000] {
+            assert_eq!(robust_winnow(hashes.to_owned(), w), window_scan(&hashes, w), "window {}", w);
+        }
+    }
+
     #[test]
     // tests fingerprint() on cases where it should return an empty output Vec; when the
     // normalized text has fewer than k characters
     fn empty_fingerprint_output() {
         let empty_nt: NormText = normalize("");
-        assert_eq!(fingerprint(empty_nt, 0, 0), vec![], "empty input NormText");
+        assert_eq!(fingerprint(empty_nt, 0, 0, HashWidth::Bits31).unwrap(), vec![], "empty input NormText");
 
         let standard_nt: NormText = normalize("# Shared list definition that everyone gets as
         boilerplate data MyList<T>: | my-empty| my-link(first :: T, rest :: List<T>) end");
-        assert_eq!(fingerprint(standard_nt, 19, 20), vec![], "normalized text fewer than k chars");
+        assert_eq!(fingerprint(standard_nt, 19, 20, HashWidth::Bits31).unwrap(), vec![], "normalized text fewer than k chars");
+    }
+
+    #[test]
+    // tests that fingerprint() rejects a guarantee threshold below the noise threshold
+    fn invalid_thresholds() {
+        let nt: NormText = normalize("fun f(x): x + 1 end");
+        assert!(matches!(fingerprint(nt, 5, 4, HashWidth::Bits31), Err(MossError::InvalidParams(_))));
     }
 
     #[test]
@@ -452,13 +614,13 @@ mod tests {
             \tend\n\
         end");
 
-        let print1: Fingerprint = Fingerprint { hash: 678832678, lines: (1, 1) };
-        let print2: Fingerprint = Fingerprint { hash: 691692376, lines: (1, 2) };
-        let print3: Fingerprint = Fingerprint { hash: 891957668, lines: (2, 3) };
-        let print4: Fingerprint = Fingerprint { hash: 707866831, lines: (3, 4) };
+        let print1: Fingerprint = Fingerprint { hash: 678832678, lines: (1, 1), cols: (5, 9) };
+        let print2: Fingerprint = Fingerprint { hash: 691692376, lines: (1, 2), cols: (7, 3) };
+        let print3: Fingerprint = Fingerprint { hash: 891957668, lines: (2, 3), cols: (7, 8) };
+        let print4: Fingerprint = Fingerprint { hash: 707866831, lines: (3, 4), cols: (3, 2) };
         let exp_out: Vec<Fingerprint> = vec![print1, print2, print3, print4];
 
-        assert_eq!(fingerprint(multiline_nt, 5, 12), exp_out);
+        assert_eq!(fingerprint(multiline_nt, 5, 12, HashWidth::Bits31).unwrap(), exp_out);
     }
 
     #[test]
@@ -476,9 +638,74 @@ mod tests {
                                             true\n\
                                         end");
 
-        let out1: Vec<Fingerprint> = fingerprint(nt1, 4, 7);
-        let out2: Vec<Fingerprint> = fingerprint(nt2, 4, 7);
+        let out1: Vec<Fingerprint> = fingerprint(nt1, 4, 7, HashWidth::Bits31).unwrap();
+        let out2: Vec<Fingerprint> = fingerprint(nt2, 4, 7, HashWidth::Bits31).unwrap();
 
         assert_eq!(out1[6].hash, out2[5].hash);
     }
+
+    #[test]
+    // tests that fingerprint_tokens() measures k-grams in tokens & preserves line numbers
+    fn token_kgrams() {
+        // tokens: fun v ( v ) : v + 1 end, so eight 3-grams, all selected when w = 1
+        let nt: NormText = normalize("fun f(x):\n x + 1\nend");
+        let fps: Vec<Fingerprint> = fingerprint_tokens(nt, 3, 3, HashWidth::Bits31).unwrap();
+
+        assert_eq!(fps.len(), 8);
+        assert_eq!(fps[0].lines, (1, 1));
+        assert_eq!(fps[5].lines, (1, 2));
+        assert_eq!(fps[6].lines, (2, 2));
+        assert_eq!(fps[7].lines, (2, 3));
+
+        // a long keyword weighs as much as any other token
+        let long: Vec<Fingerprint> = fingerprint_tokens(normalize("f() raises-other-than 5"), 2, 2, HashWidth::Bits31).unwrap();
+        let short: Vec<Fingerprint> = fingerprint_tokens(normalize("f() is 5"), 2, 2, HashWidth::Bits31).unwrap();
+        assert_eq!(long.len(), short.len());
+
+        // shared token sequences share hashes, regardless of position
+        let out1 = fingerprint_tokens(normalize("x = a * (b + c)"), 3, 3, HashWidth::Bits31).unwrap();
+        let out2 = fingerprint_tokens(normalize("y = 2\nz = a * (b + c)"), 3, 3, HashWidth::Bits31).unwrap();
+        assert_eq!(out1.last().unwrap().hash, out2.last().unwrap().hash);
+        assert_eq!(out2.last().unwrap().lines, (2, 2));
+
+        // too few tokens, or invalid thresholds
+        assert_eq!(fingerprint_tokens(normalize("x = 1"), 3, 5, HashWidth::Bits31).unwrap(), vec![]);
+        assert!(matches!(fingerprint_tokens(normalize("x = 1"), 2, 1, HashWidth::Bits31), Err(MossError::InvalidParams(_))));
+    }
+
+    #[test]
+    // tests that 64-bit hashes roll like 31-bit ones, use the wider range, & select the same k-grams
+    fn wide_hashes() {
+        let text = "each string is pretty long in this Vec";
+        let wide: Vec<i64> = super::rolling_hash(text, 30, WIDE_PRIME_MODULUS);
+        assert_eq!(wide[0], super::hash(&text[..30], WIDE_PRIME_MODULUS));
+        assert_eq!(wide[8], super::hash(&text[8..], WIDE_PRIME_MODULUS));
+        assert!(wide.iter().all(|&h| h > 0 && h < WIDE_PRIME_MODULUS));
+        assert!(wide.iter().any(|&h| h > PRIME_MODULUS));
+
+        let program = "fun f(x):\n  x + 1\nend";
+        let narrow_fps = fingerprint(normalize(program), 6, 6, HashWidth::Bits31).unwrap();
+        let wide_fps = fingerprint(normalize(program), 6, 6, HashWidth::Bits64).unwrap();
+        assert_eq!(narrow_fps.len(), wide_fps.len());
+        assert_eq!(narrow_fps[0].lines, wide_fps[0].lines);
+        assert_ne!(narrow_fps[0].hash, wide_fps[0].hash);
+    }
+
+    #[test]
+    // tests that kgram_texts() pairs each fingerprint's hash with the text of its k-gram
+    fn texts_of_kgrams() {
+        let nt: NormText = normalize("fun f(x): X + 1 end");
+        let fps: Vec<Fingerprint> = fingerprint(normalize("fun f(x): X + 1 end"), 4, 6, HashWidth::Bits31).unwrap();
+        let texts = kgram_texts(&nt, 4, 6, KgramMode::Chars, HashWidth::Bits31).unwrap();
+
+        assert_eq!(texts.iter().map(|(h, _)| *h).collect::<Vec<i64>>(),
+            fps.iter().map(|fp| fp.hash).collect::<Vec<i64>>());
+        assert!(texts.iter().all(|(h, text)| text.chars().count() == 4 && *h == hash(text)));
+
+        // token k-grams keep their tokens apart
+        let texts = kgram_texts(&normalize("x = a * b"), 3, 3, KgramMode::Tokens, HashWidth::Bits31).unwrap();
+        assert_eq!(texts[0].1, "v\u{0}=\u{0}v");
+
+        assert!(kgram_texts(&nt, 4, 6, KgramMode::Ast, HashWidth::Bits31).is_err());
+    }
 }
@@ -0,0 +1,515 @@
+//! html.rs: Render findings of overlap as a set of HTML pages, with each
+//! pair's submissions shown side by side & matching regions highlighted
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::{Sub, Doc, Overlaps};
+use crate::cli::{OptArgs, SubFileMode};
+use crate::cluster::Fragment;
+use crate::error::{MossError, Result};
+use crate::phase_ii::SubPair;
+use crate::phase_iii::{Entry, Match};
+use crate::results::{self, sub_name};
+
+// which side of a pair a submission is on, used to prefix anchor ids
+const SIDE_A: &str = "a";
+const SIDE_B: &str = "b";
+
+/// Write an index page of all given submission pairs to the output
+/// directory, along with one page for each pair (pair-N.html) showing its matches
+pub fn render_html(out_dir: &Path, sub_dir: &Path, overlaps: &Overlaps, matches: &[Vec<Match>],
+    opts: &OptArgs) -> Result<()> {
+    let sub_pairs = &overlaps.pairs;
+
+    if opts.verbose { println!("\nWriting HTML report to {}", out_dir.display()); }
+
+    fs::create_dir_all(out_dir).map_err(|e| {
+        MossError::Io(format!("failed to create HTML output directory {}", out_dir.display()), e)
+    })?;
+
+    // write a page for each pair
+    for (i, (pair, matches)) in sub_pairs.iter().zip(matches.iter()).enumerate() {
+        let page = pair_page(i + 1, pair, matches, opts)?;
+
+        write_page(&out_dir.join(pair_page_name(i + 1)), &page)?;
+    }
+
+    // write the index, linking to each pair's page
+    let index = index_page(sub_dir, overlaps, opts);
+    write_page(&out_dir.join("index.html"), &index)
+}
+
+/// Write the contents of a page to the given path
+fn write_page(path: &Path, contents: &str) -> Result<()> {
+    fs::write(path, contents).map_err(|e| {
+        MossError::Io(format!("failed to write {}", path.display()), e)
+    })
+}
+
+/// The filename of the page for the nth pair
+fn pair_page_name(n: usize) -> String {
+    format!("pair-{}.html", n)
+}
+
+/// Generate the index page, which lists all clusters (if clustering), groups
+/// (if grouping) & all pairs in order
+fn index_page(sub_dir: &Path, overlaps: &Overlaps, opts: &OptArgs) -> String {
+    let (sub_pairs, total_pairs, suppressed) = (&overlaps.pairs, overlaps.total_pairs, overlaps.suppressed);
+    let mut body = String::new();
+
+    body.push_str(&format!("<h1>Submissions Directory: {}</h1>\n",
+        escape(&sub_dir.display().to_string())));
+
+    if suppressed > 0 {
+        body.push_str(&format!("<p>Ignored {} fingerprints shared by too many submissions</p>\n",
+            suppressed));
+    }
+
+    if sub_pairs.is_empty() {
+        body.push_str("<p>Aye, no overlap was found!</p>\n");
+        return page("Pyret Moss results", &body, 0);
+    }
+
+    if opts.match_threshold > 0.0 {
+        body.push_str(&format!("<p>Showing pairs at least {:.2}% of max matches: {} kept / {} total{}</p>\n",
+            opts.match_threshold * 100.0, sub_pairs.len(), total_pairs,
+            if opts.lsh.is_some() { " candidates (see --lsh)" } else { "" }));
+    } else {
+        body.push_str(&format!("<p>Showing all submission pairs ({} total)</p>\n", sub_pairs.len()));
+    }
+
+    if let Some(similarity) = opts.cluster_similarity {
+        body.push_str(&format!("<h2>Clusters of submissions linked by pairs at least {:.2}% similar ({})</h2>\n",
+            similarity * 100.0, overlaps.clusters.len()));
+
+        for (i, cluster) in overlaps.clusters.iter().enumerate() {
+            let names: Vec<String> = cluster.members.iter()
+                .map(|sub| escape(&sub_name(sub, &opts.sub_mode)))
+                .collect();
+            let pair_links: Vec<String> = cluster.pairs.iter()
+                .map(|&p| format!("<a href=\"{}\">{}</a>", pair_page_name(p + 1), p + 1))
+                .collect();
+
+            body.push_str(&format!("<h3>Cluster {}: {}</h3>\n", i + 1, names.join(", ")));
+            body.push_str(&format!("<p>Pairs: {}</p>\n", pair_links.join(", ")));
+
+            body.push_str(&fragment_table(&cluster.members, &cluster.fragments, &names));
+        }
+    }
+
+    if let Some(size) = opts.group_size {
+        body.push_str(&format!("<h2>Groups of {} or more submissions sharing fragments ({})</h2>\n",
+            size, overlaps.groups.len()));
+
+        for (i, group) in overlaps.groups.iter().enumerate() {
+            let names: Vec<String> = group.members.iter()
+                .map(|sub| escape(&sub_name(sub, &opts.sub_mode)))
+                .collect();
+
+            body.push_str(&format!("<h3>Group {}: {}</h3>\n", i + 1, names.join(", ")));
+            body.push_str(&fragment_table(&group.members, &group.fragments, &names));
+        }
+    }
+
+    if opts.cluster_similarity.is_some() || opts.group_size.is_some() {
+        body.push_str("<h2>Pairs</h2>\n");
+    }
+
+    body.push_str("<table class=\"pairs\">\n");
+    body.push_str("<tr><th>Pair</th><th>Submission A</th><th>Submission B</th>\
+        <th>Matches</th><th>% of max</th></tr>\n");
+
+    for (i, pair) in sub_pairs.iter().enumerate() {
+        let href = pair_page_name(i + 1);
+
+        body.push_str(&format!(
+            "<tr><td><a href=\"{}\">{}</a></td><td><a href=\"{}\">{} ({:.2}%)</a></td>\
+            <td><a href=\"{}\">{} ({:.2}%)</a></td><td>{}</td><td>{:.2}%</td></tr>\n",
+            href, i + 1,
+            href, escape(&sub_name(pair.a, &opts.sub_mode)), pair.a_percent * 100.0,
+            href, escape(&sub_name(pair.b, &opts.sub_mode)), pair.b_percent * 100.0,
+            pair.matches.len(), pair.percentile * 100.0));
+    }
+
+    body.push_str("</table>\n");
+
+    page("Pyret Moss results", &body, 0)
+}
+
+/// Generate a table of fragments shared by the given submissions (whose names are
+/// already escaped), with a column locating them in each submission
+fn fragment_table(members: &[&Sub], fragments: &[Fragment], names: &[String]) -> String {
+    let mut table = String::from("<table class=\"fragments\">\n<tr><th>(size)</th>");
+    for name in names.iter() { table.push_str(&format!("<th>{}</th>", name)); }
+    table.push_str("</tr>\n");
+
+    for fragment in fragments.iter() {
+        table.push_str(&format!("<tr><td>{}</td>", fragment.size));
+        for (sub, entries) in members.iter().zip(fragment.entries.iter()) {
+            let cells: Vec<String> = sorted_entries(entries.iter()).into_iter()
+                .map(|e| format!("{} {}", escape(&file_name(sub, e.doc_idx)), line_range(e.lines)))
+                .collect();
+            table.push_str(&format!("<td>{}</td>", cells.join("<br>")));
+        }
+        table.push_str("</tr>\n");
+    }
+
+    table.push_str("</table>\n");
+    table
+}
+
+/// Generate the page for the nth pair: a table of its matches, followed
+/// by the source of both submissions side by side
+fn pair_page(n: usize, pair: &SubPair, matches: &[Match], opts: &OptArgs) -> Result<String> {
+    let a_name = sub_name(pair.a, &opts.sub_mode);
+    let b_name = sub_name(pair.b, &opts.sub_mode);
+
+    // order each match's entries so that anchor ids are deterministic
+    let entries: Vec<(Vec<&Entry>, Vec<&Entry>)> = matches.iter()
+        .map(|m| (sorted_entries(m.a_entries.iter()), sorted_entries(m.b_entries.iter())))
+        .collect();
+
+    let mut body = String::new();
+
+    body.push_str("<p><a href=\"index.html\">&larr; All pairs</a></p>\n");
+    body.push_str(&format!("<h1>Pair {}: {} and {}</h1>\n", n, escape(&a_name), escape(&b_name)));
+    body.push_str(&format!("<p>{} matches ({:.2}% of max)</p>\n",
+        pair.matches.len(), pair.percentile * 100.0));
+
+    // table of matches, linking to the regions of each
+    body.push_str("<table class=\"matches\">\n");
+    body.push_str(&format!("<tr><th>(size)</th><th>{} ({:.2}%)</th><th>{} ({:.2}%)</th></tr>\n",
+        escape(&a_name), pair.a_percent * 100.0, escape(&b_name), pair.b_percent * 100.0));
+
+    for (i, m) in matches.iter().enumerate() {
+        let (a_entries, b_entries) = &entries[i];
+
+        body.push_str(&format!("<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            m.size,
+            entry_links(SIDE_A, i, a_entries, pair.a),
+            entry_links(SIDE_B, i, b_entries, pair.b)));
+    }
+
+    body.push_str("</table>\n");
+
+    // overlap between each pair of documents, when submissions have several
+    if opts.sub_mode == SubFileMode::Multi && !pair.doc_pairs.is_empty() {
+        body.push_str("<table class=\"docs\">\n");
+        body.push_str("<tr><th>Document</th><th>Document</th><th>Matches</th></tr>\n");
+
+        for dp in pair.doc_pairs.iter() {
+            body.push_str(&format!("<tr><td>{} ({:.2}%)</td><td>{} ({:.2}%)</td><td>{}</td></tr>\n",
+                escape(&results::doc_name(pair.a, dp.a_doc)), dp.a_percent * 100.0,
+                escape(&results::doc_name(pair.b, dp.b_doc)), dp.b_percent * 100.0, dp.matches));
+        }
+
+        body.push_str("</table>\n");
+    }
+
+    // both submissions' source, side by side
+    let a_entries: Vec<&[&Entry]> = entries.iter().map(|(a, _)| a.as_slice()).collect();
+    let b_entries: Vec<&[&Entry]> = entries.iter().map(|(_, b)| b.as_slice()).collect();
+
+    body.push_str("<div class=\"sides\">\n");
+    body.push_str(&side_source(SIDE_A, SIDE_B, &a_name, pair.a, &a_entries)?);
+    body.push_str(&side_source(SIDE_B, SIDE_A, &b_name, pair.b, &b_entries)?);
+    body.push_str("</div>\n");
+
+    Ok(page(&format!("{} and {}", a_name, b_name), &body, matches.len()))
+}
+
+/// Generate links to each of a match's entries within a submission
+fn entry_links(side: &str, match_idx: usize, entries: &[&Entry], sub: &Sub) -> String {
+    let links: Vec<String> = entries.iter().enumerate().map(|(j, e)| {
+        format!("<a class=\"m{}\" href=\"#{}\">{} {}</a>",
+            match_idx, anchor(side, match_idx, j), escape(&file_name(sub, e.doc_idx)), line_range(e.lines))
+    }).collect();
+
+    links.join("<br>")
+}
+
+/// Generate the source listing for all documents in a submission, where
+/// text belonging to a match is colored by that match & links to the first
+/// region of the same match in the other submission.
+/// entries[i] holds the entries of match i within this submission.
+fn side_source(side: &str, other: &str, name: &str, sub: &Sub, entries: &[&[&Entry]]) -> Result<String> {
+    let mut out = String::new();
+
+    out.push_str("<div class=\"side\">\n");
+    out.push_str(&format!("<h2>{}</h2>\n", escape(name)));
+
+    for (doc_idx, doc) in sub.documents.iter().enumerate() {
+        let path = doc_path(doc);
+        let source = read_source(path)?;
+        let lines: Vec<&str> = source.lines().collect();
+
+        // the match coloring each char (if any), & anchors that start on each line
+        let colors = char_colors(doc_idx, &lines, entries);
+        let mut anchors: Vec<Vec<String>> = vec![Vec::new(); lines.len()];
+
+        for (i, match_entries) in entries.iter().enumerate() {
+            for (j, e) in match_entries.iter().enumerate() {
+                let start = e.lines.0 as usize;
+                if e.doc_idx == doc_idx && start >= 1 && start <= lines.len() {
+                    anchors[start - 1].push(anchor(side, i, j));
+                }
+            }
+        }
+
+        out.push_str(&format!("<h3>{}</h3>\n<pre>", escape(&file_name(sub, doc_idx))));
+
+        for (l, line) in lines.iter().enumerate() {
+            for id in anchors[l].iter() {
+                out.push_str(&format!("<span id=\"{}\"></span>", id));
+            }
+
+            out.push_str(&format!("<span class=\"ln\">{:>4}</span> ", l + 1));
+
+            // ------- Write each run of identically colored chars -------
+            let chars: Vec<char> = line.chars().collect();
+            let mut run_start = 0;
+
+            for c in 1..=chars.len() {
+                if c < chars.len() && colors[l][c] == colors[l][run_start] { continue; }
+
+                let text: String = chars[run_start..c].iter().collect();
+
+                match colors[l][run_start] {
+                    Some(i) => out.push_str(&format!("<a class=\"m{}\" href=\"#{}\">{}</a>",
+                        i, anchor(other, i, 0), escape(&text))),
+                    None => out.push_str(&escape(&text)),
+                };
+
+                run_start = c;
+            }
+
+            out.push('\n');
+        }
+
+        out.push_str("</pre>\n");
+    }
+
+    out.push_str("</div>\n");
+
+    Ok(out)
+}
+
+/// Determine which match (if any) each char of each line of a document should
+/// be colored by. Entries begin at their starting column on their first line,
+/// & end at their ending column on their last line.
+/// When a char falls within several matches, the earliest (largest) one is used.
+fn char_colors(doc_idx: usize, lines: &[&str], entries: &[&[&Entry]]) -> Vec<Vec<Option<usize>>> {
+    let mut colors: Vec<Vec<Option<usize>>> = lines.iter()
+        .map(|line| vec![None; line.chars().count()])
+        .collect();
+
+    // visit matches from smallest to largest, so larger matches overwrite smaller
+    for (i, match_entries) in entries.iter().enumerate().rev() {
+        for e in match_entries.iter().filter(|e| e.doc_idx == doc_idx) {
+            let (start, end) = e.lines;
+
+            for line in start.max(1)..=end.min(lines.len() as i32) {
+                let line_colors = &mut colors[line as usize - 1];
+
+                // columns are 1-based & inclusive
+                let first = if line == start { e.cols.0.max(1) as usize } else { 1 };
+                let last = if line == end { e.cols.1.max(0) as usize } else { line_colors.len() };
+
+                for col in first..=last.min(line_colors.len()) {
+                    line_colors[col - 1] = Some(i);
+                }
+            }
+        }
+    }
+
+    colors
+}
+
+/// Read a document's original source, replacing any invalid UTF-8
+fn read_source(path: &Path) -> Result<String> {
+    let bytes = fs::read(path).map_err(|e| {
+        MossError::Io(format!("failed to read {}", path.display()), e)
+    })?;
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Wrap a page body in the common page structure & styles, with
+/// a distinct highlight color for each of the first num_colors matches
+fn page(title: &str, body: &str, num_colors: usize) -> String {
+    let mut styles = String::from(STYLE);
+
+    for i in 0..num_colors {
+        styles.push_str(&format!(".m{} {{ background-color: {}; }}\n", i, match_color(i)));
+    }
+
+    format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+        <style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(title), styles, body)
+}
+
+// styles common to all pages
+const STYLE: &str = "body { font-family: sans-serif; margin: 1em 2em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+a { color: inherit; }
+pre a { text-decoration: none; }
+.sides { display: flex; gap: 1em; }
+.side { flex: 1; min-width: 0; height: 80vh; overflow: auto; border: 1px solid #ccc; padding: 0 0.5em; }
+.ln { color: #888; user-select: none; }
+";
+
+/// A distinct, consistent color for the ith match, spreading hues evenly
+fn match_color(i: usize) -> String {
+    let hue = (i * 137) % 360;    // golden angle, so consecutive matches contrast
+    format!("hsl({}, 85%, 80%)", hue)
+}
+
+/// The anchor id for the jth entry of the ith match on a side
+fn anchor(side: &str, match_idx: usize, entry_idx: usize) -> String {
+    format!("{}-{}-{}", side, match_idx, entry_idx)
+}
+
+/// Sort entries by document, then line range
+fn sorted_entries<'a, I>(entries: I) -> Vec<&'a Entry>
+    where I: Iterator<Item = &'a Entry> {
+    let mut entries: Vec<&Entry> = entries.collect();
+    entries.sort_by_key(|e| (e.doc_idx, e.lines));
+    entries
+}
+
+/// Describe a line range, e.g. "lines 3-4" or "line 3"
+fn line_range(lines: (i32, i32)) -> String {
+    let (start, end) = lines;
+
+    if end - start > 0 {
+        format!("lines {}-{}", start, end)
+    } else {
+        format!("line {}", start)
+    }
+}
+
+/// The path of a document
+fn doc_path(doc: &Doc) -> &PathBuf {
+    match doc {
+        Doc::Unprocessed(path) | Doc::Processed(path, _) => path,
+    }
+}
+
+/// The filename of the document at the given index in a submission
+fn file_name(sub: &Sub, doc_idx: usize) -> String {
+    let path = doc_path(&sub.documents[doc_idx]);
+    String::from(path.file_name().unwrap().to_str().unwrap())
+}
+
+/// Escape text for inclusion in HTML
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        };
+    }
+
+    escaped
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::cli::SubFileMode;
+    use crate::fingerprint::Fingerprint;
+    use crate::phase_iii;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("fun f<A>(x): x end"), "fun f&lt;A&gt;(x): x end");
+        assert_eq!(escape("\"a\" & 'b'"), "&quot;a&quot; &amp; &#39;b&#39;");
+    }
+
+    #[test]
+    fn test_char_colors() {
+        let large = [
+            &Entry { doc_idx: 0, lines: (2, 3), cols: (2, 1) },
+            &Entry { doc_idx: 1, lines: (1, 1), cols: (1, 2) }
+        ];
+        let small = [
+            &Entry { doc_idx: 0, lines: (3, 4), cols: (1, 2) }
+        ];
+        let entries: Vec<&[&Entry]> = vec![&large, &small];
+
+        // larger match (0) takes precedence where matches overlap
+        assert_eq!(char_colors(0, &["abc", "def", "ghi", "jkl", "mno"], &entries), vec![
+            vec![None, None, None],
+            vec![None, Some(0), Some(0)],
+            vec![Some(0), Some(1), Some(1)],
+            vec![Some(1), Some(1), None],
+            vec![None, None, None]
+        ]);
+        assert_eq!(char_colors(1, &["abc", ""], &entries),
+            vec![vec![Some(0), Some(0), None], vec![]]);
+    }
+
+    #[test]
+    fn test_pair_page() {
+        let a = Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![
+                Doc::Processed(PathBuf::from("test-dirs/test/single-file/sub1.arr"), vec![
+                    Fingerprint { hash: 5421077, lines: (11, 12), cols: (23, 7) },
+                    Fingerprint { hash: 14933625, lines: (17, 18), cols: (27, 28) }
+                ])
+            ]
+        };
+        let b = Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![
+                Doc::Processed(PathBuf::from("test-dirs/test/single-file/sub2.arr"), vec![
+                    Fingerprint { hash: 5421077, lines: (8, 10), cols: (23, 7) },
+                    Fingerprint { hash: 14933625, lines: (13, 14), cols: (27, 28) }
+                ])
+            ]
+        };
+        let matches: HashSet<i64> = [5421077, 14933625].iter().cloned().collect();
+
+        let sp = SubPair {
+            a: &a,
+            a_percent: 1.0,
+            b: &b,
+            b_percent: 1.0,
+            matches,
+            percentile: 1.0,
+            doc_pairs: vec![]
+        };
+
+        let opts = OptArgs { sub_mode: SubFileMode::Single, ..OptArgs::default() };
+        let matches = phase_iii::analyze_pair(&sp).unwrap();
+        let html = pair_page(1, &sp, &matches, &opts).unwrap();
+
+        assert!(html.contains("<h1>Pair 1: sub1.arr and sub2.arr</h1>"));
+        assert!(html.contains("<a class=\"m0\" href=\"#a-0-0\">sub1.arr lines 11-18</a>"));
+        assert!(html.contains("<a class=\"m0\" href=\"#b-0-0\">sub2.arr lines 8-14</a>"));
+
+        // matched text in each submission links to the region in the other,
+        // starting & ending at the matched columns
+        assert!(html.contains("<span id=\"a-0-0\"></span><span class=\"ln\">  11</span>         \
+            | link(f, r) =<a class=\"m0\" href=\"#b-0-0\">&gt; 1 + len-of-list(r)</a>\n"));
+        assert!(html.contains("<span id=\"b-0-0\"></span><span class=\"ln\">   8</span>         \
+            | link(f, r) =<a class=\"m0\" href=\"#a-0-0\">&gt; </a>\n"));
+        assert!(html.contains("<span class=\"ln\">  18</span> <a class=\"m0\" href=\"#b-0-0\">    \
+            len-of-list([list: 0, 0,</a> 0]) is 3\n"));
+
+        // source is escaped
+        assert!(html.contains("fun len-of-list&lt;A&gt;(list-name :: List&lt;A&gt;) -&gt; Number:"));
+    }
+}
@@ -1,13 +1,14 @@
-/// io_redirect.rs: Functionality for switching output between stdout & a file
+//! io_redirect.rs: Functionality for switching output between stdout & a file
 
 use std::path::Path;
 use std::fs::{File, OpenOptions};
 use std::io::Write;
 use gag::Redirect;
+use crate::error::{MossError, Result};
 
 /// Begin redirecting stdout to the indicated file & return
 /// the gag::Redirect if successful
-pub fn initialize_redirect(file: &Path) -> Redirect<File> {
+pub fn initialize_redirect(file: &Path) -> Result<Redirect<File>> {
     // open/create the indicated file for writing
     let log = match
         OpenOptions::new()
@@ -16,44 +17,46 @@ pub fn initialize_redirect(file: &Path) -> Redirect<File> {
             .write(true)
             .open(file) {
         Ok(f) => f,
-        Err(e) => { err!("failed to open file {} for writing: {}", file.display(), e); },
+        Err(e) => {
+            return Err(MossError::Io(format!("failed to open file {} for writing", file.display()), e));
+        },
     };
 
-    Redirect::stdout(log).unwrap()
+    Ok(Redirect::stdout(log).unwrap())
 }
 
 /// Resume redirecting stdout to the indicated file (do NOT truncate)
 /// & update the given redirect to reflect it
-pub fn resume_redirect(redirect: &mut Option<Redirect<File>>, file: &Path) {
+pub fn resume_redirect(redirect: &mut Option<Redirect<File>>, file: &Path) -> Result<()> {
     let log = match
         OpenOptions::new()
             .append(true)   // append on further openings
-            .write(true)
             .open(file) {
         Ok(f) => f,
-        Err(e) => { err!("failed to resume writing to {}: {}", file.display(), e); }
+        Err(e) => {
+            return Err(MossError::Io(format!("failed to resume writing to {}", file.display()), e));
+        },
     };
 
     match redirect {
         Some(_) => panic!("Attempted to redirect while already redirected"),
         None => *redirect = Some(Redirect::stdout(log).unwrap()),
     };
+
+    Ok(())
 }
 
 /// End the given redirect & update its option wrapper to None
 pub fn end_redirect(redirect: &mut Option<Redirect<File>>) {
     match redirect {
-        Some(rd) => {
-            drop(rd);
-            *redirect = None;
-        },
+        Some(_) => *redirect = None,   // dropping the redirect ends it
         None => panic!("Attempted to end redirect while not redirected."),
     };
 }
 
-/// Print a message and enter an infinite loop until confirmation is
-/// received from the user to proceed
-pub fn confirm_continue() {
+/// Print a message and enter an infinite loop until the user either
+/// confirms to proceed (true) or declines (false)
+pub fn confirm_continue() -> bool {
     loop {
         let mut confirm = String::new();
 
@@ -69,17 +72,18 @@ pub fn confirm_continue() {
 
         if confirm == "y" {
             // continue
-            break;
+            return true;
         } else if confirm == "n" {
-            // exit early
+            // stop early
             println!("Exiting!");
-            std::process::exit(0);
+            return false;
         }
     }
 }
 
 
 #[cfg(test)]
+#[allow(clippy::expect_fun_call)]
 mod tests {
 
     // NOTE: These tests involve redirecting print statements & will
@@ -114,7 +118,7 @@ mod tests {
 
         {
             let text = "This text should be in init_redirect.txt";
-            let _r = initialize_redirect(&file);
+            let _r = initialize_redirect(&file).unwrap();
 
             println!("{}", &text);  // print, therefore writing to init_redirect.txt
             flush_stdout();
@@ -123,7 +127,7 @@ mod tests {
         }
         {
             let text = "This content was redirected from stdout into this file";
-            let _r = initialize_redirect(&file);
+            let _r = initialize_redirect(&file).unwrap();
 
             print!("{}", &text);
             std::io::stdout().flush().unwrap();
@@ -144,7 +148,7 @@ mod tests {
         let text = "New text written after resuming.";
         let mut r: Option<Redirect<File>> = None;
 
-        resume_redirect(&mut r, &file); // start redirecting to this file w/o erasing its contents
+        resume_redirect(&mut r, &file).unwrap(); // start redirecting to this file w/o erasing its contents
 
         println!("{}", &text);   // write text to file
         flush_stdout();
@@ -167,7 +171,7 @@ mod tests {
         let during_redir = "This is printed during the redirect.";
         let after_redir = "This is printed AFTER the redirect was ended.";
 
-        let mut r = Some(initialize_redirect(&file));
+        let mut r = Some(initialize_redirect(&file).unwrap());
 
         println!("{}", &during_redir);
         flush_stdout();
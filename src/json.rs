@@ -0,0 +1,342 @@
+//! json.rs: Render findings of overlap as JSON, for consumption by other tools
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use serde::Serialize;
+use crate::{Sub, Doc, Overlaps};
+use crate::cli::OptArgs;
+use crate::error::{MossError, Result};
+use crate::cluster::Fragment;
+use crate::phase_iii::{Entry, Match};
+use crate::results::sub_name;
+
+/// JsonReport is the top-level JSON document describing all rendered pairs.
+/// Percent/percentile values are fractions in [0, 1].
+#[derive(Debug, Serialize, PartialEq)]
+struct JsonReport {
+    submissions_dir: String,
+    match_threshold: f64,
+    total_pairs: usize,
+    suppressed_hashes: usize,
+    clusters: Vec<JsonCluster>,
+    groups: Vec<JsonGroup>,
+    pairs: Vec<JsonPair>
+}
+
+/// JsonCluster describes a cluster of submissions: its members, the pairs
+/// between them, & the fragments shared by all of them
+#[derive(Debug, Serialize, PartialEq)]
+struct JsonCluster {
+    members: Vec<String>,
+    pairs: Vec<JsonClusterPair>,
+    fragments: Vec<JsonFragment>
+}
+
+/// JsonGroup describes a group of submissions: its members & the
+/// fragments only they share
+#[derive(Debug, Serialize, PartialEq)]
+struct JsonGroup {
+    members: Vec<String>,
+    fragments: Vec<JsonFragment>
+}
+
+/// JsonClusterPair describes the scores of a pair within a cluster
+#[derive(Debug, Serialize, PartialEq)]
+struct JsonClusterPair {
+    a: String,
+    b: String,
+    a_percent: f64,
+    b_percent: f64,
+    match_count: usize
+}
+
+/// JsonFragment describes a run of fingerprints shared by every member of a
+/// cluster or group, with its entries in every member
+#[derive(Debug, Serialize, PartialEq)]
+struct JsonFragment {
+    size: usize,
+    entries: Vec<JsonEntry>
+}
+
+/// JsonPair describes a single SubPair along with its matches
+#[derive(Debug, Serialize, PartialEq)]
+struct JsonPair {
+    a: String,
+    b: String,
+    a_percent: f64,
+    b_percent: f64,
+    percentile: f64,
+    match_count: usize,
+    documents: Vec<JsonDocPair>,
+    matches: Vec<JsonMatch>
+}
+
+/// JsonDocPair describes the overlap between a document of each submission in a pair
+#[derive(Debug, Serialize, PartialEq)]
+struct JsonDocPair {
+    a: String,
+    b: String,
+    a_percent: f64,
+    b_percent: f64,
+    match_count: usize
+}
+
+/// JsonMatch describes a single phase III Match
+#[derive(Debug, Serialize, PartialEq)]
+struct JsonMatch {
+    size: usize,
+    a_entries: Vec<JsonEntry>,
+    b_entries: Vec<JsonEntry>
+}
+
+/// JsonEntry describes a section within a particular document: its line
+/// range, & the columns at which it starts & ends on its first & last lines
+#[derive(Debug, Serialize, PartialEq)]
+struct JsonEntry {
+    doc: String,
+    lines: (i32, i32),
+    cols: (i32, i32)
+}
+
+/// Write a JSON document describing the given submission pairs &
+/// their matches to the output file, or stdout if none is given
+pub fn render_json(sub_dir: &Path, overlaps: &Overlaps, matches: &[Vec<Match>], opts: &OptArgs)
+    -> Result<()> {
+    if opts.verbose { println!("\nRendering results as JSON..."); }
+
+    let report = build_report(sub_dir, overlaps, matches, opts);
+
+    let written = match opts.out_file {
+        Some(p) => {
+            if opts.verbose { println!("Writing output to {}", p.display()); }
+
+            let file = File::create(p).map_err(|e| {
+                MossError::Io(format!("failed to open file {} for writing", p.display()), e)
+            })?;
+            serde_json::to_writer_pretty(file, &report)
+        },
+        None => {
+            let written = serde_json::to_writer_pretty(io::stdout(), &report);
+            println!();
+            written
+        },
+    };
+
+    written.map_err(|e| MossError::json(String::from("failed to write JSON report"), e))
+}
+
+/// Construct the report for the given overlaps, given the matches of each pair
+fn build_report(sub_dir: &Path, overlaps: &Overlaps, matches: &[Vec<Match>], opts: &OptArgs) -> JsonReport {
+    let sub_pairs = &overlaps.pairs;
+    let mut pairs = Vec::new();
+
+    for (pair, matches) in sub_pairs.iter().zip(matches.iter()) {
+        let matches = matches.iter()
+            .map(|m| JsonMatch {
+                size: m.size,
+                a_entries: json_entries(m.a_entries.iter(), pair.a),
+                b_entries: json_entries(m.b_entries.iter(), pair.b)
+            })
+            .collect();
+
+        let documents = pair.doc_pairs.iter()
+            .map(|dp| JsonDocPair {
+                a: doc_path(pair.a, dp.a_doc),
+                b: doc_path(pair.b, dp.b_doc),
+                a_percent: dp.a_percent,
+                b_percent: dp.b_percent,
+                match_count: dp.matches
+            })
+            .collect();
+
+        pairs.push(JsonPair {
+            a: sub_name(pair.a, &opts.sub_mode),
+            b: sub_name(pair.b, &opts.sub_mode),
+            a_percent: pair.a_percent,
+            b_percent: pair.b_percent,
+            percentile: pair.percentile,
+            match_count: pair.matches.len(),
+            documents,
+            matches
+        });
+    }
+
+    let clusters = overlaps.clusters.iter()
+        .map(|cluster| JsonCluster {
+            members: cluster.members.iter().map(|sub| sub_name(sub, &opts.sub_mode)).collect(),
+            pairs: cluster.pairs.iter()
+                .map(|&i| {
+                    let pair = &sub_pairs[i];
+                    JsonClusterPair {
+                        a: sub_name(pair.a, &opts.sub_mode),
+                        b: sub_name(pair.b, &opts.sub_mode),
+                        a_percent: pair.a_percent,
+                        b_percent: pair.b_percent,
+                        match_count: pair.matches.len()
+                    }
+                })
+                .collect(),
+            fragments: json_fragments(&cluster.members, &cluster.fragments)
+        })
+        .collect();
+
+    let groups = overlaps.groups.iter()
+        .map(|group| JsonGroup {
+            members: group.members.iter().map(|sub| sub_name(sub, &opts.sub_mode)).collect(),
+            fragments: json_fragments(&group.members, &group.fragments)
+        })
+        .collect();
+
+    JsonReport {
+        submissions_dir: sub_dir.display().to_string(),
+        match_threshold: opts.match_threshold,
+        total_pairs: overlaps.total_pairs,
+        suppressed_hashes: overlaps.suppressed,
+        clusters,
+        groups,
+        pairs
+    }
+}
+
+/// Convert fragments shared by the given submissions to JSON fragments,
+/// with the entries of every submission in turn
+fn json_fragments(members: &[&Sub], fragments: &[Fragment]) -> Vec<JsonFragment> {
+    fragments.iter()
+        .map(|f| JsonFragment {
+            size: f.size,
+            entries: members.iter().zip(f.entries.iter())
+                .flat_map(|(sub, entries)| json_entries(entries.iter(), sub))
+                .collect()
+        })
+        .collect()
+}
+
+/// Convert entries within a submission to JSON entries, ordered
+/// by document & then by line range
+fn json_entries<'a, I>(entries: I, sub: &Sub) -> Vec<JsonEntry>
+    where I: Iterator<Item = &'a Entry> {
+    let mut entries: Vec<&Entry> = entries.collect();
+    entries.sort_by_key(|e| (e.doc_idx, e.lines, e.cols));
+
+    entries.into_iter()
+        .map(|e| JsonEntry { doc: doc_path(sub, e.doc_idx), lines: e.lines, cols: e.cols })
+        .collect()
+}
+
+/// The path of the document at the given index in a submission
+fn doc_path(sub: &Sub, doc_idx: usize) -> String {
+    match &sub.documents[doc_idx] {
+        Doc::Unprocessed(path) | Doc::Processed(path, _) => path.display().to_string(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+    use crate::cli::SubFileMode;
+    use crate::fingerprint::Fingerprint;
+    use fnv::FnvHashMap;
+    use crate::phase_ii::{SubPair, DocPair};
+    use crate::phase_iii;
+
+    #[test]
+    fn test_build_report() {
+        let a = Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![
+                Doc::Processed(PathBuf::from("subs/sub1.arr"), vec![
+                    Fingerprint { hash: 28, lines: (4, 5), cols: (3, 8) },
+                    Fingerprint { hash: 17, lines: (6, 7), cols: (1, 12) },
+                    Fingerprint { hash: 12, lines: (9, 9), cols: (5, 20) }
+                ])
+            ]
+        };
+        let b = Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![
+                Doc::Processed(PathBuf::from("subs/sub2.arr"), vec![
+                    Fingerprint { hash: 12, lines: (1, 2), cols: (2, 4) },
+                    Fingerprint { hash: 28, lines: (10, 11), cols: (7, 9) },
+                    Fingerprint { hash: 17, lines: (11, 13), cols: (1, 6) }
+                ])
+            ]
+        };
+        let matches: HashSet<i64> = [12, 17, 28].iter().cloned().collect();
+
+        let sp = SubPair {
+            a: &a,
+            a_percent: 1.0,
+            b: &b,
+            b_percent: 1.0,
+            matches,
+            percentile: 1.0,
+            doc_pairs: vec![DocPair { a_doc: 0, a_percent: 1.0, b_doc: 0, b_percent: 1.0, matches: 3 }]
+        };
+
+        let opts = OptArgs { sub_mode: SubFileMode::Single, ..OptArgs::default() };
+        let matches = vec![phase_iii::analyze_pair(&sp).unwrap()];
+        let overlaps = Overlaps {
+            hash_to_subs: FnvHashMap::default(),
+            pairs: vec![sp],
+            total_pairs: 1,
+            suppressed: 0,
+            clusters: vec![],
+            groups: vec![]
+        };
+        let report = build_report(Path::new("subs"), &overlaps, &matches, &opts);
+
+        let exp_report = JsonReport {
+            submissions_dir: String::from("subs"),
+            match_threshold: 0.0,
+            total_pairs: 1,
+            suppressed_hashes: 0,
+            clusters: vec![],
+            groups: vec![],
+            pairs: vec![
+                JsonPair {
+                    a: String::from("sub1.arr"),
+                    b: String::from("sub2.arr"),
+                    a_percent: 1.0,
+                    b_percent: 1.0,
+                    percentile: 1.0,
+                    match_count: 3,
+                    documents: vec![
+                        JsonDocPair {
+                            a: String::from("subs/sub1.arr"),
+                            b: String::from("subs/sub2.arr"),
+                            a_percent: 1.0,
+                            b_percent: 1.0,
+                            match_count: 3
+                        }
+                    ],
+                    matches: vec![
+                        // [28, 17]
+                        JsonMatch {
+                            size: 2,
+                            a_entries: vec![JsonEntry { doc: String::from("subs/sub1.arr"), lines: (4, 7), cols: (3, 12) }],
+                            b_entries: vec![JsonEntry { doc: String::from("subs/sub2.arr"), lines: (10, 13), cols: (7, 6) }]
+                        },
+                        // [12]
+                        JsonMatch {
+                            size: 1,
+                            a_entries: vec![JsonEntry { doc: String::from("subs/sub1.arr"), lines: (9, 9), cols: (5, 20) }],
+                            b_entries: vec![JsonEntry { doc: String::from("subs/sub2.arr"), lines: (1, 2), cols: (2, 4) }]
+                        }
+                    ]
+                }
+            ]
+        };
+
+        assert_eq!(report, exp_report);
+
+        // entries serialize with their doc path, line range & columns
+        let json = serde_json::to_string(&report.pairs[0].matches[1].a_entries[0]).unwrap();
+        assert_eq!(json, r#"{"doc":"subs/sub1.arr","lines":[9,9],"cols":[5,20]}"#);
+    }
+}
@@ -0,0 +1,89 @@
+//! Copy-detection for Pyret.
+//!
+//! The pipeline runs in phases, each of which is exposed here so that it
+//! can be driven from other programs (e.g. grading scripts):
+//!
+//!  - `file_io::construct_subs` reads a submissions directory into `Sub`s
+//!  - `phase_i::analyze_subs` normalizes (`normalize::normalize`) and
+//!    fingerprints (`fingerprint::fingerprint`, or `ast::fingerprint` for
+//!    structural fingerprints) every document, mapping each fingerprint
+//!    hash to the submissions that contain it
+//!  - `phase_ii::find_overlaps` groups submissions into `SubPair`s,
+//!    optionally only considering candidate pairs chosen by `lsh`
+//!  - `phase_iii::analyze_pair` finds the `Match`es within a single pair
+//!  - `cluster::find_clusters` groups submissions connected by similar
+//!    pairs into `Cluster`s
+//!
+//! Fingerprinted submissions can be stored in a `db::Database`, to be
+//! compared against later submissions without refingerprinting them.
+//! Content to ignore can be suggested from the fingerprints shared most
+//! widely across submissions, with `boilerplate`.
+//!
+//! `Analysis` ties these together with a single configuration.
+
+#[macro_use] extern crate prettytable;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+use crate::fingerprint::Fingerprint;
+pub mod error;
+pub mod cli;
+pub mod fingerprint;
+pub mod tokenize;
+pub mod normalize;
+pub mod ast;
+pub mod file_io;
+mod io_redirect;
+mod cache;
+pub mod db;
+pub mod boilerplate;
+pub mod phase_i;
+pub mod lsh;
+pub mod phase_ii;
+pub mod phase_iii;
+pub mod cluster;
+pub mod results;
+mod json;
+mod html;
+mod csv;
+mod analysis;
+
+pub use crate::analysis::{Analysis, Overlaps};
+pub use crate::error::MossError;
+
+/// Sub represents a student submission.
+/// Depending on whether input submissions are directories or
+/// indiv. files, the dir_name field will be Some or None.
+/// Archived submissions (e.g. from prior terms) are only compared
+/// against current submissions, never against each other
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
+pub struct Sub {
+    pub dir_name: Option<PathBuf>,
+    #[serde(skip)]
+    pub archived: bool,
+    pub documents: Vec<Doc>
+}
+
+/// Subs are hashed by their paths alone (equal subs have equal paths), as
+/// phase I hashes each sub once for every fingerprint it contains
+impl Hash for Sub {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.dir_name.hash(state);
+        self.archived.hash(state);
+
+        for doc in self.documents.iter() {
+            match doc {
+                Doc::Unprocessed(p) | Doc::Processed(p, _) => p.hash(state),
+            }
+        }
+    }
+}
+
+/// Doc represents a file within a submission.
+/// Docs are initialized as Unprocessed (contents have not yet been
+/// read), and become Processed once they have been fingerprinted
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Serialize, Deserialize)]
+pub enum Doc {
+    Unprocessed(PathBuf),
+    Processed(PathBuf, Vec<Fingerprint>)
+}
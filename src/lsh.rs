@@ -0,0 +1,116 @@
+//! lsh.rs: MinHash signatures & locality-sensitive hashing, for choosing
+//! candidate pairs of submissions in corpora too large to pair exhaustively
+
+use fnv::{FnvHashMap, FnvHasher};
+use std::hash::Hasher;
+
+/// LshParams determine how MinHash signatures are banded: each signature
+/// has bands * rows values, & two submissions become a candidate pair if
+/// all rows of any one band agree. Submissions whose fingerprint sets have
+/// Jaccard similarity s are paired with probability 1 - (1 - s^rows)^bands,
+/// so more bands find less similar pairs, & more rows exclude them.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LshParams {
+    pub bands: usize,
+    pub rows: usize
+}
+
+impl LshParams {
+    /// The number of values in each signature
+    pub fn signature_len(&self) -> usize {
+        self.bands * self.rows
+    }
+}
+
+/// 64-bit finalizer of splitmix64, used to derive independent hash functions
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Compute the MinHash signature of a submission's set of fingerprint hashes:
+/// for each of len hash functions, the least value it takes on any of the hashes.
+/// Submissions without hashes have no signature.
+pub fn signature(hashes: &[i64], len: usize) -> Option<Vec<u64>> {
+    if hashes.is_empty() { return None; }
+
+    let seeds: Vec<u64> = (1..=len as u64).map(mix).collect();
+    let mut sig = vec![u64::MAX; len];
+
+    for &hash in hashes.iter() {
+        let x = mix(hash as u64);
+        for (min, seed) in sig.iter_mut().zip(seeds.iter()) {
+            *min = (*min).min(mix(x ^ seed));
+        }
+    }
+
+    Some(sig)
+}
+
+/// Choose candidate pairs (i, j) with i < j of the given signatures (indexed by
+/// submission), by bucketing each band of every signature & pairing up the
+/// submissions in each bucket. Pairs are returned sorted & without duplicates.
+pub fn candidate_pairs(signatures: &[Option<Vec<u64>>], params: &LshParams) -> Vec<(usize, usize)> {
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    let mut buckets: FnvHashMap<u64, Vec<usize>> = FnvHashMap::default();
+
+    for band in 0..params.bands {
+        let rows = band * params.rows..(band + 1) * params.rows;
+
+        for (id, sig) in signatures.iter().enumerate() {
+            if let Some(sig) = sig {
+                let mut hasher = FnvHasher::default();
+                for value in sig[rows.clone()].iter() { hasher.write_u64(*value); }
+                buckets.entry(hasher.finish()).or_default().push(id);
+            }
+        }
+
+        // ids are pushed in increasing order, so each bucket is sorted
+        for (_, bucket) in buckets.drain() {
+            for (i, &a) in bucket.iter().enumerate() {
+                pairs.extend(bucket[i + 1..].iter().map(|&b| (a, b)));
+            }
+        }
+    }
+
+    pairs.sort_unstable();
+    pairs.dedup();
+    pairs
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashes(range: impl Iterator<Item = i64>) -> Vec<i64> {
+        range.collect()
+    }
+
+    #[test]
+    fn test_signature() {
+        let a = signature(&hashes(0..100), 64).unwrap();
+        let b = signature(&hashes((0..100).rev()), 64).unwrap();
+        let c = signature(&hashes(50..150), 64).unwrap();
+
+        // signatures don't depend on order, & estimate Jaccard similarity (here 1/3)
+        assert_eq!(a, b);
+        let agree = a.iter().zip(c.iter()).filter(|(x, y)| x == y).count();
+        assert!((10..=35).contains(&agree), "{} of 64 values agree", agree);
+
+        assert_eq!(signature(&hashes(0..0), 64), None);
+    }
+
+    #[test]
+    fn test_candidate_pairs() {
+        let subs = [hashes(0..100), hashes(1000..1100), hashes(0..99), hashes(0..0), hashes(1000..1100)];
+        let params = LshParams { bands: 16, rows: 4 };
+        let sigs: Vec<Option<Vec<u64>>> = subs.iter()
+            .map(|s| signature(s, params.signature_len()))
+            .collect();
+
+        // near-identical subs are paired, unrelated & empty ones aren't
+        assert_eq!(candidate_pairs(&sigs, &params), vec![(0, 2), (1, 4)]);
+    }
+}
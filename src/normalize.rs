@@ -1,6 +1,13 @@
-/// normalize.rs: Pre-processer for Pyret programs to eliminate irrelevant features
+//! normalize.rs: Pre-processer for Pyret programs to eliminate irrelevant features
 
-use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use crate::tokenize::{tokenize, Token, TokenKind};
+
+/// version of the normalizer: bump whenever the normalized text produced
+/// for a given program changes (invalidates cached fingerprints)
+pub const VERSION: u32 = 2;
 
 /// A NormText stores the normalized text of some program and
 /// encodes line number information from the original
@@ -9,28 +16,58 @@ use regex::Regex;
 ///
 /// line_ends[x] = y means that y is the index of the first char
 /// in the normalized text occurring *after* line x+1 in the original
+///
+/// token_spans[x] = (a, b) means that the x-th normalized token occupies
+/// indices a (inclusive) to b (exclusive) of the normalized text
+///
+/// source_map[x] = (a, b) means that the char at index x in the normalized
+/// text came from the original text running from a to b, inclusive
+/// (a single char, or a whole token if it was replaced, i.e. an identifier)
 #[derive(Debug, PartialEq)]
 pub struct NormText {
     pub value: String,
-    line_ends: Vec<i32>
+    line_ends: Vec<i32>,
+    pub token_spans: Vec<(i32, i32)>,
+    pub source_map: Vec<(SourcePos, SourcePos)>
+}
+
+/// A SourcePos is the position of a char in the original text of a program
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct SourcePos {
+    pub byte: usize,    // byte offset
+    pub line: i32,      // line number (1-indexed)
+    pub col: i32        // column, in chars (1-indexed)
 }
 
 impl NormText {
     /// determine the line number in the original text that
     /// a char at index norm_idx in the normalized text corresponds to
     pub fn line_number(&self, norm_idx: i32) -> i32 {
-        // iterate over all lines & the first norm char occurring *after* them
-        for (zro_idx_line, first_char_after) in self.line_ends.iter().enumerate() {
-            // if first char after this line is strictly after norm_idx,
-            // then norm_idx takes place on this line (if it were on an earlier
-            // line, it would've already terminated)
-            if first_char_after > &norm_idx {
-                return (zro_idx_line as i32) + 1;    // correct for 0-indexing
-            }
+        // line_ends is sorted, so binary search for the first line whose first
+        // char after it is strictly after norm_idx: norm_idx takes place on that
+        // line (if it were on an earlier line, it would've already terminated)
+        let zro_idx_line = self.line_ends.partition_point(|&first_char_after| first_char_after <= norm_idx);
+
+        // should not run off the end (last entry in line_ends is larger than any valid norm_idx)
+        if zro_idx_line == self.line_ends.len() {
+            panic!("normalize: line_number called with invalid index {}", norm_idx);
         }
+        (zro_idx_line as i32) + 1    // correct for 0-indexing
+    }
+
+    /// determine the column in the original text at which the char at index
+    /// norm_idx in the normalized text begins, on the line given by line_number
+    pub fn start_col(&self, norm_idx: i32) -> i32 {
+        let (first, _) = self.source_map[norm_idx as usize];
 
-        // should not get here (last entry in line_ends is larger than any valid norm_idx)
-        panic!("normalize: line_number called with invalid index {}", norm_idx);
+        // replacements for tokens spanning several lines belong to their last line
+        if first.line == self.line_number(norm_idx) { first.col } else { 1 }
+    }
+
+    /// determine the column in the original text at which the char at index
+    /// norm_idx in the normalized text ends (inclusive)
+    pub fn end_col(&self, norm_idx: i32) -> i32 {
+        self.source_map[norm_idx as usize].1.col
     }
 }
 
@@ -38,6 +75,90 @@ impl NormText {
 // Note: unit tests may break if this is altered (written assuming 'v')
 const UNIFORM_IDENTIFIER: char = 'v';
 
+// keywords opening a construct that is closed by a matching `end`
+pub(crate) const SCOPE_OPENERS: [&str; 28] = [
+    "fun", "lam", "method", "data", "if", "ask", "cases", "for", "when", "block:",
+    "check:", "check", "examples:", "examples", "table:", "load-table", "reactor",
+    "let", "letrec", "type-let", "spy", "provide:", "sieve", "order", "transform",
+    "extend", "select", "extract"
+];
+
+// replacement for all numeric literals (when normalizing numbers)
+const UNIFORM_NUMBER: char = 'n';
+
+// replacement for all string literals (when normalizing strings)
+const UNIFORM_STRING: &str = "\"\"";
+
+/// IdentifierMode determines how identifiers are normalized:
+///     Uniform: every identifier is replaced with the same name (v)
+///     Renamed: identifiers are renamed consistently within each top-level
+///              construct (fun ... end, data ... end, etc.), so the first
+///              distinct name becomes v0, the next v1, and so on. Numbering
+///              only restarts at the `end` of a top-level construct, so
+///              statements outside any construct share the numbering of the
+///              construct that follows them
+///     Preserved: identifiers are kept as they are
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum IdentifierMode {
+    Uniform,
+    Renamed,
+    Preserved
+}
+
+/// NormOptions configures which normalizations are applied to a program.
+/// By default, the five standard normalizations are applied & the extra
+/// passes (numbers, strings) are not.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub struct NormOptions {
+    pub identifiers: IdentifierMode,    // how identifiers are replaced
+    pub types: bool,                    // remove type annotations
+    pub whitespace: bool,               // remove whitespace
+    pub docstrings: bool,               // remove docstrings
+    pub comments: bool,                 // remove comments
+    pub numbers: bool,                  // replace numeric literals with n
+    pub strings: bool                   // replace string literals with ""
+}
+
+impl Default for NormOptions {
+    fn default() -> Self {
+        NormOptions {
+            identifiers: IdentifierMode::Uniform,
+            types: true,
+            whitespace: true,
+            docstrings: true,
+            comments: true,
+            numbers: false,
+            strings: false
+        }
+    }
+}
+
+/// describe how options depart from the defaults, i.e. "kept comments, normalized numbers"
+impl fmt::Display for NormOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut changes = Vec::new();
+
+        match self.identifiers {
+            IdentifierMode::Uniform => {},
+            IdentifierMode::Renamed => changes.push("renamed identifiers"),
+            IdentifierMode::Preserved => changes.push("kept identifiers"),
+        }
+
+        if !self.types { changes.push("kept types"); }
+        if !self.whitespace { changes.push("kept whitespace"); }
+        if !self.docstrings { changes.push("kept docstrings"); }
+        if !self.comments { changes.push("kept comments"); }
+        if self.numbers { changes.push("normalized numbers"); }
+        if self.strings { changes.push("normalized strings"); }
+
+        if changes.is_empty() {
+            write!(f, "default")
+        } else {
+            write!(f, "{}", changes.join(", "))
+        }
+    }
+}
+
 /// Remove/normalize any features from a program's text that
 /// shouldn't differentiate it from other programs:
 ///      1. normalize identifiers
@@ -45,285 +166,302 @@ const UNIFORM_IDENTIFIER: char = 'v';
 ///      3. remove whitespace
 ///      4. remove docstrings
 ///      5. remove comments
-/// Non-ASCII text is also ignored.
+/// Non-ASCII text is also ignored. (see normalize_with to configure these)
 /// Returns the normalized string & enough info to map parts
 /// of the normalized text to line numbers in the original (LineMapping)
 pub fn normalize(program: &str) -> NormText {
-    // remove any non-ascii text
-    let program = program.replace(|c: char| !c.is_ascii(), "");
+    normalize_with(program, &NormOptions::default())
+}
+
+/// Normalize a program (see normalize), applying only the normalizations
+/// enabled in the given options
+pub fn normalize_with(program: &str, opts: &NormOptions) -> NormText {
+    let tokens = tokenize(program);
+    let normalized = normalize_tokens(&tokens, opts);
+    let positions = source_positions(program);
 
-    let mut head: &str = program.as_str();  // rest of program to be processed
     let mut norm = String::new();           // normalized program text
     let mut norm_idx = 0;                   // next index to write to in norm text
     let mut line_ends = Vec::new();         // encodes line info (see NormText above)
+    let mut token_spans = Vec::new();       // spans of normalized tokens (see NormText above)
+    let mut source_map = Vec::new();        // original position of each char (see NormText above)
+
+    for (token, text) in tokens.iter().zip(normalized) {
+        match text {
+            // ------- Removed -------
+            None => account_for_newlines(token.text, norm_idx, &mut line_ends, false),
+
+            // ------- Kept -------
+            Some(text) => {
+                // newlines are only kept within kept literals, comments & whitespace.
+                // account for these *before* incrementing norm_idx, because indices
+                // for line ends within the token need to be computed relative
+                // to norm_idx before advancing *past* the token.
+                if text.contains('\n') {
+                    account_for_newlines(&text, norm_idx, &mut line_ends, true);
+                } else {
+                    account_for_newlines(token.text, norm_idx, &mut line_ends, false);
+                }
 
-    // while haven't seen entire program
-    while !head.is_empty() {
-
-        // ------- Whitespace -------
-        if let Some((mat, rest, _)) = match_whitespace(head) {
-            head = rest;    // jump over whitespace
-            account_for_newlines(mat, norm_idx, &mut line_ends, false);
-            continue;
-        }
+                // tokens made up entirely of non-ASCII text disappear
+                if !text.is_empty() {
+                    token_spans.push((norm_idx, norm_idx + text.len() as i32));
+                }
 
-        // ------- Comments -------
-        if let Some((mat, rest, _)) = match_comment(head) {
-            head = rest;    // jump over comment
-            account_for_newlines(mat, norm_idx, &mut line_ends, false);
-            continue;
-        }
+                // kept text maps char by char, replaced tokens map to the whole token
+                let start = token.span.start;
+                if text == ascii(token.text) {
+                    for (i, _) in token.text.char_indices().filter(|(_, c)| c.is_ascii()) {
+                        let pos = positions[start + i];
+                        source_map.push((pos, pos));
+                    }
+                } else {
+                    let last = token.text.char_indices().last().map_or(0, |(i, _)| i);
+                    let span = (positions[start], positions[start + last]);
+                    source_map.extend(std::iter::repeat_n(span, text.len()));
+                }
 
-        // ------- Docstrings -------
-        if let Some((mat, rest, _)) = match_docstring(head) {
-            head = rest;    // jump over docstring
-            account_for_newlines(mat, norm_idx, &mut line_ends, false);
-            continue;
+                norm.push_str(&text);
+                norm_idx += text.len() as i32;
+            },
         }
+    }
 
-        // ------- Types -------
-        if let Some((mat, rest, _)) = match_type(head) {
-            head = rest;    // jump over annotation
-            account_for_newlines(mat, norm_idx, &mut line_ends, false);
-            continue;
-        }
+    // final line always contains everything to end of norm
+    line_ends.push(norm.chars().count() as i32);
 
-        // ------- String Literals -------
-        if let Some((mat, rest, len)) = match_string_literal(head) {
-            // account for newlines *before* incrementing norm_idx,
-            // because whitespace is preserved in strings, and indices
-            // for line ends within the literal need to be computed relative
-            // to norm_idx before advancing *past* the string.
-            account_for_newlines(mat, norm_idx, &mut line_ends, true);
+    // return normalized text in struct for line number computations
+    NormText { value: norm, line_ends, token_spans, source_map }
+}
 
-            norm.push_str(mat); // write literal to norm
-            norm_idx += len as i32;
+/// Normalize each of a program's tokens according to the given options.
+/// The result has an entry for every token: its normalized text,
+/// or None if the token is removed entirely.
+pub(crate) fn normalize_tokens(tokens: &[Token], opts: &NormOptions) -> Vec<Option<String>> {
+    let mut normalized = Vec::with_capacity(tokens.len());
+    let mut i = 0;                          // index of next token to be processed
+    let mut names = HashMap::new();         // identifier => its index in the current scope
+    let mut depth: usize = 0;               // number of constructs awaiting an `end`
 
-            head = rest;    // move to end of literal
+    // while haven't seen entire program
+    while i < tokens.len() {
+        let token = &tokens[i];
+
+        // ------- Whitespace & Comments -------
+        let remove_trivia = match token.kind {
+            TokenKind::Whitespace => opts.whitespace,
+            TokenKind::Comment => opts.comments,
+            _ => false,
+        };
+
+        if remove_trivia {
+            normalized.push(None);
+            i += 1;
             continue;
         }
 
-        // ------- Keywords & Identifiers -------
-        if let Some((is_keyw, key_or_id)) = match_keyword_or_ident(head) {
-            let (mat, rest, len) = key_or_id;
+        // ------- Docstrings & Types -------
+        let docstring = if opts.docstrings { match_docstring(tokens, i) } else { None };
+        let removed = docstring.or_else(|| if opts.types { match_type(tokens, i) } else { None });
 
-            head = rest;    // jump over keyword/ident
+        if let Some(end) = removed {
+            // jump over docstring/annotation, including any whitespace within
+            normalized.resize(normalized.len() + (end - i), None);
+            i = end;
+            continue;
+        }
 
-            if is_keyw {
-                norm.push_str(mat); // preserve keyword
-                norm_idx += len as i32;
-            } else {
-                norm.push(UNIFORM_IDENTIFIER); // normalize identifiers
-                norm_idx += 1;
+        // (the words of a keyword are separated by a single space, however spaced out)
+        let text = match token.kind {
+            TokenKind::Keyword => token.text.split_whitespace().collect::<Vec<&str>>().join(" "),
+            _ => ascii(token.text),
+        };
+
+        let norm_text = match token.kind {
+            // ------- String Literals -------
+            TokenKind::String if opts.strings => String::from(UNIFORM_STRING),
+
+            // ------- Identifiers -------
+            TokenKind::Name => match opts.identifiers {
+                IdentifierMode::Uniform => UNIFORM_IDENTIFIER.to_string(),
+                IdentifierMode::Preserved => text.clone(),
+                IdentifierMode::Renamed => {
+                    let next = names.len();
+                    let idx = *names.entry(text.clone()).or_insert(next);
+                    format!("{}{}", UNIFORM_IDENTIFIER, idx)
+                },
+            },
+
+            // ------- Numeric Literals -------
+            TokenKind::Number if opts.numbers => UNIFORM_NUMBER.to_string(),
+
+            // ------- Keywords & otherwise -------
+            // preserve keywords, numbers, symbols, (kept) strings, whitespace & comments
+            _ => text.clone(),
+        };
+
+        // ------- Scopes -------
+        // renaming restarts after each top-level construct
+        if token.kind == TokenKind::Keyword {
+            if text == "end" {
+                depth = depth.saturating_sub(1);
+                if depth == 0 { names.clear(); }
+            } else if SCOPE_OPENERS.contains(&text.as_str()) {
+                depth += 1;
             }
-            continue;
         }
 
-        // ------- otherwise -------
-        norm.push(head.chars().next().unwrap());    // write first char of head to norm
-        norm_idx += 1;      // progress next idx to be written to
-        head = &head[1..];  // progress head
+        normalized.push(Some(norm_text));
+        i += 1;
     }
 
-    // final line always contains everything to end of norm
-    line_ends.push(norm.chars().count() as i32);
-
-    // return normalized text in struct for line number computations
-    NormText { value: norm, line_ends: line_ends }
+    normalized
 }
 
-/// A Match indicates a prefix of some string that represents some feature
-/// (i.e. whitespace, docstring, identifier, etc.)
-/// In (match, rest, len), match is the matching prefix, rest is the
-/// remaining slice of the original string, and len is the size of the match
-type Match<'a> = (&'a str, &'a str, usize);
-
-/// extract the prefix of hd that matches the given reg expression, or None
-/// (ensure the given regex is prefixed with ^)
-fn extract_match<'a>(hd: &'a str, re: &Regex) -> Option<Match<'a>> {
-    match re.find(hd) {
-        Some(mat) => {
-            let e = mat.end();
-            // match, rest, and length of match
-            Some((&hd[..e], &hd[e..], e))
-        },
-        None => None,
-    }
-}
+/// Determine the position of each byte of a program (bytes within
+/// a multi-byte char share the position of that char)
+pub(crate) fn source_positions(program: &str) -> Vec<SourcePos> {
+    let mut positions = Vec::with_capacity(program.len());
+    let (mut line, mut col) = (1, 1);
 
-/// extract longest prefix of whitespace if any, or None
-fn match_whitespace(hd: &str) -> Option<Match> {
-    lazy_static! {
-        static ref WHITESPACE: Regex = Regex::new(r"^\s+").unwrap();
-    }
+    for (byte, c) in program.char_indices() {
+        positions.extend(std::iter::repeat_n(SourcePos { byte, line, col }, c.len_utf8()));
 
-    extract_match(hd, &WHITESPACE)
-}
-
-/// extract longest comment prefix if any, or None
-fn match_comment(hd: &str) -> Option<Match> {
-    lazy_static! {
-        // match single- and multi-line comments
-        static ref COMMENT: Regex = Regex::new(
-            r#"^((#\|(.|\n)*?\|#)|(#.*))"#
-        ).unwrap();
+        if c == '\n' {
+            line += 1;
+            col = 1;
+        } else {
+            col += 1;
+        }
     }
 
-    extract_match(hd, &COMMENT)
+    positions
 }
 
-/// extract longest docstring prefix if any, or None
-fn match_docstring(hd: &str) -> Option<Match> {
-    lazy_static! {
-        // match docstrings with '', "", and ``` quotes
-        static ref DOC: Regex = Regex::new(
-            r#"^doc:\s*((".*?")|('.*?')|(```(.|\n)*?```))"#
-        ).unwrap();
-    }
-
-    extract_match(hd, &DOC)
+/// remove any non-ascii text
+fn ascii(text: &str) -> String {
+    text.replace(|c: char| !c.is_ascii(), "")
 }
 
-/// extract longest type annotation prefix if any, or None
-fn match_type(hd: &str) -> Option<Match> {
-    lazy_static! {
-        // type parameters (i.e. <A, B, C>)
-        static ref TYPE_PARAMS: Regex = Regex::new(
-            r"^<([_a-zA-Z][-_a-zA-Z0-9]*(,\s*)?)*>"
-        ).unwrap();
-        // :: syntax preceding type annotations
-        // (followed by mandatory whitespace)
-        static ref ANNOT_PREFIX: Regex = Regex::new(
-            r"^::\s+"
-        ).unwrap();
-        // -> syntax preceding output type annotations
-        // (followed by arb. whitespace)
-        static ref OUT_TYPE_PREFIX: Regex = Regex::new(
-            r"^->\s*"
-        ).unwrap();
-        // simple (non-parenthesized) types
-        static ref SIMPLE_TYPE: Regex = Regex::new(
-            r"[_a-zA-Z][-_a-zA-Z0-9<>]*"
-        ).unwrap();
-    }
-
-    /// Given a match of an annotation prefix, attempt to parse the
-    /// following type, and combine them into a single match.
-    /// Also parse arbitrary whitespace following the type (so when types
-    /// are removed, no whitespace artifact will be left)
-    fn parse_type<'a>(head: &'a str, prefix: Match) -> Option<Match<'a>> {
-        let (_, pref_rest, pref_len) = prefix;
-
-        if pref_rest.starts_with('(') {
-            // try complex type (match everything within balanced parens)
-            let mut open_parens = 1;    // count of open parentheses read so far
-            let mut reader = pref_rest; // copy for reading through rest of type
-            let mut typ_len = 1;        // number of matched chars in type
-
-            while open_parens > 0 {
-                reader = &reader[1..];  // move along
-                typ_len += 1;
-
-                // if out of input before balancing parens, fail
-                if reader.is_empty() { return None; }
-
-                // count opening/closing parens
-                if reader.starts_with('(') {
-                    open_parens += 1;
-                } else if reader.starts_with(')') {
-                    open_parens -= 1;
-                }
-            }
+/// index of the first non-whitespace/comment token at or after i
+fn skip_trivia(tokens: &[Token], i: usize) -> usize {
+    let mut i = i;
+    while tokens.get(i).is_some_and(|t| t.is_trivia()) { i += 1; }
+    i
+}
 
-            // combine prefix & type matches
-            let len = pref_len + typ_len;
-            Some((&head[..len], &head[len..], len))
+/// If tokens[i] begins a docstring (doc: followed by a string literal),
+/// the index of the token just after it, or None
+fn match_docstring(tokens: &[Token], i: usize) -> Option<usize> {
+    if tokens[i].kind != TokenKind::Keyword || tokens[i].text != "doc:" { return None; }
 
-        } else {
-            // try simple type
-            match extract_match(pref_rest, &SIMPLE_TYPE) {
-                Some((_, _, typ_len)) => {
-                    // combine prefix & type matches
-                    let len = pref_len + typ_len;
-                    Some((&head[..len], &head[len..], len))
-                },
-                None => return None, // fail
-            }
-        }
+    let lit = skip_trivia(tokens, i + 1);
+    match tokens.get(lit) {
+        Some(t) if t.kind == TokenKind::String => Some(lit + 1),
+        _ => None,
     }
+}
+
+/// If tokens[i] begins a type annotation (:: or -> followed by a type)
+/// or a list of type parameters (i.e. <A, B, C>), the index of the
+/// token just after it, or None
+fn match_type(tokens: &[Token], i: usize) -> Option<usize> {
+    let token = &tokens[i];
 
-    // attempt to match :: prefix
-    if let Some(colon_prefix) = extract_match(hd, &ANNOT_PREFIX) {
-        return parse_type(hd, colon_prefix);
+    if token.is_symbol("::") || token.is_symbol("->") {
+        return parse_annotation(tokens, skip_trivia(tokens, i + 1));
     }
 
-    // attempt to match -> prefix
-    if let Some(arrow_prefix) = extract_match(hd, &OUT_TYPE_PREFIX) {
-        return parse_type(hd, arrow_prefix);
+    if token.is_symbol("<") {
+        return parse_type_params(tokens, i);
     }
 
-    // finally, attempt to match type parameter list
-    extract_match(hd, &TYPE_PARAMS)
+    None
 }
 
-/// extract longest string literal prefix (single, double, or triple quoted) if any, or None
-fn match_string_literal(hd: &str) -> Option<Match> {
-    lazy_static! {
-        // match all kinds of quoted strings
-        static ref STRING_LIT: Regex = Regex::new(
-            r#"^((".*?")|('.*?')|(```(.|\n)*?```))"#
-        ).unwrap();
+/// Parse the type beginning at tokens[i], returning the index of the token just
+/// after it. Types are either bracketed (i.e. (A -> B), {x :: A}) or simple names,
+/// possibly qualified (T.Type), applied (List<A>), and refined (Number%(is-even))
+fn parse_annotation(tokens: &[Token], i: usize) -> Option<usize> {
+    let token = tokens.get(i)?;
+    let is_symbol = |j: usize, sym: &str| tokens.get(j).is_some_and(|t| t.is_symbol(sym));
+
+    let mut end = if token.is_symbol("(") || token.is_symbol("{") {
+        // complex type (match everything within balanced brackets)
+        match_brackets(tokens, i)?
+    } else if token.kind == TokenKind::Name {
+        let mut end = i + 1;
+
+        // qualified names
+        while is_symbol(end, ".") && tokens.get(end + 1).is_some_and(|t| t.kind == TokenKind::Name) {
+            end += 2;
+        }
+
+        // type arguments
+        if is_symbol(end, "<") {
+            if let Some(args_end) = match_brackets(tokens, end) { end = args_end; }
+        }
+
+        end
+    } else {
+        return None;
+    };
+
+    // refinement
+    if is_symbol(end, "%") && is_symbol(end + 1, "(") {
+        if let Some(ref_end) = match_brackets(tokens, end + 1) { end = ref_end; }
     }
 
-    extract_match(hd, &STRING_LIT)
+    Some(end)
 }
 
-/// extract longest identifier/keyword prefix if any, or None.
-/// boolean indicates true if a keyword was matched, false if identifier
-fn match_keyword_or_ident(hd: &str) -> Option<(bool, Match)> {
-    lazy_static! {
-        // gleaned from pyret-lang/src/scripts/tokenize.js
-        // ordered length descending so longest match is preferred
-        static ref KEYWORD: Regex = Regex::new(
-            "^(raises-other-than|raises-satisfies|raises-violates|does-not-raise\
-                |provide-types|otherwise:|load-table|is-roughly|descending|transform\
-                |satisfies|is-not<=>|examples:|ascending|violates|type-let|sharing:\
-                |sanitize|provide:|is-not=~|is-not==|examples|source:|reactor|provide\
-                |newtype|include|extract|else if|because|where:|table:|shadow|select\
-                |raises|module|method|letrec|is-not|import|hiding|extend|check:|block:\
-                |with:|using|then:|sieve|order|is<=>|false|else:|check|cases|when|type\
-                |true|row:|lazy|is=~|is==|from|else|doc:|data|var|spy|ref|rec|let|lam\
-                |fun|for|end|ask|and|or|of|is|if|do|by|as)"
-        ).unwrap();
-        static ref IDENT: Regex = Regex::new(
-            r"^([_a-zA-Z][_a-zA-Z0-9]*(?:-+[_a-zA-Z0-9]+)*)"
-        ).unwrap();
-    }
-
-    let key_match = extract_match(hd, &KEYWORD);
-    let id_match = extract_match(hd, &IDENT);
-
-    match (key_match, id_match) {
-        // both keyword & identifier match
-        (Some(key), Some(id)) => {
-            // compare match lengths
-            if id.2 > key.2 {
-                // use identifier match
-                Some((false, id))
-            } else {
-                // use keyword match
-                Some((true, key))
+/// Parse a list of type parameters (i.e. <A, B, C>) beginning at tokens[i],
+/// returning the index of the token just after it
+fn parse_type_params(tokens: &[Token], i: usize) -> Option<usize> {
+    let mut j = i + 1;
+
+    loop {
+        let token = tokens.get(j)?;
+
+        if token.is_symbol(">") {
+            return Some(j + 1);
+        } else if token.kind == TokenKind::Name {
+            // each parameter is followed by either a comma or the closing >
+            j += 1;
+            if tokens.get(j)?.is_symbol(",") {
+                j = skip_trivia(tokens, j + 1);
+            } else if !tokens[j].is_symbol(">") {
+                return None;
             }
-        },
-        // only identifier
-        (None, Some(id)) => Some((false, id)),
-
-        // only keyword
-        (Some(key), None) => Some((true, key)),
+        } else {
+            return None;
+        }
+    }
+}
 
-        // neither match
-        (None, None) => None,
+/// Given the index of an opening bracket ((, {, or <), find the index
+/// of the token just after its matching closing bracket, if balanced
+fn match_brackets(tokens: &[Token], i: usize) -> Option<usize> {
+    let open = tokens[i].text;
+    let close = match open {
+        "(" => ")",
+        "{" => "}",
+        "<" => ">",
+        _ => return None,
+    };
+
+    let mut depth = 0;  // count of open brackets read so far
+
+    for (j, token) in tokens.iter().enumerate().skip(i) {
+        if token.is_symbol(open) {
+            depth += 1;
+        } else if token.is_symbol(close) {
+            depth -= 1;
+            if depth == 0 { return Some(j + 1); }
+        }
     }
+
+    None    // out of input before balancing brackets
 }
 
 /// Read over a slice & add the index of the next normalized text char
@@ -339,6 +477,7 @@ fn account_for_newlines(slice: &str, idx: i32, le: &mut Vec<i32>, preserve_newli
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod tests {
     use super::*;
 
@@ -378,6 +517,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn column_info_preserved() {
+        let norm = normalize(
+            "fun f(x):\n\
+                \tx + 10 # add\n\
+                end");
+        // norm: "funv(v):v+10end"
+        assert_eq!((norm.start_col(0), norm.end_col(2)), (1, 3));     // fun
+        assert_eq!((norm.start_col(3), norm.end_col(3)), (5, 5));     // f
+        assert_eq!((norm.start_col(8), norm.end_col(8)), (2, 2));     // x
+        assert_eq!((norm.start_col(10), norm.end_col(11)), (6, 7));   // 10
+        assert_eq!((norm.start_col(12), norm.end_col(14)), (1, 3));   // end
+
+        // bytes, lines & columns of original chars are all mapped
+        assert_eq!(norm.source_map[9],
+            (SourcePos { byte: 13, line: 2, col: 4 }, SourcePos { byte: 13, line: 2, col: 4 }));
+
+        // replacements span the whole token they replace
+        let norm = normalize("total = sum-of(my-list)");
+        // norm: "v=v(v)"
+        assert_eq!((norm.start_col(0), norm.end_col(0)), (1, 5));
+        assert_eq!((norm.start_col(4), norm.end_col(4)), (16, 22));
+    }
+
     // generic testing form for normalize()
     // calls normalize() on input string & asserts output text value & line ends
     fn test_norm(input: &str, out_val: &str, out_line_ends: Vec<i32>) {
@@ -414,6 +577,87 @@ mod tests {
             vec![3, 8]);
     }
 
+    #[test]
+    fn spaced_out_keywords() {
+        assert_eq!(normalize("if a: b else  if c: d end").value, normalize("if a: b else if c: d end").value);
+        assert_eq!(normalize("else iffy").value, "elsev");
+    }
+
+    #[test]
+    fn identifiers_renamed() {
+        let renamed = NormOptions { identifiers: IdentifierMode::Renamed, ..NormOptions::default() };
+        let norm = |program| normalize_with(program, &renamed).value;
+
+        // distinct names are numbered in order of first appearance
+        assert_eq!(norm("name-1 = 7\nsecond_name = name-1 * name-1"), "v0=7v1=v0*v0");
+
+        // consistent renaming hides a renamed variable, unlike collapsing to v
+        assert_eq!(norm("fun f(x, y): x - y end"), norm("fun g(a, b): a - b end"));
+        assert_ne!(norm("fun f(x, y): x - y end"), norm("fun f(x, y): y - x end"));
+        assert_eq!(normalize("fun f(x, y): x - y end").value,
+            normalize("fun f(x, y): y - x end").value);
+
+        // numbering restarts after each top-level construct, even with nested ends
+        assert_eq!(
+            norm("fun f(x): if x: x else: y end end\nfun g(z): z end"),
+            "funv0(v1):ifv1:v1else:v2endendfunv0(v1):v1end");
+
+        // (including those of table operations within a construct)
+        assert_eq!(
+            norm("fun f(t):\n  s = select a from t end\n  s\nend\nfun g(u): u end"),
+            "funv0(v1):v2=selectv3fromv1endv2endfunv0(v1):v1end");
+        assert_eq!(
+            norm("fun f(t):\n  s = extract a from t end\n  s\nend"),
+            "funv0(v1):v2=extractv3fromv1endv2end");
+
+        // line info is unaffected by the longer names
+        let line_ends = normalize_with("a = 1\nb = a", &renamed).line_ends;
+        assert_eq!(line_ends, vec![4, 9]);
+    }
+
+    #[test]
+    fn identifiers_renamed_top_level() {
+        let renamed = NormOptions { identifiers: IdentifierMode::Renamed, ..NormOptions::default() };
+        let norm = |program| normalize_with(program, &renamed).value;
+
+        // top-level statements are numbered together with the construct after them,
+        // & numbering restarts only once that construct ends
+        assert_eq!(
+            norm("a = 1\nb = a\nfun f(x): x + a end\nc = a"),
+            "v0=1v1=v0funv2(v3):v3+v0endv0=v1");
+    }
+
+    #[test]
+    fn normalizations_configurable() {
+        let program = "fun f(x :: Number) -> Number:\n  doc: \"adds\"\n  x + 1.5 # add\nend";
+        let norm = |opts: NormOptions| normalize_with(program, &opts).value;
+        let default = NormOptions::default();
+
+        assert_eq!(norm(default), "funv(v):v+1.5end");
+        assert_eq!(norm(NormOptions { identifiers: IdentifierMode::Preserved, ..default }),
+            "funf(x):x+1.5end");
+        assert_eq!(norm(NormOptions { types: false, ..default }),
+            "funv(v::v)->v:v+1.5end");
+        assert_eq!(norm(NormOptions { docstrings: false, ..default }),
+            "funv(v):doc:\"adds\"v+1.5end");
+        assert_eq!(norm(NormOptions { comments: false, ..default }),
+            "funv(v):v+1.5# addend");
+        assert_eq!(norm(NormOptions { numbers: true, ..default }),
+            "funv(v):v+nend");
+        assert_eq!(norm(NormOptions { strings: true, docstrings: false, ..default }),
+            "funv(v):doc:\"\"v+1.5end");
+
+        // kept whitespace (& the text within it) is mapped to the right lines
+        let kept = normalize_with(program, &NormOptions { whitespace: false, ..default });
+        assert_eq!(kept.value, "fun v(v ) :\n  \n  v + 1.5 \nend");
+        assert_eq!(kept.line_ends, vec![12, 15, 26, 29]);
+        assert_eq!(kept.line_number(16), 3);
+
+        assert_eq!(default.to_string(), "default");
+        assert_eq!(NormOptions { comments: false, numbers: true, ..default }.to_string(),
+            "kept comments, normalized numbers");
+    }
+
     #[test]
     fn types_removed() {
         test_norm(
@@ -545,6 +789,42 @@ mod tests {
             vec![9, 18, 22, 25]);
     }
 
+    #[test]
+    fn escaped_quotes_in_strings() {
+        test_norm(
+            "s = \"say \\\"hi\\\" # not a comment\"\nt = 1",
+            "v=\"say \\\"hi\\\" # not a comment\"v=1",
+            vec![30, 33]);
+        test_norm(
+            "fun f():\n\
+                doc: 'it\\'s a docstring'\n\
+                0\n\
+            end",
+
+            "funv():0end",
+            vec![7, 7, 8, 11]);
+    }
+
+    #[test]
+    fn nested_comments_removed() {
+        test_norm(
+            "x = 1 #| outer #| inner |#\nstill outer |#\ny = 2",
+            "v=1v=2",
+            vec![3, 3, 6]);
+    }
+
+    #[test]
+    fn types_in_data_definitions_removed() {
+        test_norm(
+            "data Tree<A>:\n\
+                | node(value :: A, fs :: List<(A -> Boolean)>)\n\
+                | leaf\n\
+            end",
+
+            "datav:|v(v,v)|vend",
+            vec![6, 13, 15, 18]);
+    }
+
     #[test]
     fn ignores_non_ascii() {
         // expect a program's norm text to be a particular string
@@ -1,72 +1,230 @@
-/// Phase I: Normalize/fingerprint all submissions
+//! Phase I: Normalize/fingerprint all submissions
 
 use fnv::FnvHashMap;
 use std::collections::HashSet;
 use std::io;
 use std::io::prelude::*;
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use serde::{Serialize, Deserialize};
 use crate::{Doc, Sub};
+use crate::error::{MossError, Result};
+use crate::ast;
+use crate::cache;
 use crate::file_io;
-use crate::fingerprint::{self, Fingerprint};
-use crate::normalize;
+use crate::fingerprint::{self, Fingerprint, HashWidth, KgramMode};
+use crate::normalize::{self, NormOptions};
+
+/// FpParams determine how each document is normalized & fingerprinted.
+/// Fingerprints are only comparable if they were made with equal params.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub struct FpParams {
+    pub k: i32,                 // noise threshold
+    pub t: i32,                 // guarantee threshold
+    pub norm: NormOptions,      // normalizations applied before fingerprinting
+    pub kgrams: KgramMode,      // whether k-grams (and k, t) are made of chars or tokens
+    pub hash_width: HashWidth   // range of fingerprint hash values
+}
+
+impl FpParams {
+    /// Params with the given thresholds, the default normalizations, char k-grams,
+    /// & 31-bit hashes
+    pub fn new(k: i32, t: i32) -> Self {
+        FpParams { k, t, norm: NormOptions::default(), kgrams: KgramMode::Chars,
+            hash_width: HashWidth::default() }
+    }
+}
+
+/// MaxFrequency bounds how many submissions a fingerprint may appear in before
+/// it is considered boilerplate (e.g. starter code) and suppressed:
+///  1) Count allows at most the given number of submissions
+///  2) Percent allows at most the given percentage (0-100) of the current
+///     (not archived) submissions
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MaxFrequency {
+    Count(usize),
+    Percent(f64)
+}
+
+impl MaxFrequency {
+    /// The max number of submissions a fingerprint may appear in,
+    /// out of the given number of submissions
+    pub fn limit(&self, num_subs: usize) -> usize {
+        match *self {
+            MaxFrequency::Count(n) => n,
+            MaxFrequency::Percent(p) => (p / 100.0 * num_subs as f64).floor() as usize,
+        }
+    }
+}
+
+/// Collisions maps each fingerprint hash that was found to stand for different
+/// k-grams in different submissions to the groups of submissions sharing each
+/// of its k-grams. Two submissions only really share such a hash if some group
+/// contains both of them.
+pub type Collisions<'a> = FnvHashMap<i64, Vec<HashSet<&'a Sub>>>;
+
+/// Read a file's contents into memory
+fn read_file(path: &Path) -> Result<String> {
+    let read = || -> io::Result<String> {
+        let mut file = File::open(path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        Ok(contents)
+    };
+
+    read().map_err(|e| {
+        MossError::Io(format!("failed to analyze file {}", path.display()), e)
+    })
+}
 
 /// Read a file's contents into memory and normalize/fingerprint it
-/// k, t are fingerprint params
-fn analyze_file(path: &Path, k: i32, t: i32) -> io::Result<Vec<Fingerprint>> {
+/// according to params. If a cache dir is given, fingerprints
+/// are looked up there first, and stored there if they weren't found
+fn analyze_file(path: &Path, params: &FpParams, cache_dir: Option<&Path>) -> Result<Vec<Fingerprint>> {
     // read file text
-    let mut file = File::open(path.to_str().unwrap())?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
+    let contents = read_file(path)?;
+
+    // use the cached fingerprints for these contents, if any
+    let key = cache_dir.map(|_| cache::cache_key(&contents, params));
+    if let (Some(dir), Some(key)) = (cache_dir, &key) {
+        if let Some(fps) = cache::load(dir, key, &contents) { return Ok(fps); }
+    }
 
     // normalize & fingerprint
-    let norm = normalize::normalize(&contents[..]);
-    let fps = fingerprint::fingerprint(norm, k, t);
+    let fps = match params.kgrams {
+        KgramMode::Chars => fingerprint::fingerprint(
+            normalize::normalize_with(&contents[..], &params.norm), params.k, params.t, params.hash_width)?,
+        KgramMode::Tokens => fingerprint::fingerprint_tokens(
+            normalize::normalize_with(&contents[..], &params.norm), params.k, params.t, params.hash_width)?,
+        KgramMode::Ast => ast::fingerprint(&contents[..], &params.norm, params.k, params.t, params.hash_width)?,
+    };
+
+    if let (Some(dir), Some(key)) = (cache_dir, &key) {
+        cache::store(dir, key, &contents, &fps)?;
+    }
 
     Ok(fps)
 }
 
 /// Construct a set of fingerprints to ignore by
 /// reading/normalizing/fingerprinting the given files
-/// k, t are fingerprint params
-pub fn make_ignore_set(ignore_dir: &Path, k: i32, t: i32) -> HashSet<i64> {
-    let ignore_paths = file_io::arr_files_in_dir(ignore_dir);
+/// (with the given params)
+pub fn make_ignore_set(ignore_dir: &Path, params: &FpParams) -> Result<HashSet<i64>> {
+    let ignore_paths = file_io::arr_files_in_dir(ignore_dir)?;
     let mut ignore_set = HashSet::new();
 
-    if ignore_paths.len() == 0 {
-        err!("no .arr files to ignore in `{}`", ignore_dir.display());
+    if ignore_paths.is_empty() {
+        return Err(MossError::NoIgnoreFiles(ignore_dir.to_path_buf()));
     }
 
     for path in ignore_paths.iter() {
         // normalize/fingerprint this ignore file
-        let fps = match analyze_file(path, k, t) {
-            Ok(v) => v,
-            Err(e) => { err!("failed to analyze file {}: {}", path.display(), e); }
-        };
+        let fps = analyze_file(path, params, None)?;
 
         // add all fingerprint hashes to ignore set
         for fp in fps.iter() { ignore_set.insert(fp.hash); }
     }
 
-    ignore_set
+    Ok(ignore_set)
+}
+
+/// Read/normalize/fingerprint each of the given files, using up to `jobs`
+/// threads. Results are in the same order as the paths, regardless of
+/// which thread produced them
+fn analyze_files(paths: &[PathBuf], params: &FpParams, jobs: usize,
+    cache_dir: Option<&Path>) -> Vec<Result<Vec<Fingerprint>>> {
+    // no use in having more threads than files
+    let jobs = jobs.clamp(1, paths.len().max(1));
+
+    if jobs == 1 {
+        return paths.iter().map(|p| analyze_file(p, params, cache_dir)).collect();
+    }
+
+    let next = AtomicUsize::new(0);     // index of the next path to be claimed by a thread
+    let mut results: Vec<Option<Result<Vec<Fingerprint>>>> = paths.iter().map(|_| None).collect();
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs).map(|_| {
+            scope.spawn(|| {
+                let mut done = Vec::new();
+
+                // claim & analyze files until there are none left
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= paths.len() { break; }
+
+                    done.push((i, analyze_file(&paths[i], params, cache_dir)));
+                }
+
+                done
+            })
+        }).collect();
+
+        // put each result back in the position of its path
+        for worker in workers {
+            let done = worker.join().expect("fingerprinting thread panicked");
+            for (i, result) in done { results[i] = Some(result); }
+        }
+    });
+
+    results.into_iter()
+        .map(|r| r.expect("every file is analyzed by some thread"))
+        .collect()
 }
 
 /// Read/normalize/fingerprint documents in given submissions, constructing
 /// a hashmap from fingerprint hashes to the set of subs that share that hash
-pub fn analyze_subs<'a>(subs: &'a mut Vec<&'a mut Sub>, ignore: Option<HashSet<i64>>,
-    k: i32, t: i32, verbose: bool) -> FnvHashMap<i64, HashSet<&'a Sub>> {
+/// (see process_subs & map_hashes)
+pub fn analyze_subs<'a>(subs: &'a mut [Sub], ignore: Option<HashSet<i64>>, params: &FpParams,
+    jobs: usize, cache_dir: Option<&Path>, verbose: bool) -> Result<FnvHashMap<i64, HashSet<&'a Sub>>> {
+    process_subs(subs, ignore, params, jobs, cache_dir, verbose)?;
+    Ok(map_hashes(subs))
+}
+
+/// Read/normalize/fingerprint documents in given submissions, making them all Processed.
+/// Documents that are already Processed keep their fingerprints (less any ignored).
+/// Documents are processed on up to `jobs` threads; the resulting fingerprints and
+/// any logging do not depend on the number of jobs. Fingerprints are
+/// cached in cache_dir, if given
+pub fn process_subs(subs: &mut [Sub], ignore: Option<HashSet<i64>>, params: &FpParams,
+    jobs: usize, cache_dir: Option<&Path>, verbose: bool) -> Result<()> {
     if verbose {
         println!("\nAnalyzing all submission content...");
-        println!("noise threshold = {} chars", k);
-        println!("guarantee threshold = {} chars", t);
-        println!("window size = {} fingerprints", t - k + 1);
+        let unit = match params.kgrams {
+            KgramMode::Chars => "chars",
+            KgramMode::Tokens | KgramMode::Ast => "tokens",
+        };
+
+        println!("noise threshold = {} {}", params.k, unit);
+        println!("guarantee threshold = {} {}", params.t, unit);
+        println!("window size = {} fingerprints", params.t - params.k + 1);
+        println!("normalization = {}", params.norm);
+
+        if jobs > 1 { println!("using {} jobs", jobs); }
 
         if let Some(ref set) = ignore {
             println!("ignoring {} fingerprints", set.len());
         }
+
+        if let Some(dir) = cache_dir {
+            println!("caching fingerprints in {}", dir.display());
+        }
     }
 
-    let mut fp_to_subs = FnvHashMap::default();
+    if let Some(dir) = cache_dir { cache::init(dir)?; }
+
+    // gather the paths of all documents that need to be read, in order
+    let mut doc_paths = Vec::new();
+    for sub in subs.iter() {
+        for doc in sub.documents.iter() {
+            if let Doc::Unprocessed(p) = doc { doc_paths.push(p.to_path_buf()); }
+        }
+    }
+
+    // normalize/fingerprint every document up front (possibly concurrently)
+    let mut doc_fps = analyze_files(&doc_paths, params, jobs, cache_dir).into_iter();
 
     // for each submission
     for sub in subs.iter_mut() {
@@ -76,19 +234,15 @@ pub fn analyze_subs<'a>(subs: &'a mut Vec<&'a mut Sub>, ignore: Option<HashSet<i
             }
         }
 
-        let mut sub_fps = HashSet::new();
-
         // for each document in this submission
         for doc in sub.documents.iter_mut() {
-            let doc_path = match doc {
-                Doc::Unprocessed(p) => p,
-                Doc::Processed(_, _) => panic!("Already processed document: {:?}", doc),
-            };
-
-            // attempt to normalize/fingerprint document
-            let fps = match analyze_file(doc_path, k, t) {
-                Ok(v) => v,
-                Err(e) => { err!("failed to analyze file {}: {}", doc_path.display(), e); }
+            let (doc_path, fps) = match doc {
+                // fingerprints of this document, in the same order as doc_paths
+                Doc::Unprocessed(p) => {
+                    (p.to_path_buf(), doc_fps.next().expect("one result per document")?)
+                },
+                // already fingerprinted (e.g. stored in a database), so reuse them
+                Doc::Processed(p, fps) => (p.to_path_buf(), std::mem::take(fps)),
             };
 
             let orig_amount_fps = fps.len();    // store original # fingerprints before ignore
@@ -111,18 +265,29 @@ pub fn analyze_subs<'a>(subs: &'a mut Vec<&'a mut Sub>, ignore: Option<HashSet<i
                     orig_amount_fps - fp_count);
             }
 
-            // add included fingerprints for this doc to the set for this submission
-            for fp in fps.iter() { sub_fps.insert(fp.clone()); }
-
             // update Doc at this position to include fingerprints
-            *doc = Doc::Processed(doc_path.to_path_buf(), fps);
+            *doc = Doc::Processed(doc_path, fps);
         }
+    }
 
-        for fp in sub_fps.iter() {
-            // add this sub to the vec of subs that share this fingerprint
-            fp_to_subs.entry(fp.hash)
-                .or_insert_with(HashSet::new)
-                .insert(&**sub);  // sub is &mut&mut Sub, so this converts to &Sub
+    Ok(())
+}
+
+/// Construct a hashmap from the fingerprint hashes of the Processed
+/// documents in given submissions to the set of subs that share that hash
+pub fn map_hashes(subs: &[Sub]) -> FnvHashMap<i64, HashSet<&Sub>> {
+    let mut fp_to_subs = FnvHashMap::default();
+
+    for sub in subs.iter() {
+        for doc in sub.documents.iter() {
+            if let Doc::Processed(_, fps) = doc {
+                for fp in fps.iter() {
+                    // add this sub to the set of subs that share this fingerprint
+                    fp_to_subs.entry(fp.hash)
+                        .or_insert_with(HashSet::new)
+                        .insert(sub);
+                }
+            }
         }
     }
 
@@ -130,39 +295,165 @@ pub fn analyze_subs<'a>(subs: &'a mut Vec<&'a mut Sub>, ignore: Option<HashSet<i
 }
 
 
+/// Remove every hash shared by more than max_subs submissions from the (Processed)
+/// documents of the given submissions, so that such (boilerplate) fingerprints never
+/// pair submissions up, nor count toward their match percentages. Returns the number
+/// of hashes removed
+pub fn suppress_frequent(subs: &mut [Sub], max_subs: usize, verbose: bool) -> usize {
+    let mut counts: FnvHashMap<i64, usize> = FnvHashMap::default();
+    for sub in subs.iter() {
+        let sub_hashes: HashSet<i64> = sub.documents.iter()
+            .flat_map(|doc| match doc {
+                Doc::Processed(_, fps) => fps.iter().map(|fp| fp.hash).collect(),
+                Doc::Unprocessed(_) => Vec::new(),
+            })
+            .collect();
+
+        for hash in sub_hashes { *counts.entry(hash).or_insert(0) += 1; }
+    }
+
+    let frequent: HashSet<i64> = counts.into_iter()
+        .filter(|&(_, count)| count > max_subs)
+        .map(|(hash, _)| hash)
+        .collect();
+
+    if !frequent.is_empty() {
+        for doc in subs.iter_mut().flat_map(|sub| sub.documents.iter_mut()) {
+            if let Doc::Processed(_, fps) = doc { fps.retain(|fp| !frequent.contains(&fp.hash)); }
+        }
+    }
+
+    if verbose {
+        println!("suppressed {} fingerprints shared by more than {} submissions", frequent.len(), max_subs);
+    }
+
+    frequent.len()
+}
+
+/// Read/normalize a file, pairing the hash of each of its fingerprints with
+/// the text of the k-gram (or subtree) it was made from (according to params)
+fn kgram_texts(path: &Path, params: &FpParams) -> Result<Vec<(i64, String)>> {
+    let contents = read_file(path)?;
+
+    match params.kgrams {
+        KgramMode::Chars | KgramMode::Tokens => fingerprint::kgram_texts(
+            &normalize::normalize_with(&contents[..], &params.norm), params.k, params.t,
+            params.kgrams, params.hash_width),
+        KgramMode::Ast => ast::subtree_texts(&contents[..], &params.norm, params.k, params.t,
+            params.hash_width),
+    }
+}
+
+/// Verify that the hashes shared between submissions stand for equal k-grams, by
+/// rereading the documents of every submission that shares a hash & comparing the
+/// normalized text of their k-grams. Returns the hashes which stand for different
+/// k-grams in different submissions (collisions), to be discounted when pairing.
+/// Documents which can no longer be read (or have changed since they were
+/// fingerprinted) can't be verified, so their hashes are trusted.
+pub fn verify_hashes<'a>(hash_to_subs: &FnvHashMap<i64, HashSet<&'a Sub>>, params: &FpParams,
+    verbose: bool) -> Result<Collisions<'a>> {
+    if verbose { println!("\nVerifying shared fingerprints..."); }
+
+    let shared = |hash: &i64| hash_to_subs.get(hash).is_some_and(|subs| subs.len() > 1);
+
+    // every submission that shares some hash
+    let mut sharing_subs: Vec<&'a Sub> = Vec::new();
+    let mut seen: HashSet<&'a Sub> = HashSet::new();
+    for subs in hash_to_subs.values().filter(|subs| subs.len() > 1) {
+        for &sub in subs.iter() {
+            if seen.insert(sub) { sharing_subs.push(sub); }
+        }
+    }
+
+    // for each shared hash, the subs sharing each of the texts it stands for
+    let mut hash_to_texts: FnvHashMap<i64, FnvHashMap<String, HashSet<&'a Sub>>> = FnvHashMap::default();
+
+    for &sub in sharing_subs.iter() {
+        for doc in sub.documents.iter() {
+            let (path, fps) = match doc {
+                Doc::Processed(p, fps) => (p, fps),
+                Doc::Unprocessed(p) => return Err(MossError::UnprocessedDoc(p.to_path_buf())),
+            };
+
+            if !fps.iter().any(|fp| shared(&fp.hash)) { continue; }
+
+            let texts = match kgram_texts(path, params) {
+                Ok(texts) => texts,
+                Err(e) => {
+                    if verbose { println!("\tcan't verify {} ({})", path.display(), e); }
+                    continue;
+                },
+            };
+
+            for (hash, text) in texts.into_iter().filter(|(hash, _)| shared(hash)) {
+                hash_to_texts.entry(hash).or_default()
+                    .entry(text).or_default()
+                    .insert(sub);
+            }
+        }
+    }
+
+    // hashes standing for more than one text are collisions
+    let mut collisions: Collisions<'a> = FnvHashMap::default();
+
+    for (hash, texts) in hash_to_texts.into_iter().filter(|(_, texts)| texts.len() > 1) {
+        let subs = &hash_to_subs[&hash];
+
+        // subs with no text for this hash couldn't be verified, so they belong to every group
+        let unverified: Vec<&'a Sub> = subs.iter()
+            .filter(|sub| !texts.values().any(|group| group.contains(*sub)))
+            .cloned()
+            .collect();
+
+        let groups = texts.into_values()
+            .map(|mut group| { group.extend(unverified.iter().cloned()); group })
+            .collect();
+
+        collisions.insert(hash, groups);
+    }
+
+    if verbose {
+        println!("{} of {} shared fingerprints stand for different k-grams in different submissions",
+            collisions.len(), hash_to_subs.values().filter(|subs| subs.len() > 1).count());
+    }
+
+    Ok(collisions)
+}
+
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod tests {
     use super::*;
     use std::path::PathBuf;
 
     #[test]
-    fn test_analyze_file() -> io::Result<()> {
+    fn test_analyze_file() -> Result<()> {
         let dir = "./test-dirs/test/read-and-fingerprint/";
 
         {
             let exp_fps = vec![
-                Fingerprint { hash: 1718972022, lines: (2, 2) },
-                Fingerprint { hash: 1853237366, lines: (2, 2) },
-                Fingerprint { hash: 678832442, lines: (2, 2) },
-                Fingerprint { hash: 691697194, lines: (2, 3) },
-                Fingerprint { hash: 712402286, lines: (3, 4) }];
+                Fingerprint { hash: 1718972022, lines: (2, 2), cols: (1, 10) },
+                Fingerprint { hash: 1853237366, lines: (2, 2), cols: (3, 12) },
+                Fingerprint { hash: 678832442, lines: (2, 2), cols: (11, 34) },
+                Fingerprint { hash: 691697194, lines: (2, 3), cols: (23, 7) },
+                Fingerprint { hash: 712402286, lines: (3, 4), cols: (7, 2) }];
 
             // k=4, t=6
             let out_fps = analyze_file(
-                &Path::new(&format!("{}{}", dir, "a.arr")), 4, 6)?;
+                &Path::new(&format!("{}{}", dir, "a.arr")), &FpParams::new(4, 6), None)?;
 
             assert_eq!(exp_fps, out_fps);
         }
         {
             let exp_fps = vec![
-                Fingerprint { hash: 1684351798, lines: (1, 3) },
-                Fingerprint { hash: 711221822, lines: (1, 3) },
-                Fingerprint { hash: 981235476, lines: (3, 4) },
-                Fingerprint { hash: 678832721, lines: (4, 5) }];
+                Fingerprint { hash: 1684351798, lines: (1, 3), cols: (5, 1) },
+                Fingerprint { hash: 711221822, lines: (1, 3), cols: (7, 3) },
+                Fingerprint { hash: 981235476, lines: (3, 4), cols: (6, 14) },
+                Fingerprint { hash: 678832721, lines: (4, 5), cols: (7, 1) }];
 
             // k=5, t=10
             let out_fps = analyze_file(
-                &Path::new(&format!("{}{}", dir, "b.arr")), 5, 10)?;
+                &Path::new(&format!("{}{}", dir, "b.arr")), &FpParams::new(5, 10), None)?;
 
             assert_eq!(exp_fps, out_fps);
         }
@@ -170,10 +461,37 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_analyze_file_cached() {
+        let path = Path::new("./test-dirs/test/read-and-fingerprint/a.arr");
+        let cache_dir = std::env::temp_dir()
+            .join(format!("pyret-moss-phase-i-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        cache::init(&cache_dir).unwrap();
+
+        // a miss fingerprints the file as usual, & fills the cache
+        let fps = analyze_file(path, &FpParams::new(4, 6), Some(&cache_dir)).unwrap();
+        assert_eq!(fps, analyze_file(path, &FpParams::new(4, 6), None).unwrap());
+
+        let contents = std::fs::read_to_string(path).unwrap();
+        let key = cache::cache_key(&contents, &FpParams::new(4, 6));
+        assert_eq!(cache::load(&cache_dir, &key, &contents), Some(fps));
+
+        // a hit is served from the cache without refingerprinting
+        let planted = vec![Fingerprint { hash: 1, lines: (1, 1), cols: (1, 1) }];
+        cache::store(&cache_dir, &key, &contents, &planted).unwrap();
+        assert_eq!(analyze_file(path, &FpParams::new(4, 6), Some(&cache_dir)).unwrap(), planted);
+
+        // other params are cached separately
+        assert_ne!(analyze_file(path, &FpParams::new(4, 7), Some(&cache_dir)).unwrap(), planted);
+
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
     #[test]
     fn test_ignore_set() {
         {
-            let ignore = make_ignore_set(&Path::new("test-dirs/test/ignore"), 10, 25);
+            let ignore = make_ignore_set(&Path::new("test-dirs/test/ignore"), &FpParams::new(10, 25)).unwrap();
 
             let exp_set: HashSet<i64> = [
                 // ignore1.arr
@@ -190,7 +508,7 @@ mod tests {
             assert_eq!(ignore, exp_set);
         }
         {
-            let ignore = make_ignore_set(&Path::new("test-dirs/test/ignore"), 6, 10);
+            let ignore = make_ignore_set(&Path::new("test-dirs/test/ignore"), &FpParams::new(6, 10)).unwrap();
 
             let exp_set: HashSet<i64> = [
                 // ignore1.arr
@@ -222,47 +540,51 @@ mod tests {
     #[test]
     fn test_analyze_single_files() {
         // original submissions
-        let mut sub1 = Sub {
+        let sub1 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/single-file/sub1.arr"))
             ]
         };
-        let mut sub2 = Sub {
+        let sub2 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/single-file/sub2.arr"))
             ]
         };
 
-        let mut submissions = vec![&mut sub1, &mut sub2];
-        let out = analyze_subs(&mut submissions, None, 10, 60, false);
+        let mut submissions = vec![sub1, sub2];
+        let out = analyze_subs(&mut submissions, None, &FpParams::new(10, 60), 1, None, false).unwrap();
 
         // submissions after analysis
         let proc_sub1 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Processed(
                     PathBuf::from("test-dirs/test/single-file/sub1.arr"),
                     vec![
-                        Fingerprint { hash: 5421077, lines: (11, 12) },
-                        Fingerprint { hash: 31722361, lines: (15, 16) },
-                        Fingerprint { hash: 30182096, lines: (16, 16) },
-                        Fingerprint { hash: 14933625, lines: (17, 18) },
-                        Fingerprint { hash: 73943364, lines: (19, 19) }
+                        Fingerprint { hash: 5421077, lines: (11, 12), cols: (23, 7) },
+                        Fingerprint { hash: 31722361, lines: (15, 16), cols: (30, 28) },
+                        Fingerprint { hash: 30182096, lines: (16, 16), cols: (31, 44) },
+                        Fingerprint { hash: 14933625, lines: (17, 18), cols: (27, 28) },
+                        Fingerprint { hash: 73943364, lines: (19, 19), cols: (35, 46) }
                     ])
             ]
         };
 
         let proc_sub2 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Processed(
                     PathBuf::from("test-dirs/test/single-file/sub2.arr"),
                     vec![
-                        Fingerprint { hash: 5421077, lines: (8, 10) },
-                        Fingerprint { hash: 14933625, lines: (13, 14) },
-                        Fingerprint { hash: 73943364, lines: (15, 15) }
+                        Fingerprint { hash: 5421077, lines: (8, 10), cols: (23, 7) },
+                        Fingerprint { hash: 14933625, lines: (13, 14), cols: (27, 28) },
+                        Fingerprint { hash: 73943364, lines: (15, 15), cols: (35, 46) }
                     ])
             ]
         };
@@ -281,70 +603,74 @@ mod tests {
     #[test]
     fn test_analyze_multi_files() {
         // original submissions
-        let mut sub1 = Sub {
+        let sub1 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file/sub1")),
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file/sub1/common.arr")),
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file/sub1/main.arr"))
             ]
         };
-        let mut sub2 = Sub {
+        let sub2 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file/sub2")),
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file/sub2/common.arr")),
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file/sub2/main.arr"))
             ]
         };
 
-        let mut submissions = vec![&mut sub1, &mut sub2];
-        let out = analyze_subs(&mut submissions, None, 5, 15, false);
+        let mut submissions = vec![sub1, sub2];
+        let out = analyze_subs(&mut submissions, None, &FpParams::new(5, 15), 1, None, false).unwrap();
 
         // submissions after analysis
         let proc_sub1 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file/sub1")),
+            archived: false,
             documents: vec![
                 Doc::Processed(
                     PathBuf::from("test-dirs/test/multi-file/sub1/common.arr"),
                     vec![
-                        Fingerprint { hash: 712012601, lines: (1, 2) },
-                        Fingerprint { hash: 762608186, lines: (2, 2) },
-                        Fingerprint { hash: 711221850, lines: (2, 5) },
-                        Fingerprint { hash: 678833506, lines: (7, 7) }
+                        Fingerprint { hash: 712012601, lines: (1, 2), cols: (7, 3) },
+                        Fingerprint { hash: 762608186, lines: (2, 2), cols: (7, 11) },
+                        Fingerprint { hash: 711221850, lines: (2, 5), cols: (13, 3) },
+                        Fingerprint { hash: 678833506, lines: (7, 7), cols: (4, 27) }
                     ]),
                 Doc::Processed(
                     PathBuf::from("test-dirs/test/multi-file/sub1/main.arr"),
                     vec![
-                        Fingerprint { hash: 711358008, lines: (1, 3) },
-                        Fingerprint { hash: 678832678, lines: (3, 3) },
-                        Fingerprint { hash: 691697430, lines: (3, 5) },
-                        Fingerprint { hash: 712407124, lines: (5, 6) },
-                        Fingerprint { hash: 674572885, lines: (7, 7) },
-                        Fingerprint { hash: 674703957, lines: (8, 8) },
-                        Fingerprint { hash: 674050581, lines: (9, 9) }
+                        Fingerprint { hash: 711358008, lines: (1, 3), cols: (7, 3) },
+                        Fingerprint { hash: 678832678, lines: (3, 3), cols: (5, 35) },
+                        Fingerprint { hash: 691697430, lines: (3, 5), cols: (13, 4) },
+                        Fingerprint { hash: 712407124, lines: (5, 6), cols: (2, 2) },
+                        Fingerprint { hash: 674572885, lines: (7, 7), cols: (2, 13) },
+                        Fingerprint { hash: 674703957, lines: (8, 8), cols: (2, 13) },
+                        Fingerprint { hash: 674050581, lines: (9, 9), cols: (2, 12) }
                     ])
             ]
         };
         let proc_sub2 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file/sub2")),
+            archived: false,
             documents: vec![
                 Doc::Processed(
                     PathBuf::from("test-dirs/test/multi-file/sub2/common.arr"),
                     vec![
-                        Fingerprint { hash: 711221822, lines: (1, 3) },
-                        Fingerprint { hash: 678833506, lines: (8, 8) }
+                        Fingerprint { hash: 711221822, lines: (1, 3), cols: (7, 3) },
+                        Fingerprint { hash: 678833506, lines: (8, 8), cols: (4, 41) }
                     ]),
                 Doc::Processed(
                     PathBuf::from("test-dirs/test/multi-file/sub2/main.arr"),
                     vec![
-                        Fingerprint { hash: 711358008, lines: (1, 4) },
-                        Fingerprint { hash: 678832678, lines: (4, 4) },
-                        Fingerprint { hash: 691697430, lines: (4, 6) },
-                        Fingerprint { hash: 712402522, lines: (6, 7) },
-                        Fingerprint { hash: 980822283, lines: (9, 10) },
-                        Fingerprint { hash: 674572885, lines: (10, 10) },
-                        Fingerprint { hash: 674703957, lines: (11, 11) },
-                        Fingerprint { hash: 674050581, lines: (12, 12) },
-                        Fingerprint { hash: 674376277, lines: (13, 13) }
+                        Fingerprint { hash: 711358008, lines: (1, 4), cols: (7, 3) },
+                        Fingerprint { hash: 678832678, lines: (4, 4), cols: (5, 38) },
+                        Fingerprint { hash: 691697430, lines: (4, 6), cols: (24, 6) },
+                        Fingerprint { hash: 712402522, lines: (6, 7), cols: (2, 2) },
+                        Fingerprint { hash: 980822283, lines: (9, 10), cols: (5, 21) },
+                        Fingerprint { hash: 674572885, lines: (10, 10), cols: (2, 24) },
+                        Fingerprint { hash: 674703957, lines: (11, 11), cols: (2, 24) },
+                        Fingerprint { hash: 674050581, lines: (12, 12), cols: (2, 23) },
+                        Fingerprint { hash: 674376277, lines: (13, 13), cols: (2, 24) }
                     ])
             ]
         };
@@ -372,15 +698,17 @@ mod tests {
     #[test]
     fn test_analyze_with_ignore() {
         // original submissions
-        let mut sub1 = Sub {
+        let sub1 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file/sub1")),
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file/sub1/common.arr")),
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file/sub1/main.arr"))
             ]
         };
-        let mut sub2 = Sub {
+        let sub2 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file/sub2")),
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file/sub2/common.arr")),
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file/sub2/main.arr"))
@@ -397,46 +725,48 @@ mod tests {
             980822283
         ].iter().cloned().collect();
 
-        let mut submissions = vec![&mut sub1, &mut sub2];
-        let out = analyze_subs(&mut submissions, Some(ignore_set), 5, 15, false);
+        let mut submissions = vec![sub1, sub2];
+        let out = analyze_subs(&mut submissions, Some(ignore_set), &FpParams::new(5, 15), 1, None, false).unwrap();
 
         // submissions after analysis
         let proc_sub1 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file/sub1")),
+            archived: false,
             documents: vec![
                 Doc::Processed(
                     PathBuf::from("test-dirs/test/multi-file/sub1/common.arr"),
                     vec![
-                        Fingerprint { hash: 712012601, lines: (1, 2) },
-                        Fingerprint { hash: 762608186, lines: (2, 2) },
-                        Fingerprint { hash: 678833506, lines: (7, 7) }
+                        Fingerprint { hash: 712012601, lines: (1, 2), cols: (7, 3) },
+                        Fingerprint { hash: 762608186, lines: (2, 2), cols: (7, 11) },
+                        Fingerprint { hash: 678833506, lines: (7, 7), cols: (4, 27) }
                     ]),
                 Doc::Processed(
                     PathBuf::from("test-dirs/test/multi-file/sub1/main.arr"),
                     vec![
-                        Fingerprint { hash: 712407124, lines: (5, 6) },
-                        Fingerprint { hash: 674572885, lines: (7, 7) },
-                        Fingerprint { hash: 674703957, lines: (8, 8) },
-                        Fingerprint { hash: 674050581, lines: (9, 9) }
+                        Fingerprint { hash: 712407124, lines: (5, 6), cols: (2, 2) },
+                        Fingerprint { hash: 674572885, lines: (7, 7), cols: (2, 13) },
+                        Fingerprint { hash: 674703957, lines: (8, 8), cols: (2, 13) },
+                        Fingerprint { hash: 674050581, lines: (9, 9), cols: (2, 12) }
                     ])
             ]
         };
         let proc_sub2 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file/sub2")),
+            archived: false,
             documents: vec![
                 Doc::Processed(
                     PathBuf::from("test-dirs/test/multi-file/sub2/common.arr"),
                     vec![
-                        Fingerprint { hash: 678833506, lines: (8, 8) }
+                        Fingerprint { hash: 678833506, lines: (8, 8), cols: (4, 41) }
                     ]),
                 Doc::Processed(
                     PathBuf::from("test-dirs/test/multi-file/sub2/main.arr"),
                     vec![
-                        Fingerprint { hash: 712402522, lines: (6, 7) },
-                        Fingerprint { hash: 674572885, lines: (10, 10) },
-                        Fingerprint { hash: 674703957, lines: (11, 11) },
-                        Fingerprint { hash: 674050581, lines: (12, 12) },
-                        Fingerprint { hash: 674376277, lines: (13, 13) }
+                        Fingerprint { hash: 712402522, lines: (6, 7), cols: (2, 2) },
+                        Fingerprint { hash: 674572885, lines: (10, 10), cols: (2, 24) },
+                        Fingerprint { hash: 674703957, lines: (11, 11), cols: (2, 24) },
+                        Fingerprint { hash: 674050581, lines: (12, 12), cols: (2, 23) },
+                        Fingerprint { hash: 674376277, lines: (13, 13), cols: (2, 24) }
                     ])
             ]
         };
@@ -455,4 +785,96 @@ mod tests {
         assert_eq!(out, exp_out);
     }
 
+    #[test]
+    fn test_analyze_parallel() {
+        let sub_dir = Path::new("test-dirs/test/multi-file");
+        let mut serial_subs = file_io::construct_subs(sub_dir, &crate::cli::SubFileMode::Multi,
+            &HashSet::new(), false).unwrap();
+        let mut parallel_subs = serial_subs.clone();
+
+        let serial = analyze_subs(&mut serial_subs, None, &FpParams::new(5, 15), 1, None, false).unwrap();
+
+        // more jobs than documents, as well as fewer
+        for &jobs in [3, 16].iter() {
+            let mut subs = parallel_subs.clone();
+            let parallel = analyze_subs(&mut subs, None, &FpParams::new(5, 15), jobs, None, false).unwrap();
+
+            assert_eq!(serial, parallel);
+        }
+
+        // an unreadable document is reported the same way
+        parallel_subs[0].documents.push(Doc::Unprocessed(PathBuf::from("not/a/real/file.arr")));
+        let res = analyze_subs(&mut parallel_subs, None, &FpParams::new(5, 15), 4, None, false);
+        assert!(matches!(res, Err(MossError::Io(_, _))));
+    }
+
+    #[test]
+    fn test_max_frequency_limit() {
+        assert_eq!(MaxFrequency::Count(3).limit(100), 3);
+        assert_eq!(MaxFrequency::Percent(10.0).limit(25), 2);
+        assert_eq!(MaxFrequency::Percent(100.0).limit(25), 25);
+    }
+
+    #[test]
+    fn test_suppress_frequent() {
+        let mut subs: Vec<Sub> = vec![vec![1, 2], vec![1, 2], vec![1, 3]].into_iter().enumerate()
+            .map(|(i, hashes)| Sub {
+                dir_name: Some(PathBuf::from(format!("sub{}", i))),
+                archived: false,
+                documents: vec![Doc::Processed(PathBuf::from(format!("sub{}/main.arr", i)),
+                    hashes.into_iter().map(|hash| Fingerprint { hash, lines: (1, 1), cols: (1, 1) }).collect())]
+            })
+            .collect();
+
+        // 1 is in every sub, 2 in the first two, & 3 only in the last
+        assert_eq!(suppress_frequent(&mut subs, 3, false), 0);
+        assert_eq!(map_hashes(&subs).len(), 3);
+
+        // only the hash shared by all three submissions is dropped, from documents & so the map
+        assert_eq!(suppress_frequent(&mut subs, 2, false), 1);
+        let hash_to_subs = map_hashes(&subs);
+        assert!(!hash_to_subs.contains_key(&1));
+        assert_eq!(hash_to_subs.len(), 2);
+        assert!(subs.iter().all(|sub| match &sub.documents[0] {
+            Doc::Processed(_, fps) => fps.iter().all(|fp| fp.hash != 1),
+            Doc::Unprocessed(_) => false,
+        }));
+    }
+
+    #[test]
+    fn test_verify_hashes() {
+        use crate::normalize::IdentifierMode;
+
+        // the 5-grams "abcde" & "bbcdc" share a 31-bit hash, but not a 64-bit one
+        let sub_dir = Path::new("test-dirs/test/collision");
+        let norm = NormOptions { identifiers: IdentifierMode::Preserved, ..NormOptions::default() };
+        let params = FpParams { norm, ..FpParams::new(5, 5) };
+
+        let mut subs = file_io::construct_subs(sub_dir, &crate::cli::SubFileMode::Single,
+            &HashSet::new(), false).unwrap();
+        let hash_to_subs = analyze_subs(&mut subs, None, &params, 1, None, false).unwrap();
+        let shared: Vec<&i64> = hash_to_subs.iter()
+            .filter(|(_, subs)| subs.len() > 1)
+            .map(|(hash, _)| hash)
+            .collect();
+        assert_eq!(shared.len(), 1);
+
+        // the shared hash stands for a different k-gram in each sub
+        let collisions = verify_hashes(&hash_to_subs, &params, false).unwrap();
+        let (hash, groups) = collisions.iter().next().unwrap();
+        assert_eq!(shared, vec![hash]);
+        assert_eq!(groups.len(), 2);
+        assert!(groups.iter().all(|group| group.len() == 1));
+
+        let wide = FpParams { hash_width: HashWidth::Bits64, ..params };
+        let mut subs = file_io::construct_subs(sub_dir, &crate::cli::SubFileMode::Single,
+            &HashSet::new(), false).unwrap();
+        assert_eq!(analyze_subs(&mut subs, None, &wide, 1, None, false).unwrap().len(), 4);
+
+        // genuinely shared hashes are not collisions
+        let mut subs = file_io::construct_subs(Path::new("test-dirs/test/multi-file"),
+            &crate::cli::SubFileMode::Multi, &HashSet::new(), false).unwrap();
+        let hash_to_subs = analyze_subs(&mut subs, None, &FpParams::new(5, 15), 1, None, false).unwrap();
+        assert!(verify_hashes(&hash_to_subs, &FpParams::new(5, 15), false).unwrap().is_empty());
+    }
 }
@@ -1,11 +1,13 @@
-/// Phase II: Determine pairs of submission with significant fingerprint overlap
+//! Phase II: Determine pairs of submission with significant fingerprint overlap
 
+use std::cmp::Ordering;
 use std::collections::HashSet;
-use std::collections::HashMap;
-use std::collections::BTreeSet;
+use std::path::Path;
 use fnv::FnvHashMap;
-use std::iter::FromIterator;
 use crate::{Doc, Sub};
+use crate::error::{MossError, Result};
+use crate::lsh::{self, LshParams};
+use crate::phase_i::Collisions;
 
 /// A SubPair represents an unordered pair of Subs with overlapping hashes, where
 /// each element X in the pair has a "percent" value, which is equal to the quotient of the size
@@ -18,7 +20,21 @@ pub struct SubPair<'a> {
     pub b: &'a Sub,
     pub b_percent: f64,
     pub matches: HashSet<i64>,
-    pub percentile: f64
+    pub percentile: f64,
+    pub doc_pairs: Vec<DocPair>
+}
+
+/// A DocPair represents the overlap between a document of each Sub in a SubPair
+/// (identified by their indices in the Subs' documents), where each document's
+/// "percent" value is the quotient of the number of matches between the two
+/// documents and the number of fingerprints contained in that document
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocPair {
+    pub a_doc: usize,
+    pub a_percent: f64,
+    pub b_doc: usize,
+    pub b_percent: f64,
+    pub matches: usize
 }
 
 /// two SubPairs are equal if they each contain references to the same two Subs
@@ -31,137 +47,191 @@ impl PartialEq for SubPair<'_> {
 
 impl Eq for SubPair<'_> {}
 
+/// The path identifying a submission: its dir (multi-file), or its first
+/// document (single-file), so that submissions can be ordered cheaply
+fn sub_path(sub: &Sub) -> Option<&Path> {
+    match (&sub.dir_name, sub.documents.first()) {
+        (Some(dir), _) => Some(dir),
+        (None, Some(Doc::Unprocessed(p))) | (None, Some(Doc::Processed(p, _))) => Some(p),
+        (None, None) => None,
+    }
+}
+
 /// Consider pairs of submissions that overlap, associate them with the
 /// fingerprints they share, calculate 'percent' values for each Sub in
 /// a Pair and a 'percentile' value for each SubPair, keep pairs with percentile
 /// greater than input threshold, order pairs by the quantity shared and return in tuple
-/// along with number of total subpairs found
-pub fn find_overlaps<'a>(hash_to_subs: &'a FnvHashMap<i64, HashSet<&Sub>>, threshold: f64,
-    verbose: bool) -> (Vec<SubPair<'a>>, usize) {
+/// along with number of total subpairs found. Pairs of two archived subs are never formed.
+/// If collisions are given (see phase_i::verify_hashes), a colliding hash is only shared
+/// by subs whose k-grams for it are equal. If LSH params are given, only the candidate
+/// pairs chosen by their MinHash signatures (see lsh::candidate_pairs) are considered
+/// (& counted in the total), which is approximate (dissimilar pairs may be missed) but
+/// scales to very large corpora
+pub fn find_overlaps<'a>(hash_to_subs: &FnvHashMap<i64, HashSet<&'a Sub>>,
+    collisions: Option<&Collisions<'a>>, lsh: Option<&LshParams>, threshold: f64,
+    verbose: bool) -> Result<(Vec<SubPair<'a>>, usize)> {
 
     // ensure 0 <= threshold <= 1
-    if (threshold < 0.0) || (threshold > 1.0) {
-        err!("The input percentile threshold must be between 0 and 1 inclusive.");
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(MossError::InvalidParams(
+            String::from("The input percentile threshold must be between 0 and 1 inclusive.")));
     }
 
     if verbose {
         println!("\nPairing submissions with overlap (threshold: {:.2}%)", threshold * 100.0);
     }
 
-    // a map whose keys are pairs (sets of size 2) of subs and whose values are sets of hashes
-    let mut pairs_to_hashes: HashMap<BTreeSet<&Sub>, HashSet<i64>> = HashMap::new();
-
-    // the highest HashSet<i64> size value in the pairs_to_hashes map, to be updated
-    let mut max_num_hashes: usize = 0;
-
-    // iterate through hash_to_subs by key
-    for (hash, subs) in hash_to_subs {
-        // if the current key has a value containing more than one entry, make pairs
-        let subs_len: usize = subs.len();
-        if subs_len > 1 {
-            // get all possible pairs of submissions within subs
-            let ordered_subs: Vec<&&Sub> = Vec::from_iter(subs.iter());
-            let mut i: usize = 0;
-
-            while i < (subs_len - 1) {
-                let mut j: usize = i + 1;
-                while j < (subs_len) {
-                    // the current pair of submissions, represented as an unordered set
-                    let mut sub_btset: BTreeSet<&Sub> = BTreeSet::new();
-                    sub_btset.insert(*ordered_subs[i]);
-                    sub_btset.insert(*ordered_subs[j]);
-
-                        // retrieve the size of the current value set, account for the
-                        // current element, which may or may not be added
-                        let mut num_hashes: usize = 0;
-                        let cur_val: Option<&HashSet<i64>> = pairs_to_hashes.get(&sub_btset);
-                        match cur_val {
-                            None => { num_hashes += 1; }
-                            Some(set) => {
-                                if set.contains(&hash) {
-                                    // the hash wont be added to the set
-                                    num_hashes = set.len();
-                                } else {
-                                    // the hash will be added to the set
-                                    num_hashes = set.len() + 1;
-                                }
-                            }
-                        }
-
-                        // update max_num_hashes if the size of the current value set is
-                        // larger than the current value
-                        if num_hashes > max_num_hashes {
-                            max_num_hashes = num_hashes;
-                        }
-
-                        // if sub_pair is not already a key in pairs_to_hashes, add it and
-                        // make it map to a set containing hash; otherwise, add hash to the
-                        // set sub_pair already maps to
-                        pairs_to_hashes.entry(sub_btset)
-                            .or_insert_with(HashSet::new)
-                            .insert(*hash);
-                    j += 1;
-                }
-                i += 1;
-            }
-        }
+    // identify each submission by its rank in order of path, so that pairs are formed
+    // without hashing or comparing whole submissions (& the lesser sub of a pair is a)
+    let mut subs: Vec<&'a Sub> = hash_to_subs.values().flatten().cloned().collect();
+    subs.sort_unstable_by_key(|sub| *sub as *const Sub);
+    subs.dedup_by_key(|sub| *sub as *const Sub);
+    subs.sort_by_key(|sub| sub_path(sub));
+
+    let ids: FnvHashMap<*const Sub, usize> = subs.iter().enumerate()
+        .map(|(id, sub)| (*sub as *const Sub, id))
+        .collect();
+    let to_ids = |group: &HashSet<&'a Sub>| -> Vec<usize> {
+        let mut group_ids: Vec<usize> = group.iter()
+            .filter_map(|sub| ids.get(&(*sub as *const Sub)).cloned())
+            .collect();
+        group_ids.sort_unstable();
+        group_ids
+    };
+
+    // sorted posting list of the subs containing each hash shared by 2+ subs,
+    // & the shared hashes contained in each sub
+    let mut postings: FnvHashMap<i64, Vec<usize>> = FnvHashMap::default();
+    let mut sub_hashes: Vec<Vec<i64>> = vec![Vec::new(); subs.len()];
+
+    for (hash, hash_subs) in hash_to_subs {
+        if hash_subs.len() < 2 { continue; }
+
+        let posting = to_ids(hash_subs);
+        for &id in posting.iter() { sub_hashes[id].push(*hash); }
+        postings.insert(*hash, posting);
     }
 
-    // iterate through pairs_to_hashes, add a SubPair corresponding to each key-value pair
-    // to the subpairs Vec, which will eventually be returned as output with numallpairs
-    let mut subpairs: Vec<SubPair> = Vec::new();
-    let numallpairs: usize = pairs_to_hashes.len();
-
-    for (sub_btset, matching_hashes) in pairs_to_hashes {
-        let mut sub_btset_iter = sub_btset.iter();
-        let num_hashes: usize = matching_hashes.len();
-
-        // the two subs in the subpairs
-        let sub_a: &Sub = sub_btset_iter.next().unwrap();
-        let sub_b: &Sub = sub_btset_iter.next().unwrap();
-
-        // the set of unique fingerprint hash values in the Docs of sub_a
-        let mut all_fp_hashes_a: HashSet<i64> = HashSet::new();
-        for doc in &sub_a.documents {
-            match doc {
-                Doc::Unprocessed(pathbuf) => {
-                    err!("An Unprocessed Doc was found in {:?}", pathbuf);
-                    }
-                Doc::Processed(_pathbuf, fingerprints) => {
-                    for fp in fingerprints {
-                        all_fp_hashes_a.insert(fp.hash);
+    // if a hash stands for different k-grams, the (sorted ids of) subs sharing each one
+    let groups: FnvHashMap<i64, Vec<Vec<usize>>> = collisions.map(|c| {
+        c.iter().map(|(hash, hash_groups)| (*hash, hash_groups.iter().map(to_ids).collect())).collect()
+    }).unwrap_or_default();
+
+    // subs whose k-grams for a hash merely collided don't share it
+    let in_group = |hash: &i64, a: usize, b: usize| -> bool {
+        groups.get(hash).is_none_or(|hash_groups| hash_groups.iter()
+            .any(|g| g.binary_search(&a).is_ok() && g.binary_search(&b).is_ok()))
+    };
+
+    let mut pair_hashes: Vec<(usize, usize, Vec<i64>)> = Vec::new();
+
+    match lsh {
+        Some(params) => {
+            // sign each sub by the hashes it has that are considered at all (so
+            // not those suppressed or ignored), including ones no other sub has
+            let mut kept_hashes: Vec<Vec<i64>> = vec![Vec::new(); subs.len()];
+            for (hash, hash_subs) in hash_to_subs {
+                for sub in hash_subs.iter() { kept_hashes[ids[&(*sub as *const Sub)]].push(*hash); }
+            }
+            let signatures: Vec<Option<Vec<u64>>> = kept_hashes.iter()
+                .map(|hashes| lsh::signature(hashes, params.signature_len()))
+                .collect();
+
+            // only count the hashes shared by candidate pairs, by merging their sorted hashes
+            let candidates = lsh::candidate_pairs(&signatures, params);
+
+            if verbose { println!("{} candidate pairs chosen by LSH.", candidates.len()); }
+
+            for hashes in sub_hashes.iter_mut() { hashes.sort_unstable(); }
+
+            for (a, b) in candidates {
+                // archived submissions are only compared against current ones
+                if subs[a].archived && subs[b].archived { continue; }
+
+                let (hashes_a, hashes_b) = (&sub_hashes[a], &sub_hashes[b]);
+                let (mut i, mut j) = (0, 0);
+                let mut shared: Vec<i64> = Vec::new();
+
+                while i < hashes_a.len() && j < hashes_b.len() {
+                    match hashes_a[i].cmp(&hashes_b[j]) {
+                        Ordering::Less => i += 1,
+                        Ordering::Greater => j += 1,
+                        Ordering::Equal => {
+                            if in_group(&hashes_a[i], a, b) { shared.push(hashes_a[i]); }
+                            i += 1;
+                            j += 1;
+                        },
                     }
                 }
-            }
-        }
 
-        // the number of unique fingerprint hash values in the Docs of sub_b
-        let mut all_fp_hashes_b: HashSet<i64> = HashSet::new();
-        for doc in &sub_b.documents {
-            match doc {
-                Doc::Unprocessed(pathbuf) => {
-                    err!("An Unprocessed Doc was found in {:?}", pathbuf);
-                }
-                Doc::Processed(_pathbuf, fingerprints) => {
-                    for fp in fingerprints {
-                        all_fp_hashes_b.insert(fp.hash);
+                if !shared.is_empty() { pair_hashes.push((a, b, shared)); }
+            }
+        },
+        None => {
+            // for each sub a, gather the hashes it shares with every later sub b from the
+            // posting lists of its hashes. shared[b] accumulates a's hashes in common with b,
+            // & touched records which b's have any, so only those are visited & reset
+            let mut shared: Vec<Vec<i64>> = vec![Vec::new(); subs.len()];
+            let mut touched: Vec<usize> = Vec::new();
+
+            for a in 0..subs.len() {
+                for &hash in sub_hashes[a].iter() {
+                    let posting = &postings[&hash];
+
+                    for &b in posting[posting.partition_point(|&id| id <= a)..].iter() {
+                        // archived submissions are only compared against current ones
+                        if subs[a].archived && subs[b].archived { continue; }
+                        if !in_group(&hash, a, b) { continue; }
+
+                        if shared[b].is_empty() { touched.push(b); }
+                        shared[b].push(hash);
                     }
                 }
+
+                touched.sort_unstable();
+                for b in touched.drain(..) {
+                    pair_hashes.push((a, b, std::mem::take(&mut shared[b])));
+                }
             }
+        },
+    }
+
+    // the most hashes shared by any pair, against which percentiles are measured
+    let max_num_hashes: usize = pair_hashes.iter().map(|(_, _, hashes)| hashes.len()).max().unwrap_or(0);
+    let numallpairs: usize = pair_hashes.len();
+
+    // the sets of unique fingerprint hash values in the Docs of each sub, & the number
+    // of unique values across all of them, computed once per sub
+    let mut fp_hashes: Vec<Option<(usize, Vec<HashSet<i64>>)>> = vec![None; subs.len()];
+
+    // add a SubPair corresponding to each pair to the subpairs Vec, which will
+    // eventually be returned as output with numallpairs
+    let mut subpairs: Vec<SubPair<'a>> = Vec::new();
+
+    for (a, b, matching_hashes) in pair_hashes {
+        let num_hashes: usize = matching_hashes.len();
+
+        for id in [a, b] {
+            if fp_hashes[id].is_none() { fp_hashes[id] = Some(doc_hashes(subs[id])?); }
         }
+        let (num_fp_hashes_a, docs_a) = fp_hashes[a].as_ref().unwrap();
+        let (num_fp_hashes_b, docs_b) = fp_hashes[b].as_ref().unwrap();
 
         // the SubPair representing the current pair of subs, to be added to the output
         let percentile: f64 = (num_hashes as f64) / (max_num_hashes as f64);
 
         // only add the SubPair if its percentile >= threshold
         if percentile >= threshold {
+            let matches: HashSet<i64> = matching_hashes.into_iter().collect();
+
             let sp: SubPair = SubPair {
-                a: sub_a,
-                a_percent: (num_hashes as f64) / (all_fp_hashes_a.len() as f64),
-                b: sub_b,
-                b_percent: (num_hashes as f64) / (all_fp_hashes_b.len() as f64),
-                matches: matching_hashes,
-                percentile: percentile
+                a: subs[a],
+                a_percent: (num_hashes as f64) / (*num_fp_hashes_a as f64),
+                b: subs[b],
+                b_percent: (num_hashes as f64) / (*num_fp_hashes_b as f64),
+                doc_pairs: doc_pairs(docs_a, docs_b, &matches),
+                matches,
+                percentile
             };
 
             subpairs.push(sp);
@@ -174,7 +244,58 @@ pub fn find_overlaps<'a>(hash_to_subs: &'a FnvHashMap<i64, HashSet<&Sub>>, thres
     if verbose { println!("{} pairs identified.", subpairs.len()); }
 
     // return the populated, sorted output
-    (subpairs, numallpairs)
+    Ok((subpairs, numallpairs))
+}
+
+/// The set of unique fingerprint hash values in each Doc of a sub,
+/// along with the number of unique values across all Docs
+fn doc_hashes(sub: &Sub) -> Result<(usize, Vec<HashSet<i64>>)> {
+    let mut all_fp_hashes: HashSet<i64> = HashSet::new();
+    let mut docs: Vec<HashSet<i64>> = Vec::new();
+
+    for doc in &sub.documents {
+        match doc {
+            Doc::Unprocessed(pathbuf) => {
+                return Err(MossError::UnprocessedDoc(pathbuf.to_path_buf()));
+            }
+            Doc::Processed(_pathbuf, fingerprints) => {
+                let doc_fp_hashes: HashSet<i64> = fingerprints.iter().map(|fp| fp.hash).collect();
+                all_fp_hashes.extend(doc_fp_hashes.iter());
+                docs.push(doc_fp_hashes);
+            }
+        }
+    }
+
+    Ok((all_fp_hashes.len(), docs))
+}
+
+/// Determine the overlap between each Doc of sub a & each Doc of sub b (given their
+/// hash sets) within a pair sharing the given hashes. Document pairs without overlap
+/// are omitted, & the rest are ordered by descending number of matches
+fn doc_pairs(docs_a: &[HashSet<i64>], docs_b: &[HashSet<i64>], matches: &HashSet<i64>) -> Vec<DocPair> {
+    let mut doc_pairs = Vec::new();
+
+    for (a_doc, a_hashes) in docs_a.iter().enumerate() {
+        // only hashes the pair shares can match between their docs
+        let shared: Vec<i64> = a_hashes.iter().filter(|h| matches.contains(h)).cloned().collect();
+        if shared.is_empty() { continue; }
+
+        for (b_doc, b_hashes) in docs_b.iter().enumerate() {
+            let num_matches = shared.iter().filter(|h| b_hashes.contains(h)).count();
+            if num_matches == 0 { continue; }
+
+            doc_pairs.push(DocPair {
+                a_doc,
+                a_percent: (num_matches as f64) / (a_hashes.len() as f64),
+                b_doc,
+                b_percent: (num_matches as f64) / (b_hashes.len() as f64),
+                matches: num_matches
+            });
+        }
+    }
+
+    doc_pairs.sort_by_key(|dp| std::cmp::Reverse(dp.matches));
+    doc_pairs
 }
 
 #[cfg(test)]
@@ -182,7 +303,8 @@ mod tests {
     use super::*;
     use crate::fingerprint::Fingerprint;
     use crate::Doc::Processed;
-    use crate::phase_i::analyze_subs;
+    use crate::phase_i::{analyze_subs, FpParams};
+    use std::collections::BTreeSet;
     use std::path::PathBuf;
 
     #[test]
@@ -190,22 +312,24 @@ mod tests {
     // matched hashes are correctly recorded, and the percent values/percentile are correct
     fn test_single_pair_input() {
         // original submissions
-        let mut sub1 = Sub {
+        let sub1 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/single-file/sub1.arr"))
             ]
         };
-        let mut sub2 = Sub {
+        let sub2 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/single-file/sub2.arr"))
             ]
         };
 
-        let mut submissions = vec![&mut sub1, &mut sub2];
-        let inp_map = analyze_subs(&mut submissions, None, 10, 60, false);
-        let out = find_overlaps(&inp_map, 0.0, false);
+        let mut submissions = vec![sub1, sub2];
+        let inp_map = analyze_subs(&mut submissions, None, &FpParams::new(10, 60), 1, None, false).unwrap();
+        let out = find_overlaps(&inp_map, None, None, 0.0, false).unwrap();
 
         let mut exp_matches = HashSet::new();
         exp_matches.insert(5421077);
@@ -214,22 +338,24 @@ mod tests {
 
         let processed_sub1 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Processed(PathBuf::from("test-dirs/test/single-file/sub1.arr"), vec![
-                    Fingerprint { hash: 5421077, lines: (11, 12) },
-                    Fingerprint { hash: 31722361, lines: (15, 16) },
-                    Fingerprint { hash: 30182096, lines: (16, 16) },
-                    Fingerprint { hash: 14933625, lines: (17, 18) },
-                    Fingerprint { hash: 73943364, lines: (19, 19) }])]
+                    Fingerprint { hash: 5421077, lines: (11, 12), cols: (23, 7) },
+                    Fingerprint { hash: 31722361, lines: (15, 16), cols: (30, 28) },
+                    Fingerprint { hash: 30182096, lines: (16, 16), cols: (31, 44) },
+                    Fingerprint { hash: 14933625, lines: (17, 18), cols: (27, 28) },
+                    Fingerprint { hash: 73943364, lines: (19, 19), cols: (35, 46) }])]
         };
 
         let processed_sub2 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Processed(PathBuf::from("test-dirs/test/single-file/sub2.arr"), vec![
-                    Fingerprint { hash: 5421077, lines: (8, 10) },
-                    Fingerprint { hash: 14933625, lines: (13, 14) },
-                    Fingerprint { hash: 73943364, lines: (15, 15) }])]
+                    Fingerprint { hash: 5421077, lines: (8, 10), cols: (23, 7) },
+                    Fingerprint { hash: 14933625, lines: (13, 14), cols: (27, 28) },
+                    Fingerprint { hash: 73943364, lines: (15, 15), cols: (35, 46) }])]
         };
 
         let exp_out_sp = SubPair {
@@ -238,7 +364,8 @@ mod tests {
             b: &processed_sub2,
             b_percent: 1.0,
             matches: exp_matches,
-            percentile: 1.0
+            percentile: 1.0,
+            doc_pairs: vec![]
         };
 
         assert_eq!(out, (vec![exp_out_sp], 1));
@@ -250,65 +377,72 @@ mod tests {
     // and pairs without overlap are omitted
     fn test_multiple_pairs_output() {
         // original submissions
-        let mut sub1 = Sub {
+        let sub1 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/single-file-subpairs/sub1.arr"))
             ]
         };
-        let mut sub2 = Sub {
+        let sub2 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/single-file-subpairs/sub2.arr"))
             ]
         };
-        let mut sub3 = Sub {
+        let sub3 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/single-file-subpairs/sub3.arr"))
             ]
         };
-        let mut sub4 = Sub {
+        let sub4 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/single-file-subpairs/sub4.arr"))
             ]
         };
 
-        let mut submissions = vec![&mut sub1, &mut sub2, &mut sub3, &mut sub4];
-        let inp_map = analyze_subs(&mut submissions, None, 10, 60, false);
-        let out_min_thresh = find_overlaps(&inp_map, 0.0, false);
+        let mut submissions = vec![sub1, sub2, sub3, sub4];
+        let inp_map = analyze_subs(&mut submissions, None, &FpParams::new(10, 60), 1, None, false).unwrap();
+        let out_min_thresh = find_overlaps(&inp_map, None, None, 0.0, false).unwrap();
 
         let processed_sub1 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Processed(PathBuf::from("test-dirs/test/single-file-subpairs/sub1.arr"), vec![
-                    Fingerprint { hash: 5421077, lines: (11, 12) },
-                    Fingerprint { hash: 31722361, lines: (15, 16) },
-                    Fingerprint { hash: 30182096, lines: (16, 16) },
-                    Fingerprint { hash: 14933625, lines: (17, 18) },
-                    Fingerprint { hash: 73943364, lines: (19, 19) },
-                    Fingerprint { hash: 21898048, lines: (22, 23) }])]
+                    Fingerprint { hash: 5421077, lines: (11, 12), cols: (23, 7) },
+                    Fingerprint { hash: 31722361, lines: (15, 16), cols: (30, 28) },
+                    Fingerprint { hash: 30182096, lines: (16, 16), cols: (31, 44) },
+                    Fingerprint { hash: 14933625, lines: (17, 18), cols: (27, 28) },
+                    Fingerprint { hash: 73943364, lines: (19, 19), cols: (35, 46) },
+                    Fingerprint { hash: 21898048, lines: (22, 23), cols: (2, 7) }])]
         };
 
         let processed_sub2 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Processed(PathBuf::from("test-dirs/test/single-file-subpairs/sub2.arr"), vec![
-                    Fingerprint { hash: 5421077, lines: (8, 10) },
-                    Fingerprint { hash: 14933625, lines: (13, 14) },
-                    Fingerprint { hash: 73943364, lines: (15, 15) }])]
+                    Fingerprint { hash: 5421077, lines: (8, 10), cols: (23, 7) },
+                    Fingerprint { hash: 14933625, lines: (13, 14), cols: (27, 28) },
+                    Fingerprint { hash: 73943364, lines: (15, 15), cols: (35, 46) }])]
         };
 
         let processed_sub4 = Sub {
             dir_name: None,
+            archived: false,
             documents: vec![
                 Doc::Processed(PathBuf::from("test-dirs/test/single-file-subpairs/sub4.arr"), vec![
-                    Fingerprint { hash: 5421353, lines: (5, 6) },
-                    Fingerprint { hash: 10580184, lines: (9, 10)},
-                    Fingerprint { hash: 14933625, lines: (11, 12) },
-                    Fingerprint { hash: 17304907, lines: (13, 14) },
-                    Fingerprint { hash: 21898048, lines: (17, 18) }])]
+                    Fingerprint { hash: 5421353, lines: (5, 6), cols: (26, 7) },
+                    Fingerprint { hash: 10580184, lines: (9, 10), cols: (22, 22)},
+                    Fingerprint { hash: 14933625, lines: (11, 12), cols: (27, 28) },
+                    Fingerprint { hash: 17304907, lines: (13, 14), cols: (32, 2) },
+                    Fingerprint { hash: 21898048, lines: (17, 18), cols: (2, 7) }])]
         };
 
         let mut sub1_sub2_matches = HashSet::new();
@@ -329,7 +463,8 @@ mod tests {
             b: &processed_sub2,
             b_percent: 1.0,
             matches: sub1_sub2_matches,
-            percentile: 1.0
+            percentile: 1.0,
+            doc_pairs: vec![]
         };
 
         let sub1_sub4_pair = SubPair {
@@ -338,7 +473,8 @@ mod tests {
             b: &processed_sub4,
             b_percent: 0.4,
             matches: sub1_sub4_matches,
-            percentile: 2.0 / 3.0
+            percentile: 2.0 / 3.0,
+            doc_pairs: vec![]
         };
 
         let sub2_sub4_pair = SubPair {
@@ -347,7 +483,8 @@ mod tests {
             b: &processed_sub4,
             b_percent: 0.2,
             matches: sub2_sub4_matches,
-            percentile: 1.0 / 3.0
+            percentile: 1.0 / 3.0,
+            doc_pairs: vec![]
         };
 
         assert_eq!(out_min_thresh, (vec![sub1_sub2_pair, sub1_sub4_pair, sub2_sub4_pair], 3));
@@ -359,86 +496,93 @@ mod tests {
     // and pairs below percentile threshold are omitted
     fn test_multiple_multidoc_pairs_output() {
         // original submissions
-        let mut sub1 = Sub {
+        let sub1 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file-subpairs/sub1")),
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub1/common.arr")),
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub1/main.arr"))
             ]
         };
-        let mut sub2 = Sub {
+        let sub2 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file-subpairs/sub2")),
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub2/common.arr")),
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub2/main.arr"))
             ]
         };
-        let mut sub3 = Sub {
+        let sub3 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file-subpairs/sub3")),
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub3/common.arr")),
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub3/main.arr"))
             ]
         };
-        let mut sub4 = Sub {
+        let sub4 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file-subpairs/sub4")),
+            archived: false,
             documents: vec![
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub4/common.arr")),
                 Doc::Unprocessed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub4/main.arr"))
             ]
         };
 
-        let mut submissions = vec![&mut sub1, &mut sub2, &mut sub3, &mut sub4];
-        let inp_map = analyze_subs(&mut submissions, None, 10, 60, false);
+        let mut submissions = vec![sub1, sub2, sub3, sub4];
+        let inp_map = analyze_subs(&mut submissions, None, &FpParams::new(10, 60), 1, None, false).unwrap();
         //threshold is such that some pairs are filtered out
-        let out_med_thresh = find_overlaps(&inp_map, 0.3, false);
+        let out_med_thresh = find_overlaps(&inp_map, None, None, 0.3, false).unwrap();
 
         let processed_sub1 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file-subpairs/sub1")),
+            archived: false,
             documents: vec![
                 Processed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub1/common.arr"),
-                    vec![Fingerprint { hash: 390399223, lines: (1, 2) }]),
+                    vec![Fingerprint { hash: 390399223, lines: (1, 2), cols: (2, 3) }]),
                 Processed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub1/main.arr"), vec![
-                    Fingerprint { hash: 103309548, lines: (3, 5) },
-                	Fingerprint { hash: 139046768, lines: (7, 8) },
-                	Fingerprint { hash: 157553660, lines: (12, 12) },
-                	Fingerprint { hash: 155828129, lines: (16, 17) },
-                	Fingerprint { hash: 70845857, lines: (17, 18) }])]
+                    Fingerprint { hash: 103309548, lines: (3, 5), cols: (1, 4) },
+                	Fingerprint { hash: 139046768, lines: (7, 8), cols: (9, 10) },
+                	Fingerprint { hash: 157553660, lines: (12, 12), cols: (1, 22) },
+                	Fingerprint { hash: 155828129, lines: (16, 17), cols: (18, 17) },
+                	Fingerprint { hash: 70845857, lines: (17, 18), cols: (18, 3) }])]
         };
 
         let processed_sub3 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file-subpairs/sub3")),
+            archived: false,
             documents: vec![
                 Processed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub3/common.arr"),
-                vec![Fingerprint { hash: 76905376, lines: (6, 7) },
-                    Fingerprint { hash: 76839850, lines: (7, 8) },
-                    Fingerprint { hash: 41033526, lines: (8, 8) },
-                    Fingerprint { hash: 77033123, lines: (8, 9) },
-                    Fingerprint { hash: 70845857, lines: (16, 17) }]),
+                vec![Fingerprint { hash: 76905376, lines: (6, 7), cols: (19, 4) },
+                    Fingerprint { hash: 76839850, lines: (7, 8), cols: (20, 4) },
+                    Fingerprint { hash: 41033526, lines: (8, 8), cols: (11, 22) },
+                    Fingerprint { hash: 77033123, lines: (8, 9), cols: (19, 2) },
+                    Fingerprint { hash: 70845857, lines: (16, 17), cols: (18, 3) }]),
                 Processed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub3/main.arr"), vec![
-                    Fingerprint { hash: 103309548, lines: (3, 5) },
-                    Fingerprint { hash: 103309548, lines: (13, 15) },
-                    Fingerprint { hash: 138677810, lines: (22, 25) },
-                    Fingerprint { hash: 90448699, lines: (26, 26) },
-                    Fingerprint { hash: 90391867, lines: (26, 27) },
-                    Fingerprint { hash: 40051188, lines: (27, 27) },
-                    Fingerprint { hash: 1866481, lines: (27, 27) }])]
+                    Fingerprint { hash: 103309548, lines: (3, 5), cols: (1, 4) },
+                    Fingerprint { hash: 103309548, lines: (13, 15), cols: (1, 6) },
+                    Fingerprint { hash: 138677810, lines: (22, 25), cols: (25, 30) },
+                    Fingerprint { hash: 90448699, lines: (26, 26), cols: (7, 53) },
+                    Fingerprint { hash: 90391867, lines: (26, 27), cols: (16, 17) },
+                    Fingerprint { hash: 40051188, lines: (27, 27), cols: (20, 62) },
+                    Fingerprint { hash: 1866481, lines: (27, 27), cols: (29, 71) }])]
         };
 
         let processed_sub4 = Sub {
             dir_name: Some(PathBuf::from("test-dirs/test/multi-file-subpairs/sub4")),
+            archived: false,
             documents: vec![
                 Processed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub4/common.arr"),
-                vec![Fingerprint { hash: 84319302, lines: (14, 14) },
-                    Fingerprint { hash: 83117630, lines: (14, 14) },
-                    Fingerprint { hash: 77155669, lines: (14, 14) },
-                    Fingerprint { hash: 76905376, lines: (14, 15) },
-                    Fingerprint { hash: 76839850, lines: (15, 16) },
-                    Fingerprint { hash: 41033526, lines: (16, 16) },
-                    Fingerprint { hash: 40051188, lines: (20, 20) },
-                    Fingerprint { hash: 1866481, lines: (20, 20) }]),
+                vec![Fingerprint { hash: 84319302, lines: (14, 14), cols: (9, 19) },
+                    Fingerprint { hash: 83117630, lines: (14, 14), cols: (10, 21) },
+                    Fingerprint { hash: 77155669, lines: (14, 14), cols: (17, 30) },
+                    Fingerprint { hash: 76905376, lines: (14, 15), cols: (19, 4) },
+                    Fingerprint { hash: 76839850, lines: (15, 16), cols: (20, 4) },
+                    Fingerprint { hash: 41033526, lines: (16, 16), cols: (11, 22) },
+                    Fingerprint { hash: 40051188, lines: (20, 20), cols: (20, 62) },
+                    Fingerprint { hash: 1866481, lines: (20, 20), cols: (29, 71) }]),
                 Processed(PathBuf::from("test-dirs/test/multi-file-subpairs/sub4/main.arr"), vec![
-                    Fingerprint { hash: 103309548, lines: (4, 6) }])]
+                    Fingerprint { hash: 103309548, lines: (4, 6), cols: (1, 6) }])]
         };
 
         let mut sub3_sub4_matches = HashSet::new();
@@ -459,7 +603,8 @@ mod tests {
             b: &processed_sub4,
             b_percent: 2.0 / 3.0,
             matches: sub3_sub4_matches,
-            percentile: 1.0
+            percentile: 1.0,
+            doc_pairs: vec![]
         };
 
         let sub1_sub3_pair = SubPair {
@@ -468,10 +613,175 @@ mod tests {
             b: &processed_sub3,
             b_percent: 2.0 / 11.0,
             matches: sub1_sub3_matches,
-            percentile: 1.0 / 3.0
+            percentile: 1.0 / 3.0,
+            doc_pairs: vec![]
         };
 
         assert_eq!(out_med_thresh, (vec![sub3_sub4_pair, sub1_sub3_pair], 6));
+
+        // sub3 & sub4 overlap most in common.arr, though sub4's main.arr is entirely matched
+        assert_eq!(out_med_thresh.0[0].doc_pairs, vec![
+            DocPair { a_doc: 0, a_percent: 3.0 / 5.0, b_doc: 0, b_percent: 3.0 / 8.0, matches: 3 },
+            DocPair { a_doc: 1, a_percent: 2.0 / 6.0, b_doc: 0, b_percent: 2.0 / 8.0, matches: 2 },
+            DocPair { a_doc: 1, a_percent: 1.0 / 6.0, b_doc: 1, b_percent: 1.0, matches: 1 }
+        ]);
     }
 
+    #[test]
+    // tests that invalid thresholds & unprocessed documents are reported as errors
+    fn test_find_overlaps_errors() {
+        let a = Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![Doc::Unprocessed(PathBuf::from("a.arr"))]
+        };
+        let b = Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![Processed(PathBuf::from("b.arr"), vec![
+                Fingerprint { hash: 10, lines: (1, 1), cols: (1, 1) }])]
+        };
+
+        let mut inp_map: FnvHashMap<i64, HashSet<&Sub>> = FnvHashMap::default();
+        inp_map.insert(10, [&a, &b].iter().cloned().collect());
+
+        assert!(matches!(find_overlaps(&inp_map, None, None, 1.5, false),
+            Err(MossError::InvalidParams(_))));
+        assert!(matches!(find_overlaps(&inp_map, None, None, 0.0, false),
+            Err(MossError::UnprocessedDoc(_))));
+    }
+
+    #[test]
+    // tests that archived subs are paired with current subs, but never with each other
+    fn test_archived_pairs() {
+        // names of the subs in each pair, in a consistent order
+        fn pair_names(pairs: &[SubPair]) -> BTreeSet<(PathBuf, PathBuf)> {
+            let path = |sub: &Sub| match &sub.documents[0] {
+                Processed(p, _) => p.to_path_buf(),
+                Doc::Unprocessed(p) => p.to_path_buf(),
+            };
+            pairs.iter().map(|sp| {
+                let (a, b) = (path(sp.a), path(sp.b));
+                if a < b { (a, b) } else { (b, a) }
+            }).collect()
+        }
+
+        let mk_subs = |archived: &[bool]| -> Vec<Sub> {
+            archived.iter().enumerate().map(|(i, &archived)| Sub {
+                dir_name: None,
+                archived,
+                documents: vec![
+                    Doc::Unprocessed(PathBuf::from(
+                        format!("test-dirs/test/single-file-subpairs/sub{}.arr", i + 1)))
+                ]
+            }).collect()
+        };
+
+        let mut current = mk_subs(&[false, false, false, false]);
+        let current_map = analyze_subs(&mut current, None, &FpParams::new(10, 60), 1, None, false).unwrap();
+        let (all_pairs, _) = find_overlaps(&current_map, None, None, 0.0, false).unwrap();
+
+        // sub3 & sub4 are from the archive
+        let mut with_archive = mk_subs(&[false, false, true, true]);
+        let archive_map = analyze_subs(&mut with_archive, None, &FpParams::new(10, 60), 1, None, false).unwrap();
+        let (pairs, total) = find_overlaps(&archive_map, None, None, 0.0, false).unwrap();
+
+        let archive_pair = (PathBuf::from("test-dirs/test/single-file-subpairs/sub3.arr"),
+            PathBuf::from("test-dirs/test/single-file-subpairs/sub4.arr"));
+
+        let mut exp_names = pair_names(&all_pairs);
+        exp_names.remove(&archive_pair);
+
+        assert_eq!(pair_names(&pairs), exp_names);
+        assert_eq!(total, exp_names.len());
+        assert!(pairs.iter().all(|sp| !(sp.a.archived && sp.b.archived)));
+    }
+
+    #[test]
+    // tests that a corpus of 1,000 subs is paired correctly: neighboring subs share 10 hashes,
+    // & each block of 50 subs shares 1 more
+    fn test_many_subs() {
+        let subs: Vec<Sub> = (0..1000i64).map(|i| {
+            let mut hashes: Vec<i64> = (0..20).map(|j| i * 1000 + j).collect();
+            hashes.extend((0..10).map(|j| (i + 1) * 1000 + j));
+            hashes.push(-1 - i / 50);
+
+            Sub {
+                dir_name: Some(PathBuf::from(format!("sub{:04}", i))),
+                archived: false,
+                documents: vec![Processed(PathBuf::from(format!("sub{:04}/main.arr", i)),
+                    hashes.into_iter().map(|hash| Fingerprint { hash, lines: (1, 1), cols: (1, 1) }).collect())]
+            }
+        }).collect();
+
+        let mut inp_map: FnvHashMap<i64, HashSet<&Sub>> = FnvHashMap::default();
+        for sub in subs.iter() {
+            if let Processed(_, fps) = &sub.documents[0] {
+                for fp in fps { inp_map.entry(fp.hash).or_default().insert(sub); }
+            }
+        }
+
+        let (pairs, total) = find_overlaps(&inp_map, None, None, 0.0, false).unwrap();
+
+        // 20 blocks of 50 subs, plus the 19 neighbors in different blocks
+        assert_eq!(total, 20 * (50 * 49 / 2) + 19);
+        assert_eq!(pairs.len(), total);
+
+        // neighbors within a block share the most, & the lesser sub is always a
+        let top: Vec<&SubPair> = pairs.iter().filter(|sp| sp.percentile == 1.0).collect();
+        assert_eq!(top.len(), 20 * 49);
+        assert!(top.iter().all(|sp| sp.matches.len() == 11 && sp.a_percent == 11.0 / 31.0));
+        assert!(pairs.iter().all(|sp| sp.a.dir_name < sp.b.dir_name));
+    }
+
+    #[test]
+    // tests that with LSH, candidate pairs get the same SubPairs as exhaustive pairing,
+    // & that pairs which aren't candidates are left out
+    fn test_lsh_pairs() {
+        let mut submissions: Vec<Sub> = (1..=4).map(|i| Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![
+                Doc::Unprocessed(PathBuf::from(format!("test-dirs/test/single-file-subpairs/sub{}.arr", i)))
+            ]
+        }).collect();
+
+        let inp_map = analyze_subs(&mut submissions, None, &FpParams::new(10, 60), 1, None, false).unwrap();
+        let (exact, _) = find_overlaps(&inp_map, None, None, 0.0, false).unwrap();
+
+        // with many single-row bands, sub1 & sub2 (sharing half of sub1's hashes) are candidates
+        let loose = LshParams { bands: 64, rows: 1 };
+        let (pairs, total) = find_overlaps(&inp_map, None, Some(&loose), 0.0, false).unwrap();
+
+        assert_eq!(pairs[0], exact[0]);
+        assert_eq!(pairs[0].matches, exact[0].matches);
+        assert_eq!((pairs[0].a_percent, pairs[0].b_percent), (exact[0].a_percent, exact[0].b_percent));
+        assert!(total <= exact.len());
+
+        // a single band of many rows only pairs near-identical subs
+        let strict = LshParams { bands: 1, rows: 64 };
+        assert_eq!(find_overlaps(&inp_map, None, Some(&strict), 0.0, false).unwrap().1, 0);
+    }
+
+    #[test]
+    // tests that LSH signatures only reflect the hashes being considered, so hashes
+    // dropped from the map (e.g. suppressed as too common) don't keep pairs apart
+    fn test_lsh_kept_hashes() {
+        let subs: Vec<Sub> = [100, 300].iter().map(|&start| Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![Processed(PathBuf::from(format!("sub{}.arr", start)),
+                (1..=10).chain(start..start + 100)
+                    .map(|hash| Fingerprint { hash, lines: (1, 1), cols: (1, 1) }).collect())]
+        }).collect();
+
+        // only hashes 1-10 are kept
+        let mut inp_map: FnvHashMap<i64, HashSet<&Sub>> = FnvHashMap::default();
+        for hash in 1..=10 { inp_map.insert(hash, subs.iter().collect()); }
+
+        let strict = LshParams { bands: 1, rows: 64 };
+        let (pairs, total) = find_overlaps(&inp_map, None, Some(&strict), 0.0, false).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(pairs[0].matches.len(), 10);
+    }
 }
@@ -1,16 +1,21 @@
-/// Phase III: Find common substrings of fingerprints in a submission pair
+//! Phase III: Find common substrings of fingerprints in a submission pair
 
 use std::collections::{HashSet, BTreeMap};
 use std::cmp::{min, max};
+use fnv::FnvHashMap;
 use crate::phase_ii::SubPair;
 use crate::fingerprint::Fingerprint;
 use crate::{Sub, Doc};
+use crate::error::{MossError, Result};
 
-/// An Entry indicates a particular section of a document within a submission.
+/// An Entry indicates a particular section of a document within a submission:
+/// the lines it spans (inclusive), and the columns at which it begins
+/// (on its first line) and ends (on its last line, inclusive)
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Entry {
     pub doc_idx: usize,
-    pub lines: (i32, i32)
+    pub lines: (i32, i32),
+    pub cols: (i32, i32)
 }
 
 /// A Match indicates a set of entries from submission A which all share
@@ -32,7 +37,6 @@ struct SubString {
     b_entry: Entry
 }
 
-type SubStrTable = Vec<Vec<usize>>;
 type FpVec = Vec<Option<Fingerprint>>;
 
 /// Analyzes a pair of submissions to determine how overlap should be reported.
@@ -45,17 +49,18 @@ type FpVec = Vec<Option<Fingerprint>>;
 /// It's possible that a shared fingerprint appear in more than one match (it
 /// may be part of the LCS that includes some other fingerprint), but it must
 /// appear at least once.
-pub fn analyze_pair(pair: &SubPair) -> Vec<Match> {
+///
+/// Only the pair's shared hashes (its `matches`) are considered, and only the
+/// common substrings themselves are built, so memory is proportional to the
+/// amount of overlap rather than to the product of the submissions' sizes.
+pub fn analyze_pair(pair: &SubPair) -> Result<Vec<Match>> {
     // encode submission fingerprints as single vector of fingerprint options
-    let rows = flatten_docs(pair.a);
-    let cols = flatten_docs(pair.b);
+    let rows = flatten_docs(pair.a)?;
+    let cols = flatten_docs(pair.b)?;
 
-    // construct/populate dynamic programming table for longest common substring,
+    // choose set of common substrings that maintain above property ^^^,
     // using sub A and B's fingerprints as rows & cols, respectively
-    let table = substr_table(&rows, &cols);
-
-    // choose set of common substrings that maintain above property ^^^
-    let chosen_substrs = choose_substrs(&rows, &cols, &table);
+    let chosen_substrs = choose_substrs(&rows, &cols, &pair.matches);
 
     let mut hash_vec_to_substrs = BTreeMap::new();
 
@@ -83,20 +88,55 @@ pub fn analyze_pair(pair: &SubPair) -> Vec<Match> {
         // construct Match containing all the a & b entries
         matches.push(Match {
             size: hash_vec.len(),
-            a_entries: a_entries,
-            b_entries: b_entries
+            a_entries,
+            b_entries
         });
     }
 
     // sort by match size (size of hash vector), descending
-    matches.sort_by(|a, b| b.size.cmp(&a.size));
+    matches.sort_by_key(|m| std::cmp::Reverse(m.size));
+
+    Ok(matches)
+}
+
+/// Find the maximal runs of consecutive fingerprints (within a single document of the
+/// given submission) whose hashes are all in keep, along with the Entry each run spans
+/// & the hashes of its fingerprints, in order. Runs are ordered by document & position.
+pub fn runs(sub: &Sub, keep: &HashSet<i64>) -> Result<Vec<(Entry, Vec<i64>)>> {
+    let mut runs = Vec::new();
+
+    for (doc_idx, doc) in sub.documents.iter().enumerate() {
+        let fps = match doc {
+            Doc::Processed(_, fps) => fps,
+            Doc::Unprocessed(path) => return Err(MossError::UnprocessedDoc(path.clone())),
+        };
+
+        // the span & hashes of the run in progress, if any
+        let mut cur: Option<(Span, Vec<i64>)> = None;
+
+        for fp in fps.iter().map(Some).chain(std::iter::once(None)) {
+            match (fp.filter(|fp| keep.contains(&fp.hash)), cur.take()) {
+                (Some(fp), Some((s, mut hashes))) => {
+                    hashes.push(fp.hash);
+                    cur = Some((extend(s, fp), hashes));
+                },
+                (Some(fp), None) => cur = Some((span(fp), vec![fp.hash])),
+                (None, Some((s, hashes))) => {
+                    let ((start_line, start_col), (end_line, end_col)) = s;
+                    let entry = Entry { doc_idx, lines: (start_line, end_line), cols: (start_col, end_col) };
+                    runs.push((entry, hashes));
+                },
+                (None, None) => {},
+            }
+        }
+    }
 
-    matches
+    Ok(runs)
 }
 
 /// Produce a vector of Options of all fingerprints in the given submission,
 /// with different documents delimited by None
-fn flatten_docs(sub: &Sub) -> FpVec {
+fn flatten_docs(sub: &Sub) -> Result<FpVec> {
     let mut flat: Vec<Option<Fingerprint>> = Vec::new();
 
     for doc in sub.documents.iter() {
@@ -105,92 +145,102 @@ fn flatten_docs(sub: &Sub) -> FpVec {
             Doc::Processed(_, fps) => {
                 for fp in fps.iter() { flat.push(Some(*fp)); }
             }
-            Doc::Unprocessed(_) => {
-                panic!("unprocessed document encountered while flattening (flatten_docs)");
-            }
+            Doc::Unprocessed(path) => return Err(MossError::UnprocessedDoc(path.clone())),
         };
     }
 
-    flat
+    Ok(flat)
 }
 
-/// Populates the DP table for longest common substring, using rows
-/// & cols as the strings (documents are delimited by None)
-fn substr_table(rows: &FpVec, cols: &FpVec) -> SubStrTable {
-    let mut table = Vec::new();
-
-    for (r, row_el) in rows.iter().enumerate() {
-        table.push(Vec::new()); // add a new row vector
-
-        for (c, col_el) in cols.iter().enumerate() {
-            // if both are fingerprints with the same hash
-            if let (Some(row_fp), Some(col_fp)) = (row_el, col_el) {
-                if row_fp.hash == col_fp.hash {
-                    // write top-left diagonal + 1 to this cell
-                    let diag = table[r - 1][c - 1];
-                    table[r].push(diag + 1);
-                    continue;
-                }
-            }
+/// Produce the index of the document each position of a flattened submission lies in
+/// (a delimiting None belongs to the document that follows it)
+fn doc_indices(flat: &FpVec) -> Vec<usize> {
+    let mut doc_idx = 0;
+
+    flat.iter().enumerate()
+        .map(|(i, elt)| {
+            if elt.is_none() && i > 0 { doc_idx += 1; }
+            doc_idx
+        })
+        .collect()
+}
 
-            // if either is None, or hashes are unequal, write 0
-            table[r].push(0);
-        }
-    }
+/// Whether rows[r] & cols[c] are fingerprints with the same shared hash
+/// (a cell of the longest common substring table that extends a substring)
+fn is_common(rows: &FpVec, cols: &FpVec, coord: (usize, usize), shared: &HashSet<i64>) -> bool {
+    let (r, c) = coord;
 
-    table
+    match (rows.get(r), cols.get(c)) {
+        (Some(Some(row_fp)), Some(Some(col_fp))) => {
+            row_fp.hash == col_fp.hash && shared.contains(&row_fp.hash)
+        },
+        _ => false,
+    }
 }
 
-/// Choose longest common substrings from the substring table such that each row/col
-/// fingerprint has at least 1 of their longest common substrings in the chosen set
-fn choose_substrs(rows: &FpVec, cols: &FpVec, table: &SubStrTable) -> HashSet<SubString> {
-    let mut all_substrs = Vec::new();
-    let mut row_to_substrs: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
-    let mut col_to_substrs: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+/// Find the (row, col) positions at which each maximal common substring of shared
+/// hashes begins, in row-major order. Rather than filling in a table over all rows &
+/// cols, only the positions of shared hashes in cols (posting lists) are consulted
+/// for each row, so just the cells that are actually common are visited.
+fn substr_starts(rows: &FpVec, cols: &FpVec, shared: &HashSet<i64>) -> Vec<(usize, usize)> {
+    // the positions at which each shared hash occurs in cols, ascending
+    let mut postings: FnvHashMap<i64, Vec<usize>> = FnvHashMap::default();
+    for (c, col_elt) in cols.iter().enumerate() {
+        if let Some(fp) = col_elt {
+            if shared.contains(&fp.hash) { postings.entry(fp.hash).or_default().push(c); }
+        }
+    }
 
-    let mut a_doc_idx = 0;
+    let mut starts = Vec::new();
 
-    // for each row element
     for (r, row_elt) in rows.iter().enumerate() {
-        // track A's document index by Nones in row vector
-        if row_elt.is_none() && r > 0 {
-            a_doc_idx += 1;
-            continue;
+        let positions = match row_elt.and_then(|fp| postings.get(&fp.hash)) {
+            Some(positions) => positions,
+            None => continue,
+        };
+
+        // a common cell starts a substring unless the cell diagonally before it is common
+        for &c in positions.iter() {
+            if r == 0 || c == 0 || !is_common(rows, cols, (r - 1, c - 1), shared) {
+                starts.push((r, c));
+            }
         }
+    }
 
-        let mut b_doc_idx = 0;
+    starts
+}
 
-        // for each column element
-        for (c, col_elt) in cols.iter().enumerate() {
-            // track B's document index by Nones in column vector
-            if col_elt.is_none() && c > 0 {
-                b_doc_idx += 1;
-                continue;
-            }
+/// Choose longest common substrings of shared hashes such that each row/col
+/// fingerprint has at least 1 of their longest common substrings in the chosen set
+fn choose_substrs(rows: &FpVec, cols: &FpVec, shared: &HashSet<i64>) -> HashSet<SubString> {
+    let mut all_substrs = Vec::new();
+    let mut row_to_substrs: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    let mut col_to_substrs: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
 
-            // if not the start of a substring (1), proceed
-            if table[r][c] == 0 || table[r][c] > 1 { continue; }
+    let a_doc_idxs = doc_indices(rows);
+    let b_doc_idxs = doc_indices(cols);
 
-            // construct new substring by following diagonal
-            let new_substr = trace_diagonal(table, (rows, cols), (r, c), (a_doc_idx, b_doc_idx));
+    // for each position at which a common substring begins
+    for (r, c) in substr_starts(rows, cols, shared) {
+        // construct new substring by following diagonal
+        let new_substr = trace_diagonal((rows, cols), (r, c), (a_doc_idxs[r], b_doc_idxs[c]), shared);
 
-            all_substrs.push(new_substr.clone());       // add copy to substrings vec
-            let new_substr_idx = all_substrs.len() - 1; // get index of newly-added substring
+        let affected_rows = r..(r + new_substr.size);
+        let affected_cols = c..(c + new_substr.size);
 
-            let affected_rows = r..(r + new_substr.size);
-            let affected_cols = c..(c + new_substr.size);
+        all_substrs.push(new_substr);               // add to substrings vec
+        let new_substr_idx = all_substrs.len() - 1; // get index of newly-added substring
 
-            // update affected rows/cols to include a ref to this substring
-            for row_idx in affected_rows {
-                row_to_substrs.entry(row_idx)
-                    .or_insert_with(Vec::new)
-                    .push(new_substr_idx);
-            }
-            for col_idx in affected_cols {
-                col_to_substrs.entry(col_idx)
-                    .or_insert_with(Vec::new)
-                    .push(new_substr_idx);
-            }
+        // update affected rows/cols to include a ref to this substring
+        for row_idx in affected_rows {
+            row_to_substrs.entry(row_idx)
+                .or_default()
+                .push(new_substr_idx);
+        }
+        for col_idx in affected_cols {
+            col_to_substrs.entry(col_idx)
+                .or_default()
+                .push(new_substr_idx);
         }
     }
 
@@ -213,12 +263,12 @@ fn choose_substrs(rows: &FpVec, cols: &FpVec, table: &SubStrTable) -> HashSet<Su
 /// Choose a set of substrings (by index) for the fingerprints along a given dimension
 /// (row/col) such that at least one of each fp's longest common substring is included
 fn choose_for_dim(dim_to_substrs: &BTreeMap<usize, Vec<usize>>,
-    all_substrs: &Vec<SubString>, chosen: &HashSet<usize>) -> HashSet<usize> {
+    all_substrs: &[SubString], chosen: &HashSet<usize>) -> HashSet<usize> {
     // new substrings to add, having processed this dimension
     let mut chosen_this_dim: HashSet<usize> = HashSet::new();
 
     // for each fingerprint in this dimension
-    for (_, substr_idxs) in dim_to_substrs {
+    for substr_idxs in dim_to_substrs.values() {
         // (already_chosen, max_idx) indicates that all_substrs[max_idx] is the
         // max length substring for this dimension, & whether or not it has been chosen already
         let mut max: Option<(bool, usize)> = None;
@@ -259,38 +309,50 @@ fn choose_for_dim(dim_to_substrs: &BTreeMap<usize, Vec<usize>>,
     chosen_this_dim
 }
 
-/// Trace diagonally down/right from table[r][c] to construct a SubString
-/// representing the substring that lies on that diagonal
-fn trace_diagonal(table: &SubStrTable, dims: (&FpVec, &FpVec),
-    coord: (usize, usize), docs: (usize, usize)) -> SubString {
+// the (line, col) positions at which a section of a document begins & ends
+type Span = ((i32, i32), (i32, i32));
+
+/// The span of a single fingerprint
+fn span(fp: &Fingerprint) -> Span {
+    ((fp.lines.0, fp.cols.0), (fp.lines.1, fp.cols.1))
+}
+
+/// Extend a span to also cover a fingerprint
+fn extend(s: Span, fp: &Fingerprint) -> Span {
+    let fp_span = span(fp);
+    (min(s.0, fp_span.0), max(s.1, fp_span.1))
+}
+
+/// Trace diagonally down/right from (r, c), the start of a common substring,
+/// to construct a SubString representing the substring that lies on that diagonal
+fn trace_diagonal(dims: (&FpVec, &FpVec), coord: (usize, usize), docs: (usize, usize),
+    shared: &HashSet<i64>) -> SubString {
 
     let (rows, cols) = dims;
     let (mut r, mut c) = coord;
 
-    // 1 indicates start of substring--shouldn't be called anywhere else
-    if table[r][c] != 1 {
-        panic!("tried to trace diagonal on cell with value {}", table[r][c]);
+    // substrings start at common cells--shouldn't be called anywhere else
+    if !is_common(rows, cols, (r, c), shared) {
+        panic!("tried to trace diagonal on cell ({}, {}), which isn't common", r, c);
     }
 
     let mut hashes = Vec::new();
-    let mut lines: Option<((i32, i32), (i32, i32))> = None;
+    let mut spans: Option<(Span, Span)> = None;
 
     // while there's more diagonal to be processed
-    while r < rows.len() && c < cols.len() && table[r][c] != 0 {
+    while is_common(rows, cols, (r, c), shared) {
         let a_elt = rows[r].unwrap();
         let b_elt = cols[c].unwrap();
 
         hashes.push(a_elt.hash);    // hashes match, so arbitrarily add A's
 
-        // update line ranges to extend maximally
-        match lines {
-            Some((a_lines, b_lines)) => {
-                lines = Some((
-                    (min(a_lines.0, a_elt.lines.0), max(a_lines.1, a_elt.lines.1)),
-                    (min(b_lines.0, b_elt.lines.0), max(b_lines.1, b_elt.lines.1))));
+        // update spans to extend maximally
+        match spans {
+            Some((a_span, b_span)) => {
+                spans = Some((extend(a_span, &a_elt), extend(b_span, &b_elt)));
             },
             None => {
-                lines = Some((a_elt.lines, b_elt.lines));
+                spans = Some((span(&a_elt), span(&b_elt)));
             }
         };
 
@@ -299,20 +361,22 @@ fn trace_diagonal(table: &SubStrTable, dims: (&FpVec, &FpVec),
         c += 1;
     }
 
-    if let Some(lines) = lines {
+    if let Some((a_span, b_span)) = spans {
         // construct the SubString
-        return SubString {
+        SubString {
             size: hashes.len(),
-            hashes: hashes,
+            hashes,
             a_entry: Entry {
                 doc_idx: docs.0,
-                lines: lines.0
+                lines: (a_span.0.0, a_span.1.0),
+                cols: (a_span.0.1, a_span.1.1)
             },
             b_entry: Entry {
                 doc_idx: docs.1,
-                lines: lines.1
+                lines: (b_span.0.0, b_span.1.0),
+                cols: (b_span.0.1, b_span.1.1)
             }
-        };
+        }
     } else {
         panic!("no lines were found in tracing diagonal");
     }
@@ -324,193 +388,175 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    // turn a vector into a hashset (convenience)
+    fn set<T: Clone+Eq+std::hash::Hash>(elts: Vec<T>) -> HashSet<T> {
+        elts.iter().cloned().collect()
+    }
+
     #[test]
     fn test_flatten_docs() {
         {
             let s = Sub {
                 dir_name: None,
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 17, lines: (1, 2) },
-                        Fingerprint { hash: 38, lines: (3, 7) },
-                        Fingerprint { hash: 22, lines: (14, 18) }
+                        Fingerprint { hash: 17, lines: (1, 2), cols: (1, 1) },
+                        Fingerprint { hash: 38, lines: (3, 7), cols: (1, 1) },
+                        Fingerprint { hash: 22, lines: (14, 18), cols: (1, 1) }
                     ]),
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 889, lines: (3, 3) },
-                        Fingerprint { hash: 24, lines: (4, 7) },
-                        Fingerprint { hash: 105, lines: (7, 10) }
+                        Fingerprint { hash: 889, lines: (3, 3), cols: (1, 1) },
+                        Fingerprint { hash: 24, lines: (4, 7), cols: (1, 1) },
+                        Fingerprint { hash: 105, lines: (7, 10), cols: (1, 1) }
                     ]),
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 98, lines: (1, 5) }
+                        Fingerprint { hash: 98, lines: (1, 5), cols: (1, 1) }
                     ])
                 ]
             };
 
             assert_eq!(
-                flatten_docs(&s),
+                flatten_docs(&s).unwrap(),
                 vec![
                     None,
-                    Some(Fingerprint { hash: 17, lines: (1, 2) }),
-                    Some(Fingerprint { hash: 38, lines: (3, 7) }),
-                    Some(Fingerprint { hash: 22, lines: (14, 18) }),
+                    Some(Fingerprint { hash: 17, lines: (1, 2), cols: (1, 1) }),
+                    Some(Fingerprint { hash: 38, lines: (3, 7), cols: (1, 1) }),
+                    Some(Fingerprint { hash: 22, lines: (14, 18), cols: (1, 1) }),
                     None,
-                    Some(Fingerprint { hash: 889, lines: (3, 3) }),
-                    Some(Fingerprint { hash: 24, lines: (4, 7) }),
-                    Some(Fingerprint { hash: 105, lines: (7, 10) }),
+                    Some(Fingerprint { hash: 889, lines: (3, 3), cols: (1, 1) }),
+                    Some(Fingerprint { hash: 24, lines: (4, 7), cols: (1, 1) }),
+                    Some(Fingerprint { hash: 105, lines: (7, 10), cols: (1, 1) }),
                     None,
-                    Some(Fingerprint { hash: 98, lines: (1, 5) })
+                    Some(Fingerprint { hash: 98, lines: (1, 5), cols: (1, 1) })
                 ]);
         }
         {
             let s = Sub {
                 dir_name: None,
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 3812, lines: (31, 40) },
-                        Fingerprint { hash: 4722, lines: (40, 43) },
-                        Fingerprint { hash: 2139, lines: (42, 49) },
-                        Fingerprint { hash: 1274, lines: (45, 62) },
-                        Fingerprint { hash: 2347, lines: (55, 81) }
+                        Fingerprint { hash: 3812, lines: (31, 40), cols: (1, 1) },
+                        Fingerprint { hash: 4722, lines: (40, 43), cols: (1, 1) },
+                        Fingerprint { hash: 2139, lines: (42, 49), cols: (1, 1) },
+                        Fingerprint { hash: 1274, lines: (45, 62), cols: (1, 1) },
+                        Fingerprint { hash: 2347, lines: (55, 81), cols: (1, 1) }
                     ])
                 ]
             };
 
             assert_eq!(
-                flatten_docs(&s),
+                flatten_docs(&s).unwrap(),
                 vec![
                     None,
-                    Some(Fingerprint { hash: 3812, lines: (31, 40) }),
-                    Some(Fingerprint { hash: 4722, lines: (40, 43) }),
-                    Some(Fingerprint { hash: 2139, lines: (42, 49) }),
-                    Some(Fingerprint { hash: 1274, lines: (45, 62) }),
-                    Some(Fingerprint { hash: 2347, lines: (55, 81) })
+                    Some(Fingerprint { hash: 3812, lines: (31, 40), cols: (1, 1) }),
+                    Some(Fingerprint { hash: 4722, lines: (40, 43), cols: (1, 1) }),
+                    Some(Fingerprint { hash: 2139, lines: (42, 49), cols: (1, 1) }),
+                    Some(Fingerprint { hash: 1274, lines: (45, 62), cols: (1, 1) }),
+                    Some(Fingerprint { hash: 2347, lines: (55, 81), cols: (1, 1) })
                 ]);
         }
         {
             let s = Sub {
                 dir_name: None,
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from(""), vec![]),
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 41, lines: (31, 40) },
-                        Fingerprint { hash: 28, lines: (40, 43) }
+                        Fingerprint { hash: 41, lines: (31, 40), cols: (1, 1) },
+                        Fingerprint { hash: 28, lines: (40, 43), cols: (1, 1) }
                     ])
                 ]
             };
 
             assert_eq!(
-                flatten_docs(&s),
+                flatten_docs(&s).unwrap(),
                 vec![
                     None,
                     None,
-                    Some(Fingerprint { hash: 41, lines: (31, 40) }),
-                    Some(Fingerprint { hash: 28, lines: (40, 43) })
+                    Some(Fingerprint { hash: 41, lines: (31, 40), cols: (1, 1) }),
+                    Some(Fingerprint { hash: 28, lines: (40, 43), cols: (1, 1) })
                 ]);
         }
     }
 
     #[test]
-    fn test_substr_table() {
+    fn test_substr_starts() {
         {
             // single document each
             let rows = vec![
                 None,
-                Some(Fingerprint { hash: 180, lines: (2, 3) }),
-                Some(Fingerprint { hash: 17, lines: (6, 10) }),
-                Some(Fingerprint { hash: 224, lines: (11, 13) }),
-                Some(Fingerprint { hash: 61, lines: (20, 22) }),
-                Some(Fingerprint { hash: 17, lines: (24, 30) })
+                Some(Fingerprint { hash: 180, lines: (2, 3), cols: (1, 1) }),
+                Some(Fingerprint { hash: 17, lines: (6, 10), cols: (1, 1) }),
+                Some(Fingerprint { hash: 224, lines: (11, 13), cols: (1, 1) }),
+                Some(Fingerprint { hash: 61, lines: (20, 22), cols: (1, 1) }),
+                Some(Fingerprint { hash: 17, lines: (24, 30), cols: (1, 1) })
             ];
 
             let cols = vec![
                 None,
-                Some(Fingerprint { hash: 17, lines: (7, 14) }),
-                Some(Fingerprint { hash: 224, lines: (26, 29) }),
-                Some(Fingerprint { hash: 180, lines: (34, 39) }),
-                Some(Fingerprint { hash: 17, lines: (46, 50) })
-            ];
-
-            let exp_table = vec![
-                vec![0, 0, 0, 0, 0],
-                vec![0, 0, 0, 1, 0],
-                vec![0, 1, 0, 0, 2],
-                vec![0, 0, 2, 0, 0],
-                vec![0, 0, 0, 0, 0],
-                vec![0, 1, 0, 0, 1]
+                Some(Fingerprint { hash: 17, lines: (7, 14), cols: (1, 1) }),
+                Some(Fingerprint { hash: 224, lines: (26, 29), cols: (1, 1) }),
+                Some(Fingerprint { hash: 180, lines: (34, 39), cols: (1, 1) }),
+                Some(Fingerprint { hash: 17, lines: (46, 50), cols: (1, 1) })
             ];
 
-            assert_eq!(substr_table(&rows, &cols), exp_table);
+            // (the cells of the substring table that would hold 1)
+            assert_eq!(substr_starts(&rows, &cols, &set(vec![180, 17, 224])), vec![(1, 3), (2, 1), (5, 1), (5, 4)]);
         }
         {
             // multiple documents
             let rows = vec![
                 None,
-                Some(Fingerprint { hash: 37, lines: (2, 3) }),
-                Some(Fingerprint { hash: 22, lines: (6, 10) }),
+                Some(Fingerprint { hash: 37, lines: (2, 3), cols: (1, 1) }),
+                Some(Fingerprint { hash: 22, lines: (6, 10), cols: (1, 1) }),
                 None,
-                Some(Fingerprint { hash: 11, lines: (11, 13) }),
-                Some(Fingerprint { hash: 6, lines: (20, 22) }),
-                Some(Fingerprint { hash: 22, lines: (24, 30) })
+                Some(Fingerprint { hash: 11, lines: (11, 13), cols: (1, 1) }),
+                Some(Fingerprint { hash: 6, lines: (20, 22), cols: (1, 1) }),
+                Some(Fingerprint { hash: 22, lines: (24, 30), cols: (1, 1) })
             ];
 
             let cols = vec![
                 None,
-                Some(Fingerprint { hash: 5, lines: (3, 8) }),
+                Some(Fingerprint { hash: 5, lines: (3, 8), cols: (1, 1) }),
                 None,
-                Some(Fingerprint { hash: 22, lines: (9, 12) }),
-                Some(Fingerprint { hash: 11, lines: (14, 14) }),
-                Some(Fingerprint { hash: 6, lines: (15, 16) }),
+                Some(Fingerprint { hash: 22, lines: (9, 12), cols: (1, 1) }),
+                Some(Fingerprint { hash: 11, lines: (14, 14), cols: (1, 1) }),
+                Some(Fingerprint { hash: 6, lines: (15, 16), cols: (1, 1) }),
                 None,
-                Some(Fingerprint { hash: 6, lines: (17, 20) }),
-                Some(Fingerprint { hash: 14, lines: (21, 28) }),
-                Some(Fingerprint { hash: 11, lines: (28, 28) }),
+                Some(Fingerprint { hash: 6, lines: (17, 20), cols: (1, 1) }),
+                Some(Fingerprint { hash: 14, lines: (21, 28), cols: (1, 1) }),
+                Some(Fingerprint { hash: 11, lines: (28, 28), cols: (1, 1) }),
             ];
 
-            let exp_table = vec![
-                vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-                vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-                vec![0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
-                vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-                vec![0, 0, 0, 0, 1, 0, 0, 0, 0, 1],
-                vec![0, 0, 0, 0, 0, 2, 0, 1, 0, 0],
-                vec![0, 0, 0, 1, 0, 0, 0, 0, 0, 0]
-            ];
-
-            assert_eq!(substr_table(&rows, &cols), exp_table);
+            // (the cells of the substring table that would hold 1)
+            assert_eq!(substr_starts(&rows, &cols, &set(vec![22, 11, 6])), vec![(2, 3), (4, 4), (4, 9), (5, 7), (6, 3)]);
         }
         {
             // longer substring
             let rows = vec![
                 None,
-                Some(Fingerprint { hash: 1, lines: (2, 3) }),
-                Some(Fingerprint { hash: 2, lines: (6, 10) }),
-                Some(Fingerprint { hash: 3, lines: (11, 13) }),
-                Some(Fingerprint { hash: 4, lines: (20, 22) }),
-                Some(Fingerprint { hash: 5, lines: (24, 30) }),
-                Some(Fingerprint { hash: 1, lines: (27, 31) })
+                Some(Fingerprint { hash: 1, lines: (2, 3), cols: (1, 1) }),
+                Some(Fingerprint { hash: 2, lines: (6, 10), cols: (1, 1) }),
+                Some(Fingerprint { hash: 3, lines: (11, 13), cols: (1, 1) }),
+                Some(Fingerprint { hash: 4, lines: (20, 22), cols: (1, 1) }),
+                Some(Fingerprint { hash: 5, lines: (24, 30), cols: (1, 1) }),
+                Some(Fingerprint { hash: 1, lines: (27, 31), cols: (1, 1) })
             ];
 
             let cols = vec![
                 None,
-                Some(Fingerprint { hash: 1, lines: (3, 8) }),
+                Some(Fingerprint { hash: 1, lines: (3, 8), cols: (1, 1) }),
                 None,
-                Some(Fingerprint { hash: 2, lines: (4, 9) }),
-                Some(Fingerprint { hash: 3, lines: (5, 10) }),
-                Some(Fingerprint { hash: 4, lines: (6, 11) }),
-                Some(Fingerprint { hash: 5, lines: (7, 12) })
-            ];
-
-            let exp_table = vec![
-                vec![0, 0, 0, 0, 0, 0, 0],
-                vec![0, 1, 0, 0, 0, 0, 0],
-                vec![0, 0, 0, 1, 0, 0, 0],
-                vec![0, 0, 0, 0, 2, 0, 0],
-                vec![0, 0, 0, 0, 0, 3, 0],
-                vec![0, 0, 0, 0, 0, 0, 4],
-                vec![0, 1, 0, 0, 0, 0, 0],
+                Some(Fingerprint { hash: 2, lines: (4, 9), cols: (1, 1) }),
+                Some(Fingerprint { hash: 3, lines: (5, 10), cols: (1, 1) }),
+                Some(Fingerprint { hash: 4, lines: (6, 11), cols: (1, 1) }),
+                Some(Fingerprint { hash: 5, lines: (7, 12), cols: (1, 1) })
             ];
 
-            assert_eq!(substr_table(&rows, &cols), exp_table);
+            // (the cells of the substring table that would hold 1)
+            assert_eq!(substr_starts(&rows, &cols, &set(vec![1, 2, 3, 4, 5])), vec![(1, 1), (2, 3), (6, 1)]);
         }
     }
 
@@ -519,140 +565,172 @@ mod tests {
         {
             let rows = vec![
                 None,
-                Some(Fingerprint { hash: 1, lines: (1, 5) }),
-                Some(Fingerprint { hash: 2, lines: (5, 7) }),
-                Some(Fingerprint { hash: 1, lines: (10, 15) }),
-                Some(Fingerprint { hash: 2, lines: (20, 31) })];
+                Some(Fingerprint { hash: 1, lines: (1, 5), cols: (1, 1) }),
+                Some(Fingerprint { hash: 2, lines: (5, 7), cols: (1, 1) }),
+                Some(Fingerprint { hash: 1, lines: (10, 15), cols: (1, 1) }),
+                Some(Fingerprint { hash: 2, lines: (20, 31), cols: (1, 1) })];
 
             let cols = vec![
                 None,
-                Some(Fingerprint { hash: 2, lines: (3, 9) }),
-                Some(Fingerprint { hash: 1, lines: (10, 22) }),
-                Some(Fingerprint { hash: 2, lines: (18, 24) }),
+                Some(Fingerprint { hash: 2, lines: (3, 9), cols: (1, 1) }),
+                Some(Fingerprint { hash: 1, lines: (10, 22), cols: (1, 1) }),
+                Some(Fingerprint { hash: 2, lines: (18, 24), cols: (1, 1) }),
                 None,
-                Some(Fingerprint { hash: 1, lines: (14, 17) }),
-                Some(Fingerprint { hash: 2, lines: (16, 19) }),
-                Some(Fingerprint { hash: 1, lines: (20, 22) })];
-
-            let table = vec![
-                vec![0, 0, 0, 0, 0, 0, 0, 0],
-                vec![0, 0, 1, 0, 0, 1, 0, 1],
-                vec![0, 1, 0, 2, 0, 0, 2, 0],
-                vec![0, 0, 2, 0, 0, 1, 0, 3],
-                vec![0, 1, 0, 3, 0, 0, 2, 0]
-            ];
+                Some(Fingerprint { hash: 1, lines: (14, 17), cols: (1, 1) }),
+                Some(Fingerprint { hash: 2, lines: (16, 19), cols: (1, 1) }),
+                Some(Fingerprint { hash: 1, lines: (20, 22), cols: (1, 1) })];
+
+            let shared = set(vec![1, 2]);
+
 
             // trace at r=1, c=2
             assert_eq!(
-                trace_diagonal(&table, (&rows, &cols), (1, 2), (0, 0)),
+                trace_diagonal((&rows, &cols), (1, 2), (0, 0), &shared),
                 SubString {
                     size: 2,
                     hashes: vec![1, 2],
                     a_entry: Entry {
                         doc_idx: 0,
-                        lines: (1, 7)
+                        lines: (1, 7),
+                        cols: (1, 1)
                     },
                     b_entry: Entry {
                         doc_idx: 0,
-                        lines: (10, 24)
+                        lines: (10, 24),
+                        cols: (1, 1)
                     }
                 });
 
             // trace at r=1, c=5
             assert_eq!(
-                trace_diagonal(&table, (&rows, &cols), (1, 5), (0, 1)),
+                trace_diagonal((&rows, &cols), (1, 5), (0, 1), &shared),
                 SubString {
                     size: 3,
                     hashes: vec![1, 2, 1],
                     a_entry: Entry {
                         doc_idx: 0,
-                        lines: (1, 15)
+                        lines: (1, 15),
+                        cols: (1, 1)
                     },
                     b_entry: Entry {
                         doc_idx: 1,
-                        lines: (14, 22)
+                        lines: (14, 22),
+                        cols: (1, 1)
                     }
                 });
 
             // trace at r=4, c=1
             assert_eq!(
-                trace_diagonal(&table, (&rows, &cols), (4, 1), (0, 0)),
+                trace_diagonal((&rows, &cols), (4, 1), (0, 0), &shared),
                 SubString {
                     size: 1,
                     hashes: vec![2],
                     a_entry: Entry {
                         doc_idx: 0,
-                        lines: (20, 31)
+                        lines: (20, 31),
+                        cols: (1, 1)
                     },
                     b_entry: Entry {
                         doc_idx: 0,
-                        lines: (3, 9)
+                        lines: (3, 9),
+                        cols: (1, 1)
                     }
                 });
         }
         {
             let rows = vec![
                 None,
-                Some(Fingerprint { hash: 100, lines: (12, 14) }),
-                Some(Fingerprint { hash: 200, lines: (13, 18) }),
-                Some(Fingerprint { hash: 300, lines: (20, 25) }),
-                Some(Fingerprint { hash: 400, lines: (24, 29) }),
-                Some(Fingerprint { hash: 500, lines: (30, 41) })];
+                Some(Fingerprint { hash: 100, lines: (12, 14), cols: (1, 1) }),
+                Some(Fingerprint { hash: 200, lines: (13, 18), cols: (1, 1) }),
+                Some(Fingerprint { hash: 300, lines: (20, 25), cols: (1, 1) }),
+                Some(Fingerprint { hash: 400, lines: (24, 29), cols: (1, 1) }),
+                Some(Fingerprint { hash: 500, lines: (30, 41), cols: (1, 1) })];
 
             let cols = vec![
                 None,
-                Some(Fingerprint { hash: 100, lines: (2, 5) }),
+                Some(Fingerprint { hash: 100, lines: (2, 5), cols: (1, 1) }),
                 None,
-                Some(Fingerprint { hash: 200, lines: (1, 3) }),
-                Some(Fingerprint { hash: 300, lines: (4, 5) }),
-                Some(Fingerprint { hash: 400, lines: (7, 18) }),
-                Some(Fingerprint { hash: 500, lines: (15, 22) })];
-
-            let table = vec![
-                vec![0, 0, 0, 0, 0, 0, 0],
-                vec![0, 1, 0, 0, 0, 0, 0],
-                vec![0, 0, 0, 1, 0, 0, 0],
-                vec![0, 0, 0, 0, 2, 0, 0],
-                vec![0, 0, 0, 0, 0, 3, 0],
-                vec![0, 0, 0, 0, 0, 0, 4]
-            ];
+                Some(Fingerprint { hash: 200, lines: (1, 3), cols: (1, 1) }),
+                Some(Fingerprint { hash: 300, lines: (4, 5), cols: (1, 1) }),
+                Some(Fingerprint { hash: 400, lines: (7, 18), cols: (1, 1) }),
+                Some(Fingerprint { hash: 500, lines: (15, 22), cols: (1, 1) })];
+
+            let shared = set(vec![100, 200, 300, 400, 500]);
+
 
             // trace at r=1, c=1
             assert_eq!(
-                trace_diagonal(&table, (&rows, &cols), (1, 1), (0, 0)),
+                trace_diagonal((&rows, &cols), (1, 1), (0, 0), &shared),
                 SubString {
                     size: 1,
                     hashes: vec![100],
                     a_entry: Entry {
                         doc_idx: 0,
-                        lines: (12, 14)
+                        lines: (12, 14),
+                        cols: (1, 1)
                     },
                     b_entry: Entry {
                         doc_idx: 0,
-                        lines: (2, 5)
+                        lines: (2, 5),
+                        cols: (1, 1)
                     }
                 });
 
             // trace at r=2, c=3
             assert_eq!(
-                trace_diagonal(&table, (&rows, &cols), (2, 3), (0, 1)),
+                trace_diagonal((&rows, &cols), (2, 3), (0, 1), &shared),
                 SubString {
                     size: 4,
                     hashes: vec![200, 300, 400, 500],
                     a_entry: Entry {
                         doc_idx: 0,
-                        lines: (13, 41)
+                        lines: (13, 41),
+                        cols: (1, 1)
                     },
                     b_entry: Entry {
                         doc_idx: 1,
-                        lines: (1, 22)
+                        lines: (1, 22),
+                        cols: (1, 1)
                     }
                 });
 
-            // trying to trace at a 0-cell is an error
-            let result = std::panic::catch_unwind(|| trace_diagonal(&table, (&rows, &cols), (2, 1), (0, 0)));
+            // trying to trace at a cell that isn't common is an error
+            let result = std::panic::catch_unwind(|| trace_diagonal((&rows, &cols), (2, 1), (0, 0), &shared));
             assert!(result.is_err());
         }
+        {
+            // columns extend to the earliest start & latest end, even where
+            // those come from different fingerprints on the same line
+            let rows = vec![
+                None,
+                Some(Fingerprint { hash: 1, lines: (2, 3), cols: (5, 4) }),
+                Some(Fingerprint { hash: 2, lines: (2, 3), cols: (9, 2) })];
+
+            let cols = vec![
+                None,
+                Some(Fingerprint { hash: 1, lines: (7, 7), cols: (3, 12) }),
+                Some(Fingerprint { hash: 2, lines: (7, 7), cols: (6, 15) })];
+
+            let shared = set(vec![1, 2]);
+
+
+            assert_eq!(
+                trace_diagonal((&rows, &cols), (1, 1), (0, 0), &shared),
+                SubString {
+                    size: 2,
+                    hashes: vec![1, 2],
+                    a_entry: Entry {
+                        doc_idx: 0,
+                        lines: (2, 3),
+                        cols: (5, 4)
+                    },
+                    b_entry: Entry {
+                        doc_idx: 0,
+                        lines: (7, 7),
+                        cols: (3, 15)
+                    }
+                });
+        }
     }
 
     #[test]
@@ -660,28 +738,21 @@ mod tests {
         {
             let rows = vec![
                 None,
-                Some(Fingerprint { hash: 1, lines: (1, 5) }),
-                Some(Fingerprint { hash: 2, lines: (5, 7) }),
-                Some(Fingerprint { hash: 1, lines: (10, 15) }),
-                Some(Fingerprint { hash: 2, lines: (20, 31) })];
+                Some(Fingerprint { hash: 1, lines: (1, 5), cols: (1, 1) }),
+                Some(Fingerprint { hash: 2, lines: (5, 7), cols: (1, 1) }),
+                Some(Fingerprint { hash: 1, lines: (10, 15), cols: (1, 1) }),
+                Some(Fingerprint { hash: 2, lines: (20, 31), cols: (1, 1) })];
 
             let cols = vec![
                 None,
-                Some(Fingerprint { hash: 2, lines: (3, 9) }),
-                Some(Fingerprint { hash: 1, lines: (10, 22) }),
-                Some(Fingerprint { hash: 2, lines: (18, 24) }),
+                Some(Fingerprint { hash: 2, lines: (3, 9), cols: (1, 1) }),
+                Some(Fingerprint { hash: 1, lines: (10, 22), cols: (1, 1) }),
+                Some(Fingerprint { hash: 2, lines: (18, 24), cols: (1, 1) }),
                 None,
-                Some(Fingerprint { hash: 1, lines: (14, 17) }),
-                Some(Fingerprint { hash: 2, lines: (16, 19) }),
-                Some(Fingerprint { hash: 1, lines: (20, 22) })];
-
-            let table = vec![
-                vec![0, 0, 0, 0, 0, 0, 0, 0],
-                vec![0, 0, 1, 0, 0, 1, 0, 1],
-                vec![0, 1, 0, 2, 0, 0, 2, 0],
-                vec![0, 0, 2, 0, 0, 1, 0, 3],
-                vec![0, 1, 0, 3, 0, 0, 2, 0]
-            ];
+                Some(Fingerprint { hash: 1, lines: (14, 17), cols: (1, 1) }),
+                Some(Fingerprint { hash: 2, lines: (16, 19), cols: (1, 1) }),
+                Some(Fingerprint { hash: 1, lines: (20, 22), cols: (1, 1) })];
+
 
             let mut exp = HashSet::new();
             exp.insert(SubString {
@@ -689,11 +760,13 @@ mod tests {
                 hashes: vec![1, 2, 1],
                 a_entry: Entry {
                     doc_idx: 0,
-                    lines: (1, 15)
+                    lines: (1, 15),
+                    cols: (1, 1)
                 },
                 b_entry: Entry {
                     doc_idx: 1,
-                    lines: (14, 22)
+                    lines: (14, 22),
+                    cols: (1, 1)
                 }
             });
             exp.insert(SubString {
@@ -701,43 +774,37 @@ mod tests {
                 hashes: vec![2, 1, 2],
                 a_entry: Entry {
                     doc_idx: 0,
-                    lines: (5, 31)
+                    lines: (5, 31),
+                    cols: (1, 1)
                 },
                 b_entry: Entry {
                     doc_idx: 0,
-                    lines: (3, 24)
+                    lines: (3, 24),
+                    cols: (1, 1)
                 }
             });
 
-            assert_eq!(choose_substrs(&rows, &cols, &table), exp);
+            assert_eq!(choose_substrs(&rows, &cols, &set(vec![1, 2])), exp);
         }
         {
             let rows = vec![
                 None,
-                Some(Fingerprint { hash: 7, lines: (2, 19) }),
-                Some(Fingerprint { hash: 8, lines: (15, 22) }),
-                Some(Fingerprint { hash: 7, lines: (30, 35) }),
-                Some(Fingerprint { hash: 8, lines: (34, 39) }),
-                Some(Fingerprint { hash: 9, lines: (40, 42) })];
+                Some(Fingerprint { hash: 7, lines: (2, 19), cols: (1, 1) }),
+                Some(Fingerprint { hash: 8, lines: (15, 22), cols: (1, 1) }),
+                Some(Fingerprint { hash: 7, lines: (30, 35), cols: (1, 1) }),
+                Some(Fingerprint { hash: 8, lines: (34, 39), cols: (1, 1) }),
+                Some(Fingerprint { hash: 9, lines: (40, 42), cols: (1, 1) })];
 
             let cols = vec![
                 None,
-                Some(Fingerprint { hash: 7, lines: (14, 20) }),
-                Some(Fingerprint { hash: 8, lines: (16, 22) }),
-                Some(Fingerprint { hash: 9, lines: (18, 24) }),
+                Some(Fingerprint { hash: 7, lines: (14, 20), cols: (1, 1) }),
+                Some(Fingerprint { hash: 8, lines: (16, 22), cols: (1, 1) }),
+                Some(Fingerprint { hash: 9, lines: (18, 24), cols: (1, 1) }),
                 None,
-                Some(Fingerprint { hash: 7, lines: (4, 8) }),
-                Some(Fingerprint { hash: 8, lines: (10, 24) }),
-                Some(Fingerprint { hash: 11, lines: (21, 40) })];
-
-            let table = vec![
-                vec![0, 0, 0, 0, 0, 0, 0, 0],
-                vec![0, 1, 0, 0, 0, 1, 0, 0],
-                vec![0, 0, 2, 0, 0, 0, 2, 0],
-                vec![0, 1, 0, 0, 0, 1, 0, 0],
-                vec![0, 0, 2, 0, 0, 0, 2, 0],
-                vec![0, 0, 0, 3, 0, 0, 0, 0]
-            ];
+                Some(Fingerprint { hash: 7, lines: (4, 8), cols: (1, 1) }),
+                Some(Fingerprint { hash: 8, lines: (10, 24), cols: (1, 1) }),
+                Some(Fingerprint { hash: 11, lines: (21, 40), cols: (1, 1) })];
+
 
             let mut exp = HashSet::new();
             exp.insert(SubString {
@@ -745,11 +812,13 @@ mod tests {
                 hashes: vec![7, 8],
                 a_entry: Entry {
                     doc_idx: 0,
-                    lines: (2, 22)
+                    lines: (2, 22),
+                    cols: (1, 1)
                 },
                 b_entry: Entry {
                     doc_idx: 0,
-                    lines: (14, 22)
+                    lines: (14, 22),
+                    cols: (1, 1)
                 }
             });
             exp.insert(SubString {
@@ -757,11 +826,13 @@ mod tests {
                 hashes: vec![7, 8, 9],
                 a_entry: Entry {
                     doc_idx: 0,
-                    lines: (30, 42)
+                    lines: (30, 42),
+                    cols: (1, 1)
                 },
                 b_entry: Entry {
                     doc_idx: 0,
-                    lines: (14, 24)
+                    lines: (14, 24),
+                    cols: (1, 1)
                 }
             });
             exp.insert(SubString {
@@ -769,15 +840,17 @@ mod tests {
                 hashes: vec![7, 8],
                 a_entry: Entry {
                     doc_idx: 0,
-                    lines: (2, 22)
+                    lines: (2, 22),
+                    cols: (1, 1)
                 },
                 b_entry: Entry {
                     doc_idx: 1,
-                    lines: (4, 24)
+                    lines: (4, 24),
+                    cols: (1, 1)
                 }
             });
 
-            assert_eq!(choose_substrs(&rows, &cols, &table), exp);
+            assert_eq!(choose_substrs(&rows, &cols, &set(vec![7, 8, 9])), exp);
         }
     }
 
@@ -787,20 +860,20 @@ mod tests {
             SubString {
                 size: 3,
                 hashes: vec![41, 25, 18],
-                a_entry: Entry { doc_idx: 0, lines: (2, 15) },
-                b_entry: Entry { doc_idx: 0, lines: (14, 29) }
+                a_entry: Entry { doc_idx: 0, lines: (2, 15), cols: (1, 1) },
+                b_entry: Entry { doc_idx: 0, lines: (14, 29), cols: (1, 1) }
             },
             SubString {
                 size: 3,
                 hashes: vec![99, 26, 30],
-                a_entry: Entry { doc_idx: 0, lines: (44, 59) },
-                b_entry: Entry { doc_idx: 0, lines: (16, 20) }
+                a_entry: Entry { doc_idx: 0, lines: (44, 59), cols: (1, 1) },
+                b_entry: Entry { doc_idx: 0, lines: (16, 20), cols: (1, 1) }
             },
             SubString {
                 size: 2,
                 hashes: vec![7, 10],
-                a_entry: Entry { doc_idx: 0, lines: (89, 102) },
-                b_entry: Entry { doc_idx: 0, lines: (66, 81) }
+                a_entry: Entry { doc_idx: 0, lines: (89, 102), cols: (1, 1) },
+                b_entry: Entry { doc_idx: 0, lines: (66, 81), cols: (1, 1) }
             }
         ];
 
@@ -829,50 +902,47 @@ mod tests {
         assert_eq!(col_out, exp_col_out);
     }
 
-    // turn a vector into a hashset (convenience)
-    fn set<T: Clone+Eq+std::hash::Hash>(elts: Vec<T>) -> HashSet<T> {
-        elts.iter().cloned().collect()
-    }
-
     #[test]
     fn test_analyze_pair() {
         {
             let a = Sub {
                 dir_name: Some(PathBuf::from("")),
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 11, lines: (2, 7) },
-                        Fingerprint { hash: 22, lines: (6, 12) },
-                        Fingerprint { hash: 33, lines: (8, 18) },
-                        Fingerprint { hash: 11, lines: (22, 30) }
+                        Fingerprint { hash: 11, lines: (2, 7), cols: (1, 1) },
+                        Fingerprint { hash: 22, lines: (6, 12), cols: (1, 1) },
+                        Fingerprint { hash: 33, lines: (8, 18), cols: (1, 1) },
+                        Fingerprint { hash: 11, lines: (22, 30), cols: (1, 1) }
                     ]),
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 22, lines: (1, 4) },
-                        Fingerprint { hash: 11, lines: (4, 5) },
-                        Fingerprint { hash: 22, lines: (5, 5) },
-                        Fingerprint { hash: 33, lines: (6, 9) },
-                        Fingerprint { hash: 67, lines: (10, 13) }
+                        Fingerprint { hash: 22, lines: (1, 4), cols: (1, 1) },
+                        Fingerprint { hash: 11, lines: (4, 5), cols: (1, 1) },
+                        Fingerprint { hash: 22, lines: (5, 5), cols: (1, 1) },
+                        Fingerprint { hash: 33, lines: (6, 9), cols: (1, 1) },
+                        Fingerprint { hash: 67, lines: (10, 13), cols: (1, 1) }
                     ])
                 ]
             };
             let b = Sub {
                 dir_name: Some(PathBuf::from("")),
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 22, lines: (3, 5) }
+                        Fingerprint { hash: 22, lines: (3, 5), cols: (1, 1) }
                     ]),
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 22, lines: (4, 4) },
-                        Fingerprint { hash: 33, lines: (5, 8) },
-                        Fingerprint { hash: 11, lines: (7, 11) }
+                        Fingerprint { hash: 22, lines: (4, 4), cols: (1, 1) },
+                        Fingerprint { hash: 33, lines: (5, 8), cols: (1, 1) },
+                        Fingerprint { hash: 11, lines: (7, 11), cols: (1, 1) }
                     ]),
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 98, lines: (2, 3) },
-                        Fingerprint { hash: 41, lines: (4, 10) },
-                        Fingerprint { hash: 22, lines: (15, 25) },
-                        Fingerprint { hash: 11, lines: (18, 27) },
-                        Fingerprint { hash: 22, lines: (29, 35) },
-                        Fingerprint { hash: 33, lines: (30, 39) }
+                        Fingerprint { hash: 98, lines: (2, 3), cols: (1, 1) },
+                        Fingerprint { hash: 41, lines: (4, 10), cols: (1, 1) },
+                        Fingerprint { hash: 22, lines: (15, 25), cols: (1, 1) },
+                        Fingerprint { hash: 11, lines: (18, 27), cols: (1, 1) },
+                        Fingerprint { hash: 22, lines: (29, 35), cols: (1, 1) },
+                        Fingerprint { hash: 33, lines: (30, 39), cols: (1, 1) }
                     ])
                 ]
             };
@@ -883,76 +953,79 @@ mod tests {
                 b: &b,
                 b_percent: 0.0,
                 matches: set(vec![11, 22, 33]),
-                percentile: 0.0
+                percentile: 0.0,
+                doc_pairs: vec![]
             };
 
             let exp_matches = vec![
                 // [22, 11, 22, 33]
                 Match {
                     size: 4,
-                    a_entries: set(vec![ Entry { doc_idx: 1, lines: (1, 9) } ]),
-                    b_entries: set(vec![ Entry { doc_idx: 2, lines: (15, 39) } ])
+                    a_entries: set(vec![ Entry { doc_idx: 1, lines: (1, 9), cols: (1, 1) } ]),
+                    b_entries: set(vec![ Entry { doc_idx: 2, lines: (15, 39), cols: (1, 1) } ])
                 },
                 // [11, 22, 33]
                 Match {
                     size: 3,
-                    a_entries: set(vec![ Entry { doc_idx: 0, lines: (2, 18) } ]),
-                    b_entries: set(vec![ Entry { doc_idx: 2, lines: (18, 39) } ])
+                    a_entries: set(vec![ Entry { doc_idx: 0, lines: (2, 18), cols: (1, 1) } ]),
+                    b_entries: set(vec![ Entry { doc_idx: 2, lines: (18, 39), cols: (1, 1) } ])
                 },
                 // [22, 33, 11]
                 Match {
                     size: 3,
-                    a_entries: set(vec![ Entry { doc_idx: 0, lines: (6, 30) } ]),
-                    b_entries: set(vec![ Entry { doc_idx: 1, lines: (4, 11) } ])
+                    a_entries: set(vec![ Entry { doc_idx: 0, lines: (6, 30), cols: (1, 1) } ]),
+                    b_entries: set(vec![ Entry { doc_idx: 1, lines: (4, 11), cols: (1, 1) } ])
                 },
                 // [22]
                 Match {
                     size: 1,
-                    a_entries: set(vec![ Entry { doc_idx: 0, lines: (6, 12) } ]),
-                    b_entries: set(vec![ Entry { doc_idx: 0, lines: (3, 5) } ])
+                    a_entries: set(vec![ Entry { doc_idx: 0, lines: (6, 12), cols: (1, 1) } ]),
+                    b_entries: set(vec![ Entry { doc_idx: 0, lines: (3, 5), cols: (1, 1) } ])
                 }
             ];
 
-            assert_eq!(analyze_pair(&sp), exp_matches);
+            assert_eq!(analyze_pair(&sp).unwrap(), exp_matches);
         }
         {
             let a = Sub {
                 dir_name: Some(PathBuf::from("")),
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 1, lines: (5, 5) },
-                        Fingerprint { hash: 2, lines: (6, 8) },
-                        Fingerprint { hash: 100, lines: (10, 20) },
-                        Fingerprint { hash: 200, lines: (24, 28) },
-                        Fingerprint { hash: 3, lines: (26, 33) },
-                        Fingerprint { hash: 100, lines: (29, 40) },
-                        Fingerprint { hash: 200, lines: (42, 48) },
-                        Fingerprint { hash: 4, lines: (50, 53) }
+                        Fingerprint { hash: 1, lines: (5, 5), cols: (1, 1) },
+                        Fingerprint { hash: 2, lines: (6, 8), cols: (1, 1) },
+                        Fingerprint { hash: 100, lines: (10, 20), cols: (1, 1) },
+                        Fingerprint { hash: 200, lines: (24, 28), cols: (1, 1) },
+                        Fingerprint { hash: 3, lines: (26, 33), cols: (1, 1) },
+                        Fingerprint { hash: 100, lines: (29, 40), cols: (1, 1) },
+                        Fingerprint { hash: 200, lines: (42, 48), cols: (1, 1) },
+                        Fingerprint { hash: 4, lines: (50, 53), cols: (1, 1) }
                     ]),
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 100, lines: (3, 7) },
-                        Fingerprint { hash: 200, lines: (5, 11) },
+                        Fingerprint { hash: 100, lines: (3, 7), cols: (1, 1) },
+                        Fingerprint { hash: 200, lines: (5, 11), cols: (1, 1) },
                     ])
                 ]
             };
             let b = Sub {
                 dir_name: Some(PathBuf::from("")),
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 5, lines: (5, 10) },
-                        Fingerprint { hash: 100, lines: (9, 18) },
-                        Fingerprint { hash: 200, lines: (17, 26) }
+                        Fingerprint { hash: 5, lines: (5, 10), cols: (1, 1) },
+                        Fingerprint { hash: 100, lines: (9, 18), cols: (1, 1) },
+                        Fingerprint { hash: 200, lines: (17, 26), cols: (1, 1) }
                     ]),
                     Doc::Processed(PathBuf::from(""), vec![
-                        Fingerprint { hash: 6, lines: (1, 22) },
-                        Fingerprint { hash: 7, lines: (18, 35) },
-                        Fingerprint { hash: 8, lines: (30, 36) },
-                        Fingerprint { hash: 100, lines: (39, 44) },
-                        Fingerprint { hash: 200, lines: (44, 45) },
-                        Fingerprint { hash: 9, lines: (47, 47) },
-                        Fingerprint { hash: 100, lines: (51, 52) },
-                        Fingerprint { hash: 200, lines: (55, 58) },
-                        Fingerprint { hash: 10, lines: (61, 66) }
+                        Fingerprint { hash: 6, lines: (1, 22), cols: (1, 1) },
+                        Fingerprint { hash: 7, lines: (18, 35), cols: (1, 1) },
+                        Fingerprint { hash: 8, lines: (30, 36), cols: (1, 1) },
+                        Fingerprint { hash: 100, lines: (39, 44), cols: (1, 1) },
+                        Fingerprint { hash: 200, lines: (44, 45), cols: (1, 1) },
+                        Fingerprint { hash: 9, lines: (47, 47), cols: (1, 1) },
+                        Fingerprint { hash: 100, lines: (51, 52), cols: (1, 1) },
+                        Fingerprint { hash: 200, lines: (55, 58), cols: (1, 1) },
+                        Fingerprint { hash: 10, lines: (61, 66), cols: (1, 1) }
                     ])
                 ]
             };
@@ -963,7 +1036,8 @@ mod tests {
                 b: &b,
                 b_percent: 0.0,
                 matches: set(vec![100, 200]),
-                percentile: 0.0
+                percentile: 0.0,
+                doc_pairs: vec![]
             };
 
             let exp_matches = vec![
@@ -971,19 +1045,94 @@ mod tests {
                 Match {
                     size: 2,
                     a_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (10, 28) },
-                        Entry { doc_idx: 0, lines: (29, 48) },
-                        Entry { doc_idx: 1, lines: (3, 11) }
+                        Entry { doc_idx: 0, lines: (10, 28), cols: (1, 1) },
+                        Entry { doc_idx: 0, lines: (29, 48), cols: (1, 1) },
+                        Entry { doc_idx: 1, lines: (3, 11), cols: (1, 1) }
                     ]),
                     b_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (9, 26) },
-                        Entry { doc_idx: 1, lines: (39, 45) },
-                        Entry { doc_idx: 1, lines: (51, 58) }
+                        Entry { doc_idx: 0, lines: (9, 26), cols: (1, 1) },
+                        Entry { doc_idx: 1, lines: (39, 45), cols: (1, 1) },
+                        Entry { doc_idx: 1, lines: (51, 58), cols: (1, 1) }
                     ])
                 }
             ];
 
-            assert_eq!(analyze_pair(&sp), exp_matches);
+            assert_eq!(analyze_pair(&sp).unwrap(), exp_matches);
         }
     }
+
+    #[test]
+    fn test_runs() {
+        let fp = |hash, lines: (i32, i32), cols: (i32, i32)| Fingerprint { hash, lines, cols };
+        let sub = Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![
+                Doc::Processed(PathBuf::from("a.arr"), vec![
+                    fp(1, (1, 2), (4, 3)),
+                    fp(2, (2, 3), (1, 8)),
+                    fp(3, (4, 4), (1, 9)),
+                    fp(1, (5, 6), (2, 2))
+                ]),
+                Doc::Processed(PathBuf::from("b.arr"), vec![
+                    fp(2, (7, 7), (3, 12))
+                ])
+            ]
+        };
+
+        // runs end at unkept fingerprints & at the ends of documents
+        assert_eq!(runs(&sub, &set(vec![1, 2])).unwrap(), vec![
+            (Entry { doc_idx: 0, lines: (1, 3), cols: (4, 8) }, vec![1, 2]),
+            (Entry { doc_idx: 0, lines: (5, 6), cols: (2, 2) }, vec![1]),
+            (Entry { doc_idx: 1, lines: (7, 7), cols: (3, 12) }, vec![2])
+        ]);
+        assert!(runs(&sub, &set(vec![9])).unwrap().is_empty());
+
+        // documents must be fingerprinted first
+        let unprocessed = Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![Doc::Unprocessed(PathBuf::from("a.arr"))]
+        };
+        assert!(matches!(runs(&unprocessed, &set(vec![1])), Err(MossError::UnprocessedDoc(_))));
+        assert!(matches!(flatten_docs(&unprocessed), Err(MossError::UnprocessedDoc(_))));
+    }
+
+    #[test]
+    fn test_analyze_large_pair() {
+        // two 20k-fingerprint submissions sharing a single run of 50 fingerprints
+        // (a full substring table would hold 400 million cells)
+        let fps = |offset: i64, shared_at: usize| -> Vec<Fingerprint> {
+            (0..20_000).map(|i| {
+                let hash = if (shared_at..shared_at + 50).contains(&i) {
+                    (i - shared_at) as i64
+                } else {
+                    offset + i as i64
+                };
+                Fingerprint { hash, lines: (i as i32, i as i32), cols: (1, 1) }
+            }).collect()
+        };
+
+        let a = Sub { dir_name: None, archived: false,
+            documents: vec![Doc::Processed(PathBuf::from(""), fps(1_000_000, 100))] };
+        let b = Sub { dir_name: None, archived: false,
+            documents: vec![Doc::Processed(PathBuf::from(""), fps(2_000_000, 15_000))] };
+
+        let sp = SubPair {
+            a: &a,
+            a_percent: 0.0,
+            b: &b,
+            b_percent: 0.0,
+            matches: (0..50).collect(),
+            percentile: 0.0,
+            doc_pairs: vec![]
+        };
+
+        let matches = analyze_pair(&sp).unwrap();
+        assert_eq!(matches, vec![Match {
+            size: 50,
+            a_entries: set(vec![ Entry { doc_idx: 0, lines: (100, 149), cols: (1, 1) } ]),
+            b_entries: set(vec![ Entry { doc_idx: 0, lines: (15_000, 15_049), cols: (1, 1) } ])
+        }]);
+    }
 }
@@ -1,10 +1,15 @@
-/// results.rs: Render findings of overlap between submissions, if any
+//! results.rs: Render findings of overlap between submissions, if any
 
-use crate::{Sub, Doc};
-use std::path::{Path, PathBuf, Component};
-use crate::cli::SubFileMode;
+use crate::{Sub, Doc, Overlaps};
+use std::path::{Path, Component};
+use crate::cli::{OptArgs, OutputFormat, SubFileMode};
+use crate::error::Result;
 use crate::phase_ii::SubPair;
+use crate::cluster::Fragment;
 use crate::io_redirect;
+use crate::json;
+use crate::html;
+use crate::csv;
 use crate::phase_iii::{self, Entry, Match};
 use std::collections::HashSet;
 use prettytable::Table;
@@ -12,10 +17,44 @@ use prettytable::Table;
 // number of results to display before prompting the user to continue
 const RESULT_BUFFER_SIZE: usize = 50;
 
-/// Given a vector of matched submission pairs ordered by amount of overlap,
-/// render a message (to stdout or the given file) summarizing the overlaps
-pub fn render_results(sub_dir: &Path, sub_pairs: Vec<SubPair>, mode: &SubFileMode, out_file: Option<&Path>,
-    match_thresh: f64, total_pairs: usize, no_pauses: bool, verbose: bool) {
+/// Given the overlaps found between submissions (whose pairs are ordered by amount
+/// of overlap), render a message (to stdout or the given file) summarizing them,
+/// in the output format indicated by the options
+pub fn render_results(sub_dir: &Path, overlaps: &Overlaps, opts: &OptArgs) -> Result<()> {
+    // the HTML, CSV & JSON reports need the matches of every pair, so analyze common
+    // substrings of fingerprints in each pair once up front if any of them is requested
+    // (otherwise, tables analyze each pair only as it's rendered)
+    let eager = opts.html_dir.is_some() || opts.csv_file.is_some() || opts.format == OutputFormat::Json;
+    let matches: Option<Vec<Vec<Match>>> = if eager {
+        Some(overlaps.pairs.iter().map(phase_iii::analyze_pair).collect::<Result<_>>()?)
+    } else {
+        None
+    };
+    let all_matches = matches.as_deref().unwrap_or_default();
+
+    // write the HTML & CSV reports first, as rendering to the terminal may pause
+    if let Some(html_dir) = opts.html_dir {
+        html::render_html(html_dir, sub_dir, overlaps, all_matches, opts)?;
+    }
+
+    if let Some(csv_file) = opts.csv_file {
+        csv::render_csv(csv_file, &overlaps.pairs, all_matches, opts)?;
+    }
+
+    match opts.format {
+        OutputFormat::Table => render_tables(sub_dir, overlaps, matches.as_deref(), opts),
+        OutputFormat::Json => json::render_json(sub_dir, overlaps, all_matches, opts),
+    }
+}
+
+/// Render a header & table for each submission pair, given the matches of
+/// each if already analyzed (otherwise, each pair is analyzed as it's rendered)
+fn render_tables(sub_dir: &Path, overlaps: &Overlaps, matches: Option<&[Vec<Match>]>,
+    opts: &OptArgs) -> Result<()> {
+    let (sub_pairs, total_pairs) = (&overlaps.pairs, overlaps.total_pairs);
+    let mode = &opts.sub_mode;
+    let out_file = opts.out_file;
+    let (no_pauses, verbose) = (opts.no_pauses, opts.verbose);
 
     if verbose {
         println!("\nRendering results...");
@@ -27,7 +66,7 @@ pub fn render_results(sub_dir: &Path, sub_pairs: Vec<SubPair>, mode: &SubFileMod
     let mut redirect = match out_file {
         Some(p) => {
             if verbose { println!("Redirecting output to {}", p.display()); }
-            Some(io_redirect::initialize_redirect(p))
+            Some(io_redirect::initialize_redirect(p)?)
         },
         None => None,
     };
@@ -35,16 +74,54 @@ pub fn render_results(sub_dir: &Path, sub_pairs: Vec<SubPair>, mode: &SubFileMod
     // show a header message with the submissions dir path
     format::results_header(sub_dir);
 
-    // if no submission pairs were found in Phase II, exit
+    // mention any fingerprints that were too common to be considered
+    if overlaps.suppressed > 0 {
+        format::suppressed_msg(overlaps.suppressed);
+    }
+
+    // render groups of submissions sharing fragments, if grouping (groups
+    // don't depend on pairs, so may be found even when no pairs are)
+    if let Some(size) = opts.group_size {
+        format::num_groups(size, overlaps.groups.len());
+
+        for (i, group) in overlaps.groups.iter().enumerate() {
+            let names: Vec<String> = group.members.iter().map(|sub| sub_name(sub, mode)).collect();
+            format::group_header(redirecting, i + 1, &names);
+
+            fragment_table(&group.members, &group.fragments, &names, mode).printstd();
+        }
+    }
+
+    // if no submission pairs were found in Phase II, there's nothing more to render
     if sub_pairs.is_empty() {
         format::no_overlap_msg(redirecting);
-        std::process::exit(0);
+        return Ok(());
     }
 
     format::overlap_found_msg(redirecting);
 
     let total_pairs_rendering = sub_pairs.len();
-    format::num_pairs_rendering(redirecting, match_thresh, total_pairs, total_pairs_rendering);
+    format::num_pairs_rendering(redirecting, opts.match_threshold, total_pairs, total_pairs_rendering,
+        opts.lsh.is_some());
+
+    // render clusters before the pairs they're made of, if clustering
+    if let Some(similarity) = opts.cluster_similarity {
+        format::num_clusters(similarity, overlaps.clusters.len());
+
+        for (i, cluster) in overlaps.clusters.iter().enumerate() {
+            let names: Vec<String> = cluster.members.iter().map(|sub| sub_name(sub, mode)).collect();
+            format::cluster_header(redirecting, i + 1, &names);
+
+            // the scores of every pair within the cluster
+            for &pair_idx in cluster.pairs.iter() {
+                let pair = &sub_pairs[pair_idx];
+                format::cluster_pair_line(pair_idx + 1, &sub_name(pair.a, mode), pair.a_percent,
+                    &sub_name(pair.b, mode), pair.b_percent, pair.matches.len());
+            }
+
+            fragment_table(&cluster.members, &cluster.fragments, &names, mode).printstd();
+        }
+    }
 
     // for each pair & its index
     for (i, pair) in sub_pairs.iter().enumerate() {
@@ -55,12 +132,12 @@ pub fn render_results(sub_dir: &Path, sub_pairs: Vec<SubPair>, mode: &SubFileMod
 
             // wait for user to confirm to continue
             format::pair_progress(redirecting, i, total_pairs_rendering);
-            io_redirect::confirm_continue();
+            if !io_redirect::confirm_continue() { return Ok(()); }
 
             // resume redirecting stdout
             if redirecting {
                 match out_file {
-                    Some(p) => io_redirect::resume_redirect(&mut redirect, p),
+                    Some(p) => io_redirect::resume_redirect(&mut redirect, p)?,
                     None => {
                         panic!("set to redirect, but no out file found");
                     },
@@ -81,11 +158,31 @@ pub fn render_results(sub_dir: &Path, sub_pairs: Vec<SubPair>, mode: &SubFileMod
             pair.matches.len(),
             pair.percentile);
 
-        // analyze common substrings of fingerprints to get a vector of matches
-        let matches = phase_iii::analyze_pair(pair);
+        // break the overlap down by document, when submissions have several
+        if let SubFileMode::Multi = mode {
+            for dp in pair.doc_pairs.iter() {
+                format::doc_pair_line(
+                    &doc_name(pair.a, dp.a_doc),
+                    dp.a_percent,
+                    &doc_name(pair.b, dp.b_doc),
+                    dp.b_percent,
+                    dp.matches);
+            }
+        }
 
-        pair_table(pair, (&sub_a_name, &sub_b_name), matches, mode).printstd();
+        // analyze this pair's matches now, unless they already were
+        let analyzed;
+        let pair_matches = match matches {
+            Some(matches) => &matches[i],
+            None => {
+                analyzed = phase_iii::analyze_pair(pair)?;
+                &analyzed
+            },
+        };
+        pair_table(pair, (&sub_a_name, &sub_b_name), pair_matches, mode).printstd();
     }
+
+    Ok(())
 }
 
 /// Wrappers for printing messages in result rendering, because
@@ -119,6 +216,11 @@ mod format {
         println!("\nSubmissions Directory: {}", sub_dir.display());
     }
 
+    /// print a message indicating how many fingerprints were suppressed as too common
+    pub fn suppressed_msg(suppressed: usize) {
+        println!("Ignored {} fingerprints shared by too many submissions (see --max-frequency)", suppressed);
+    }
+
     /// print a message indicating that no overlap between submission was found
     pub fn no_overlap_msg(redir: bool) {
         let message = "Aye, no overlap was found!";
@@ -140,10 +242,11 @@ mod format {
     }
 
     /// print a message indicating how many submission pairs will be rendered
-    pub fn num_pairs_rendering(_redir: bool, thresh: f64, total: usize, total_render: usize) {
+    /// (with LSH, the total only counts candidate pairs)
+    pub fn num_pairs_rendering(_redir: bool, thresh: f64, total: usize, total_render: usize, lsh: bool) {
         if thresh > 0.0 {
-            println!("Rendering pairs at least {:.2}% of max matches: {} kept / {} total",
-                thresh * 100.0, total_render, total);
+            println!("Rendering pairs at least {:.2}% of max matches: {} kept / {} total{}",
+                thresh * 100.0, total_render, total, if lsh { " candidates (see --lsh)" } else { "" });
         } else {
             println!("Rendering all submission pairs ({} total)", total_render);
         }
@@ -164,6 +267,50 @@ mod format {
         println!("\nPair {}: {} and {}: {} ({:.2}% of max)", n, a_fmt, b_fmt, match_fmt, perc_of_max * 100.0);
     }
 
+    /// print the overlap between one document of each submission in a pair
+    pub fn doc_pair_line(a_name: &str, a_percent: f64, b_name: &str, b_percent: f64, matches: usize) {
+        println!("    {} ({:.2}%) vs {} ({:.2}%): {} matches", a_name, a_percent * 100.0,
+            b_name, b_percent * 100.0, matches);
+    }
+
+    /// print a message indicating how many clusters were found
+    pub fn num_clusters(similarity: f64, clusters: usize) {
+        println!("Clusters of submissions linked by pairs at least {:.2}% similar: {}",
+            similarity * 100.0, clusters);
+    }
+
+    /// print the header indicating cluster number & the names of its members
+    pub fn cluster_header(redir: bool, n: usize, names: &[String]) {
+        let members = names.join(", ");
+
+        let members_fmt = cond_fmt!(redir, &members,
+            White.bold().paint(&members));
+
+        println!("\nCluster {}: {} ({} submissions)", n, members_fmt, names.len());
+    }
+
+    /// print the scores of a pair within a cluster
+    pub fn cluster_pair_line(n: usize, a_name: &str, a_percent: f64, b_name: &str, b_percent: f64,
+        matches: usize) {
+        println!("    Pair {}: {} ({:.2}%) & {} ({:.2}%): {} matches", n, a_name, a_percent * 100.0,
+            b_name, b_percent * 100.0, matches);
+    }
+
+    /// print a message indicating how many groups were found
+    pub fn num_groups(size: usize, groups: usize) {
+        println!("Groups of {} or more submissions sharing fragments: {}", size, groups);
+    }
+
+    /// print the header indicating group number & the names of its members
+    pub fn group_header(redir: bool, n: usize, names: &[String]) {
+        let members = names.join(", ");
+
+        let members_fmt = cond_fmt!(redir, &members,
+            White.bold().paint(&members));
+
+        println!("\nGroup {}: {} ({} submissions)", n, members_fmt, names.len());
+    }
+
     /// print a message indicating how many pairs have been rendered so far
     pub fn pair_progress(_redir: bool, so_far: usize, total: usize) {
         let message = format!("Pausing at {} / {} pairs rendered.", so_far, total);
@@ -177,12 +324,14 @@ mod format {
 /// Extract a "name" for a submission (for use in output) based on the sub mode:
 /// - single-file: subs are named by their only document's filename
 /// - multi-file: subs are named by the dir that contains their document files
-fn sub_name(sub: &Sub, mode: &SubFileMode) -> String {
-    match mode {
+///
+/// Archived submissions are labeled as such.
+pub(crate) fn sub_name(sub: &Sub, mode: &SubFileMode) -> String {
+    let name = match mode {
         SubFileMode::Multi => {
             // retrieve the name of the bottom-most level dir from a pathbuf
-            fn lowest_dir(p: &PathBuf) -> &str {
-                let comp = p.components().last().unwrap();
+            fn lowest_dir(p: &Path) -> &str {
+                let comp = p.components().next_back().unwrap();
 
                 // extract the string inside
                 if let Component::Normal(os_str) = comp {
@@ -201,21 +350,37 @@ fn sub_name(sub: &Sub, mode: &SubFileMode) -> String {
             }
 
             // extract the filename from a path
-            fn file_name(p: &PathBuf) -> String {
+            fn file_name(p: &Path) -> String {
                 String::from(p.file_name().unwrap().to_str().unwrap())
             }
 
             // extract file names of each submissions' singular doc
             match &sub.documents[0] {
-                Doc::Processed(path, _) => file_name(path),
-                _ => { panic!("unprocessed document encountered in {:?}", sub.documents[0]); },
+                Doc::Unprocessed(path) | Doc::Processed(path, _) => file_name(path),
             }
         }
+    };
+
+    if sub.archived { format!("{} (archive)", name) } else { name }
+}
+
+/// Extract a "name" for the document at the given index in a submission: its filename,
+/// preceded by the submission's dir in multi-file mode (e.g. "sub1/main.arr")
+pub(crate) fn doc_name(sub: &Sub, doc_idx: usize) -> String {
+    let file_name = |p: &Path| String::from(p.file_name().unwrap().to_str().unwrap());
+
+    let doc = match &sub.documents[doc_idx] {
+        Doc::Unprocessed(path) | Doc::Processed(path, _) => file_name(path),
+    };
+
+    match &sub.dir_name {
+        Some(dir) => format!("{}/{}", file_name(dir), doc),
+        None => doc,
     }
 }
 
 /// Generate a table summarizing fingerprint matches for a given pair of submissions
-fn pair_table(pair: &SubPair, names: (&String, &String), matches: Vec<Match>, mode: &SubFileMode) -> Table {
+fn pair_table(pair: &SubPair, names: (&String, &String), matches: &[Match], mode: &SubFileMode) -> Table {
     let mut table = Table::new();
 
     let (a_name, b_name) = names;
@@ -238,14 +403,39 @@ fn pair_table(pair: &SubPair, names: (&String, &String), matches: Vec<Match>, mo
     table   // constructed table for this pair
 }
 
+/// Generate a table of the fragments shared by every member of a cluster or group,
+/// with a column locating them in each member
+fn fragment_table(members: &[&Sub], fragments: &[Fragment], names: &[String], mode: &SubFileMode) -> Table {
+    let mut table = Table::new();
+
+    // add title row: member names
+    let mut title = row!["(size)"];
+    for name in names.iter() { title.add_cell(cell!(Fcbic->name)); }
+    table.add_row(title);
+
+    // add each fragment to table
+    for fragment in fragments.iter() {
+        let mut row = row![bc->fragment.size];
+
+        for (sub, entries) in members.iter().zip(fragment.entries.iter()) {
+            let entries: HashSet<Entry> = entries.iter().cloned().collect();
+            row.add_cell(cell!(format_entries(&entries, sub, mode)));
+        }
+
+        table.add_row(row);
+    }
+
+    table   // constructed table for these fragments
+}
+
 /// Generate a string describing the given entries, for a single cell of a sub pair table
 fn format_entries(entries: &HashSet<Entry>, sub: &Sub, mode: &SubFileMode) -> String {
-    let mut entries: Vec<_> = entries.into_iter().collect();
+    let mut entries: Vec<_> = entries.iter().collect();
 
     // first sort by line range beginnings, then sort by
     // document index to group docs together for readability
-    entries.sort_by(|a, b| a.lines.0.cmp(&b.lines.0));
-    entries.sort_by(|a, b| a.doc_idx.cmp(&b.doc_idx));
+    entries.sort_by_key(|a| a.lines.0);
+    entries.sort_by_key(|a| a.doc_idx);
 
     let mut entry_text = Vec::new();
 
@@ -254,15 +444,13 @@ fn format_entries(entries: &HashSet<Entry>, sub: &Sub, mode: &SubFileMode) -> St
 
         // if multi-file mode
         if let SubFileMode::Multi = mode {
-            let doc = &sub.documents[entry.doc_idx];
+            let path = match &sub.documents[entry.doc_idx] {
+                Doc::Unprocessed(path) | Doc::Processed(path, _) => path,
+            };
 
             // write document filename to line
-            if let Doc::Processed(path, _) = doc {
-                let fname = path.file_name().unwrap().to_str().unwrap();
-                entry_line.push_str(&format!("{} ", fname));
-            } else {
-                panic!("invalid document encountered while formatting output: {:?}", doc);
-            }
+            let fname = path.file_name().unwrap().to_str().unwrap();
+            entry_line.push_str(&format!("{} ", fname));
         }
 
         let (start, end) = entry.lines;
@@ -282,6 +470,7 @@ fn format_entries(entries: &HashSet<Entry>, sub: &Sub, mode: &SubFileMode) -> St
 
 
 #[cfg(test)]
+#[allow(clippy::redundant_field_names)]
 mod tests {
     use super::*;
     use std::collections::HashSet;
@@ -294,6 +483,7 @@ mod tests {
         {
             let sub = Sub {
                 dir_name: Some(PathBuf::from("all-subs/sub-abcd/")),
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from("all-subs/sub-abcd/main.arr"), vec![]),
                     Doc::Processed(PathBuf::from("all-subs/sub-abcd/tests.arr"), vec![])
@@ -306,6 +496,7 @@ mod tests {
         {
             let sub = Sub {
                 dir_name: Some(PathBuf::from("all-subs/sub-xyz/")),
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from("all-subs/sub-xyz/main.arr"), vec![]),
                     Doc::Processed(PathBuf::from("all-subs/sub-xyz/tests.arr"), vec![])
@@ -318,6 +509,7 @@ mod tests {
         {
             let sub = Sub {
                 dir_name: Some(PathBuf::from("all-subs/sub-lmn/")),
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from("all-subs/sub-lmn/main.arr"), vec![]),
                     Doc::Processed(PathBuf::from("all-subs/sub-lmn/tests.arr"), vec![])
@@ -333,6 +525,7 @@ mod tests {
         {
             let sub = Sub {
                 dir_name: None,
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from("all-subs/submissionA.arr"), vec![])
                 ]
@@ -345,6 +538,7 @@ mod tests {
         {
             let sub = Sub {
                 dir_name: None,
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from("~/Desktop/nested/dirs/all-subs/this-is-the-sub-name.arr"), vec![])
                 ]
@@ -354,6 +548,21 @@ mod tests {
             let exp_name = String::from("this-is-the-sub-name.arr");
             assert_eq!(name, exp_name);
         }
+
+        // archived submissions
+        {
+            let sub = Sub {
+                dir_name: Some(PathBuf::from("2019/sub-abcd/")),
+                archived: true,
+                documents: vec![
+                    Doc::Processed(PathBuf::from("2019/sub-abcd/main.arr"), vec![])
+                ]
+            };
+
+            let name = sub_name(&sub, &SubFileMode::Multi);
+            let exp_name = String::from("sub-abcd/ (archive)");
+            assert_eq!(name, exp_name);
+        }
     }
 
     // turn a vector into a hashset (convenience)
@@ -361,40 +570,62 @@ mod tests {
         elts.iter().cloned().collect()
     }
 
+    #[test]
+    fn test_doc_name() {
+        let multi = Sub {
+            dir_name: Some(PathBuf::from("all-subs/sub-abcd/")),
+            archived: true,
+            documents: vec![
+                Doc::Processed(PathBuf::from("all-subs/sub-abcd/main.arr"), vec![]),
+                Doc::Processed(PathBuf::from("all-subs/sub-abcd/tests.arr"), vec![])
+            ]
+        };
+        assert_eq!(doc_name(&multi, 1), "sub-abcd/tests.arr");
+
+        let single = Sub {
+            dir_name: None,
+            archived: false,
+            documents: vec![Doc::Processed(PathBuf::from("all-subs/sub-xyz.arr"), vec![])]
+        };
+        assert_eq!(doc_name(&single, 0), "sub-xyz.arr");
+    }
+
     #[test]
     fn test_pair_table() {
         {
             let a = Sub {
                 dir_name: Some(PathBuf::from("sub1/")),
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from("sub1/doc1.arr"), vec![
-                        Fingerprint { hash: 17, lines: (1, 3) },
-                        Fingerprint { hash: 20, lines: (5, 5) },
-                        Fingerprint { hash: 17, lines: (6, 10) },
-                        Fingerprint { hash: 11, lines: (10, 11) },
-                        Fingerprint { hash: 11, lines: (12, 15) }
+                        Fingerprint { hash: 17, lines: (1, 3), cols: (1, 1) },
+                        Fingerprint { hash: 20, lines: (5, 5), cols: (1, 1) },
+                        Fingerprint { hash: 17, lines: (6, 10), cols: (1, 1) },
+                        Fingerprint { hash: 11, lines: (10, 11), cols: (1, 1) },
+                        Fingerprint { hash: 11, lines: (12, 15), cols: (1, 1) }
                     ]),
                     Doc::Processed(PathBuf::from("sub1/doc2.arr"), vec![
-                        Fingerprint { hash: 51, lines: (21, 24) },
-                        Fingerprint { hash: 20, lines: (25, 30) },
-                        Fingerprint { hash: 17, lines: (44, 57) }
+                        Fingerprint { hash: 51, lines: (21, 24), cols: (1, 1) },
+                        Fingerprint { hash: 20, lines: (25, 30), cols: (1, 1) },
+                        Fingerprint { hash: 17, lines: (44, 57), cols: (1, 1) }
                     ])
                 ]
             };
             let b = Sub {
                 dir_name: Some(PathBuf::from("sub2/")),
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from("sub2/doc1.arr"), vec![
-                        Fingerprint { hash: 11, lines: (5, 5) },
-                        Fingerprint { hash: 17, lines: (8, 12) },
-                        Fingerprint { hash: 40, lines: (12, 12) },
-                        Fingerprint { hash: 11, lines: (17, 30) },
-                        Fingerprint { hash: 33, lines: (29, 34) }
+                        Fingerprint { hash: 11, lines: (5, 5), cols: (1, 1) },
+                        Fingerprint { hash: 17, lines: (8, 12), cols: (1, 1) },
+                        Fingerprint { hash: 40, lines: (12, 12), cols: (1, 1) },
+                        Fingerprint { hash: 11, lines: (17, 30), cols: (1, 1) },
+                        Fingerprint { hash: 33, lines: (29, 34), cols: (1, 1) }
                     ]),
                     Doc::Processed(PathBuf::from("sub2/doc2.arr"), vec![
-                        Fingerprint { hash: 12, lines: (3, 4) },
-                        Fingerprint { hash: 28, lines: (4, 4) },
-                        Fingerprint { hash: 20, lines: (8, 10) }
+                        Fingerprint { hash: 12, lines: (3, 4), cols: (1, 1) },
+                        Fingerprint { hash: 28, lines: (4, 4), cols: (1, 1) },
+                        Fingerprint { hash: 20, lines: (8, 10), cols: (1, 1) }
                     ])
                 ]
             };
@@ -406,7 +637,8 @@ mod tests {
                 b: &b,
                 b_percent: 0.78,
                 matches: matches,
-                percentile: 0.55
+                percentile: 0.55,
+                doc_pairs: vec![]
             };
 
             let a_name = String::from("sub1/");
@@ -417,35 +649,35 @@ mod tests {
                 Match {
                     size: 1,
                     a_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (1, 3) },
-                        Entry { doc_idx: 0, lines: (6, 10) },
-                        Entry { doc_idx: 1, lines: (44, 57) },
+                        Entry { doc_idx: 0, lines: (1, 3), cols: (1, 1) },
+                        Entry { doc_idx: 0, lines: (6, 10), cols: (1, 1) },
+                        Entry { doc_idx: 1, lines: (44, 57), cols: (1, 1) },
                     ]),
                     b_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (8, 12) }
+                        Entry { doc_idx: 0, lines: (8, 12), cols: (1, 1) }
                     ])
                 },
                 // [20]
                 Match {
                     size: 1,
                     a_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (5, 5) },
-                        Entry { doc_idx: 1, lines: (25, 30) },
+                        Entry { doc_idx: 0, lines: (5, 5), cols: (1, 1) },
+                        Entry { doc_idx: 1, lines: (25, 30), cols: (1, 1) },
                     ]),
                     b_entries: set(vec![
-                        Entry { doc_idx: 1, lines: (8, 10) }
+                        Entry { doc_idx: 1, lines: (8, 10), cols: (1, 1) }
                     ])
                 },
                 // [11]
                 Match {
                     size: 1,
                     a_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (10, 11) },
-                        Entry { doc_idx: 0, lines: (12, 15) },
+                        Entry { doc_idx: 0, lines: (10, 11), cols: (1, 1) },
+                        Entry { doc_idx: 0, lines: (12, 15), cols: (1, 1) },
                     ]),
                     b_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (5, 5) },
-                        Entry { doc_idx: 0, lines: (17, 30) }
+                        Entry { doc_idx: 0, lines: (5, 5), cols: (1, 1) },
+                        Entry { doc_idx: 0, lines: (17, 30), cols: (1, 1) }
                     ])
                 }
             ];
@@ -457,33 +689,35 @@ mod tests {
                 [bc->"1", "doc1.arr lines 10-11\ndoc1.arr lines 12-15", "doc1.arr line 5\ndoc1.arr lines 17-30"] // fp 11
             );
 
-            let out = pair_table(&sp, (&a_name, &b_name), matches, &SubFileMode::Multi);
+            let out = pair_table(&sp, (&a_name, &b_name), &matches, &SubFileMode::Multi);
 
             assert_eq!(out, exp_table);
         }
         {
             let a = Sub {
                 dir_name: None,
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from("submissions/sub1.arr"), vec![
-                        Fingerprint { hash: 28, lines: (4, 5) },
-                        Fingerprint { hash: 12, lines: (5, 5) },
-                        Fingerprint { hash: 28, lines: (11, 15) },
-                        Fingerprint { hash: 28, lines: (16, 19) },
-                        Fingerprint { hash: 28, lines: (18, 22) },
-                        Fingerprint { hash: 17, lines: (30, 31) }
+                        Fingerprint { hash: 28, lines: (4, 5), cols: (1, 1) },
+                        Fingerprint { hash: 12, lines: (5, 5), cols: (1, 1) },
+                        Fingerprint { hash: 28, lines: (11, 15), cols: (1, 1) },
+                        Fingerprint { hash: 28, lines: (16, 19), cols: (1, 1) },
+                        Fingerprint { hash: 28, lines: (18, 22), cols: (1, 1) },
+                        Fingerprint { hash: 17, lines: (30, 31), cols: (1, 1) }
                     ])
                 ]
             };
             let b = Sub {
                 dir_name: None,
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from("submissions/sub2.arr"), vec![
-                        Fingerprint { hash: 31, lines: (9, 15) },
-                        Fingerprint { hash: 28, lines: (17, 17) },
-                        Fingerprint { hash: 28, lines: (17, 29) },
-                        Fingerprint { hash: 17, lines: (30, 31) },
-                        Fingerprint { hash: 12, lines: (38, 42) }
+                        Fingerprint { hash: 31, lines: (9, 15), cols: (1, 1) },
+                        Fingerprint { hash: 28, lines: (17, 17), cols: (1, 1) },
+                        Fingerprint { hash: 28, lines: (17, 29), cols: (1, 1) },
+                        Fingerprint { hash: 17, lines: (30, 31), cols: (1, 1) },
+                        Fingerprint { hash: 12, lines: (38, 42), cols: (1, 1) }
                     ])
                 ]
             };
@@ -495,7 +729,8 @@ mod tests {
                 b: &b,
                 b_percent: 0.31,
                 matches: matches,
-                percentile: 0.55
+                percentile: 0.55,
+                doc_pairs: vec![]
             };
 
             let a_name = String::from("sub1.arr");
@@ -506,40 +741,40 @@ mod tests {
                 Match {
                     size: 3,
                     a_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (16, 31) }
+                        Entry { doc_idx: 0, lines: (16, 31), cols: (1, 1) }
                     ]),
                     b_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (17, 31) }
+                        Entry { doc_idx: 0, lines: (17, 31), cols: (1, 1) }
                     ])
                 },
                 // [28. 28]
                 Match {
                     size: 2,
                     a_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (11, 19) }
+                        Entry { doc_idx: 0, lines: (11, 19), cols: (1, 1) }
                     ]),
                     b_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (17, 29) }
+                        Entry { doc_idx: 0, lines: (17, 29), cols: (1, 1) }
                     ])
                 },
                 // [28]
                 Match {
                     size: 1,
                     a_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (4, 5) }
+                        Entry { doc_idx: 0, lines: (4, 5), cols: (1, 1) }
                     ]),
                     b_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (17, 17) }
+                        Entry { doc_idx: 0, lines: (17, 17), cols: (1, 1) }
                     ])
                 },
                 // [12]
                 Match {
                     size: 1,
                     a_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (5, 5) }
+                        Entry { doc_idx: 0, lines: (5, 5), cols: (1, 1) }
                     ]),
                     b_entries: set(vec![
-                        Entry { doc_idx: 0, lines: (38, 42) }
+                        Entry { doc_idx: 0, lines: (38, 42), cols: (1, 1) }
                     ])
                 }
             ];
@@ -552,7 +787,7 @@ mod tests {
                 [bc->"1", "line 5", "lines 38-42"] // [12]
             );
 
-            let out = pair_table(&sp, (&a_name, &b_name), matches, &SubFileMode::Single);
+            let out = pair_table(&sp, (&a_name, &b_name), &matches, &SubFileMode::Single);
 
             assert_eq!(out, exp_table);
         }
@@ -562,14 +797,15 @@ mod tests {
     fn test_format_entries() {
         {
             let entries = set(vec![
-                Entry { doc_idx: 2, lines: (15, 18) },
-                Entry { doc_idx: 0, lines: (1, 4) },
-                Entry { doc_idx: 2, lines: (2, 8) },
-                Entry { doc_idx: 1, lines: (3, 20) }
+                Entry { doc_idx: 2, lines: (15, 18), cols: (1, 1) },
+                Entry { doc_idx: 0, lines: (1, 4), cols: (1, 1) },
+                Entry { doc_idx: 2, lines: (2, 8), cols: (1, 1) },
+                Entry { doc_idx: 1, lines: (3, 20), cols: (1, 1) }
             ]);
 
             let sub = Sub {
                 dir_name: Some(PathBuf::from("~/submissions/sub/")),
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from("~/submissions/sub/one.arr"), Vec::new()),
                     Doc::Processed(PathBuf::from("~/submissions/sub/two.arr"), Vec::new()),
@@ -583,13 +819,14 @@ mod tests {
         }
         {
             let entries = set(vec![
-                Entry { doc_idx: 3, lines: (2, 5) },
-                Entry { doc_idx: 0, lines: (1, 5) },
-                Entry { doc_idx: 0, lines: (4, 8) }
+                Entry { doc_idx: 3, lines: (2, 5), cols: (1, 1) },
+                Entry { doc_idx: 0, lines: (1, 5), cols: (1, 1) },
+                Entry { doc_idx: 0, lines: (4, 8), cols: (1, 1) }
             ]);
 
             let sub = Sub {
                 dir_name: Some(PathBuf::from("dir/abcd/")),
+                archived: false,
                 documents: vec![
                     Doc::Processed(PathBuf::from("dir/abcd/first.arr"), Vec::new()),
                     Doc::Processed(PathBuf::from("dir/abcd/second.arr"), Vec::new()),
@@ -603,4 +840,39 @@ mod tests {
             assert_eq!(format_entries(&entries, &sub, &SubFileMode::Multi), exp_cell);
         }
     }
+
+    #[test]
+    fn test_groups_without_pairs() {
+        use fnv::FnvHashMap;
+        use std::process::Command;
+
+        // the harness captures printed output, so render in a child
+        // process (this test, rerun uncaptured) & inspect its stdout
+        if std::env::var_os("PYRET_MOSS_RENDER_CHILD").is_some() {
+            let overlaps = Overlaps {
+                hash_to_subs: FnvHashMap::default(),
+                pairs: vec![],
+                total_pairs: 0,
+                suppressed: 0,
+                clusters: vec![],
+                groups: vec![]
+            };
+            let opts = OptArgs { group_size: Some(3), no_pauses: true, ..OptArgs::default() };
+
+            render_tables(Path::new("subs"), &overlaps, None, &opts).unwrap();
+            return;
+        }
+
+        let child = Command::new(std::env::current_exe().unwrap())
+            .args(["results::tests::test_groups_without_pairs", "--exact", "--nocapture"])
+            .env("PYRET_MOSS_RENDER_CHILD", "1")
+            .output()
+            .unwrap();
+        let output = String::from_utf8_lossy(&child.stdout);
+
+        // the groups section is rendered even though no pairs were found
+        assert!(child.status.success());
+        assert!(output.contains("Groups of 3 or more submissions sharing fragments: 0"));
+        assert!(output.contains("Aye, no overlap was found!"));
+    }
 }
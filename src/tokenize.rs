@@ -0,0 +1,365 @@
+//! tokenize.rs: Lexer for Pyret programs, following the token grammar
+//! of pyret-lang (src/scripts/tokenize.js)
+
+/// TokenKind classifies a lexed token
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TokenKind {
+    Whitespace,
+    Comment,    // line (#) or block (#| |#, possibly nested) comment
+    String,     // single-, double-, or triple-quoted (```) string literal
+    Number,     // integer, decimal, rational, or rough (~) number
+    Name,       // identifier
+    Keyword,    // reserved word, possibly including a colon (e.g. check:)
+    Symbol,     // operator or punctuation
+    Other       // any other character (e.g. non-ASCII outside of strings)
+}
+
+/// A Span locates a token in the source text: start/end are byte
+/// offsets (end exclusive), and lines are the (1-indexed) lines on
+/// which the token starts & ends
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub start_line: i32,
+    pub end_line: i32
+}
+
+/// A Token is a lexeme of the source text along with its kind & location
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    pub span: Span
+}
+
+impl Token<'_> {
+    /// whether this token has no bearing on the meaning of the program
+    pub fn is_trivia(&self) -> bool {
+        self.kind == TokenKind::Whitespace || self.kind == TokenKind::Comment
+    }
+
+    /// whether this token is the given symbol
+    pub fn is_symbol(&self, sym: &str) -> bool {
+        self.kind == TokenKind::Symbol && self.text == sym
+    }
+}
+
+// reserved words, gleaned from pyret-lang/src/scripts/tokenize.js
+const KEYWORDS: [&str; 83] = [
+    "raises-other-than", "raises-satisfies", "raises-violates", "does-not-raise",
+    "provide-types", "otherwise:", "load-table", "is-roughly", "descending", "transform",
+    "satisfies", "is-not<=>", "examples:", "ascending", "violates", "type-let", "sharing:",
+    "sanitize", "provide:", "is-not=~", "is-not==", "examples", "source:", "reactor", "provide",
+    "newtype", "include", "extract", "else if", "because", "where:", "table:", "shadow", "select",
+    "raises", "module", "method", "letrec", "is-not", "import", "hiding", "extend", "check:", "block:",
+    "with:", "using", "then:", "sieve", "order", "is<=>", "false", "else:", "check", "cases", "when", "type",
+    "true", "row:", "lazy", "is=~", "is==", "from", "else", "doc:", "data", "var", "spy", "ref", "rec", "let", "lam",
+    "fun", "for", "end", "ask", "and", "or", "of", "is", "if", "do", "by", "as"
+];
+
+// operators made up of more than one character, longest first
+const MULTI_CHAR_SYMBOLS: [&str; 11] = [
+    "<=>", "...", "::", "->", "=>", ":=", "==", "=~", "<=", ">=", "<>"
+];
+
+/// Split a program into tokens, covering the entire text. Lexing never
+/// fails: unterminated strings extend to the end of their line (or the
+/// end of the program, for ```), & unterminated block comments extend
+/// to the end of the program
+pub fn tokenize(program: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;    // byte offset of the start of the next token
+    let mut line = 1;   // line on which the next token starts
+
+    while pos < program.len() {
+        let rest = &program[pos..];
+        let (kind, len) = next_token(rest);
+
+        let text = &rest[..len];
+        let end_line = line + text.matches('\n').count() as i32;
+
+        tokens.push(Token {
+            kind,
+            text,
+            span: Span { start: pos, end: pos + len, start_line: line, end_line }
+        });
+
+        pos += len;
+        line = end_line;
+    }
+
+    tokens
+}
+
+/// Determine the kind & length (in bytes) of the token at the start of rest
+fn next_token(rest: &str) -> (TokenKind, usize) {
+    let c = rest.chars().next().unwrap();
+
+    if c.is_whitespace() {
+        let len = rest.find(|c: char| !c.is_whitespace()).unwrap_or(rest.len());
+        return (TokenKind::Whitespace, len);
+    }
+
+    if rest.starts_with("#|") {
+        return (TokenKind::Comment, block_comment_len(rest));
+    }
+
+    if c == '#' {
+        return (TokenKind::Comment, rest.find('\n').unwrap_or(rest.len()));
+    }
+
+    if rest.starts_with("```") {
+        return (TokenKind::String, string_len(rest, "```"));
+    }
+
+    if c == '"' || c == '\'' {
+        return (TokenKind::String, string_len(rest, &rest[..1]));
+    }
+
+    if let Some(len) = number_len(rest) {
+        return (TokenKind::Number, len);
+    }
+
+    // prefer the longer of a keyword & name match, with keywords winning ties
+    let key_len = KEYWORDS.iter()
+        .filter_map(|k| keyword_len(rest, k))
+        .max();
+    let name_len = name_len(rest);
+
+    match (key_len, name_len) {
+        (Some(k), Some(n)) if n > k => return (TokenKind::Name, n),
+        (Some(k), _) => return (TokenKind::Keyword, k),
+        (None, Some(n)) => return (TokenKind::Name, n),
+        (None, None) => {},
+    }
+
+    if let Some(sym) = MULTI_CHAR_SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+        return (TokenKind::Symbol, sym.len());
+    }
+
+    if c.is_ascii_punctuation() {
+        return (TokenKind::Symbol, 1);
+    }
+
+    (TokenKind::Other, c.len_utf8())
+}
+
+/// length of the given keyword at the start of rest, if it's there. As in pyret-lang,
+/// the words of a keyword (i.e. `else if`) may be separated by any spaces or tabs,
+/// & a keyword ending in a name char may not be followed by more of a name
+fn keyword_len(rest: &str, keyword: &str) -> Option<usize> {
+    let mut len = 0;
+    let mut word_start = 0;
+
+    for (i, word) in keyword.split(' ').enumerate() {
+        if i > 0 {
+            let spaces = rest[len..].find(|c| c != ' ' && c != '\t').unwrap_or(rest.len() - len);
+            if spaces == 0 { return None; }
+            len += spaces;
+        }
+
+        if !rest[len..].starts_with(word) { return None; }
+        word_start = len;
+        len += word.len();
+    }
+
+    // a name char (or a hyphen & name chars) would continue the last word
+    if keyword.ends_with(|c: char| c.is_ascii_alphanumeric())
+        && name_len(&rest[word_start..]).is_some_and(|n| n > len - word_start) {
+        return None;
+    }
+
+    Some(len)
+}
+
+/// length of a (possibly nested) block comment starting at rest
+fn block_comment_len(rest: &str) -> usize {
+    let mut depth = 0;  // number of comments opened but not yet closed
+    let mut i = 0;
+
+    while i < rest.len() {
+        if rest[i..].starts_with("#|") {
+            depth += 1;
+            i += 2;
+        } else if rest[i..].starts_with("|#") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 { return i; }
+        } else {
+            i += rest[i..].chars().next().unwrap().len_utf8();
+        }
+    }
+
+    rest.len()  // unterminated
+}
+
+/// length of a string literal delimited by quote, starting at rest.
+/// Backslashes escape the character that follows them
+fn string_len(rest: &str, quote: &str) -> usize {
+    let multi_line = quote == "```";
+    let mut chars = rest.char_indices().skip(quote.chars().count()).peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();   // skip escaped char
+        } else if rest[i..].starts_with(quote) {
+            return i + quote.len();
+        } else if c == '\n' && !multi_line {
+            return i;   // unterminated: end at the line break
+        }
+    }
+
+    rest.len()  // unterminated
+}
+
+/// length of a number literal at the start of rest, if any:
+/// ~?digits(.digits)?(e[+-]?digits)? or ~?digits/digits
+fn number_len(rest: &str) -> Option<usize> {
+    let bytes = rest.as_bytes();
+    let digits_from = |i: usize| bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+
+    let mut i = if bytes[0] == b'~' { 1 } else { 0 };
+
+    let int_len = digits_from(i);
+    if int_len == 0 { return None; }
+    i += int_len;
+
+    // rational
+    if bytes.get(i) == Some(&b'/') && digits_from(i + 1) > 0 {
+        return Some(i + 1 + digits_from(i + 1));
+    }
+
+    // decimal part
+    if bytes.get(i) == Some(&b'.') && i + 1 < bytes.len() && digits_from(i + 1) > 0 {
+        i += 1 + digits_from(i + 1);
+    }
+
+    // exponent
+    if bytes.get(i) == Some(&b'e') || bytes.get(i) == Some(&b'E') {
+        let sign = match bytes.get(i + 1) {
+            Some(b'+') | Some(b'-') => 1,
+            _ => 0,
+        };
+        let exp_len = if i + 1 + sign <= bytes.len() { digits_from(i + 1 + sign) } else { 0 };
+        if exp_len > 0 { i += 1 + sign + exp_len; }
+    }
+
+    Some(i)
+}
+
+/// length of a name at the start of rest, if any:
+/// [_a-zA-Z][_a-zA-Z0-9]*(-+[_a-zA-Z0-9]+)*
+fn name_len(rest: &str) -> Option<usize> {
+    let bytes = rest.as_bytes();
+    let is_start = |b: u8| b == b'_' || b.is_ascii_alphabetic();
+    let is_part = |b: u8| b == b'_' || b.is_ascii_alphanumeric();
+
+    if !is_start(bytes[0]) { return None; }
+
+    let mut len = 1 + bytes[1..].iter().take_while(|&&b| is_part(b)).count();
+
+    // hyphenated segments (hyphens must be followed by more name chars)
+    loop {
+        let hyphens = bytes[len..].iter().take_while(|&&b| b == b'-').count();
+        if hyphens == 0 { break; }
+
+        let segment = bytes[len + hyphens..].iter().take_while(|&&b| is_part(b)).count();
+        if segment == 0 { break; }
+
+        len += hyphens + segment;
+    }
+
+    Some(len)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TokenKind::*;
+
+    // the kinds & texts of the tokens of a program, ignoring whitespace
+    fn lex(program: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(program).into_iter()
+            .filter(|t| t.kind != Whitespace)
+            .map(|t| (t.kind, t.text))
+            .collect()
+    }
+
+    #[test]
+    fn names_and_keywords() {
+        assert_eq!(lex("fun is-even(n): n end"), vec![
+            (Keyword, "fun"), (Name, "is-even"), (Symbol, "("), (Name, "n"), (Symbol, ")"),
+            (Symbol, ":"), (Name, "n"), (Keyword, "end")]);
+
+        // names that start with keywords are names
+        assert_eq!(lex("ending ifs or-else"), vec![(Name, "ending"), (Name, "ifs"), (Name, "or-else")]);
+
+        // keywords that include punctuation
+        assert_eq!(lex("check: x is== y is-not<=> z end"), vec![
+            (Keyword, "check:"), (Name, "x"), (Keyword, "is=="), (Name, "y"),
+            (Keyword, "is-not<=>"), (Name, "z"), (Keyword, "end")]);
+
+        // trailing hyphens aren't part of names
+        assert_eq!(lex("a-- b"), vec![(Name, "a"), (Symbol, "-"), (Symbol, "-"), (Name, "b")]);
+
+        // a keyword of several words ends where a word does, & may be spaced out
+        assert_eq!(lex("else iffy"), vec![(Keyword, "else"), (Name, "iffy")]);
+        assert_eq!(lex("else  if x"), vec![(Keyword, "else  if"), (Name, "x")]);
+        assert_eq!(lex("else\tif-x"), vec![(Keyword, "else"), (Name, "if-x")]);
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(lex(r#"x = "say \"hi\"" + 'it\'s'"#), vec![
+            (Name, "x"), (Symbol, "="), (String, r#""say \"hi\"""#), (Symbol, "+"),
+            (String, r"'it\'s'")]);
+
+        assert_eq!(lex("```a\n`b` \\``` c```"), vec![(String, "```a\n`b` \\``` c```")]);
+
+        // unterminated strings stop at the end of the line
+        assert_eq!(lex("\"open\nx"), vec![(String, "\"open"), (Name, "x")]);
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(lex("x # rest of line\ny"), vec![
+            (Name, "x"), (Comment, "# rest of line"), (Name, "y")]);
+
+        assert_eq!(lex("#| outer #| inner |# still outer |# x"), vec![
+            (Comment, "#| outer #| inner |# still outer |#"), (Name, "x")]);
+
+        assert_eq!(lex("#| never closed\nx"), vec![(Comment, "#| never closed\nx")]);
+    }
+
+    #[test]
+    fn numbers_and_symbols() {
+        assert_eq!(lex("1 ~2.5 3/4 1.5e-3 10.x"), vec![
+            (Number, "1"), (Number, "~2.5"), (Number, "3/4"), (Number, "1.5e-3"),
+            (Number, "10"), (Symbol, "."), (Name, "x")]);
+
+        assert_eq!(lex("a :: (A -> B) := c <=> d..."), vec![
+            (Name, "a"), (Symbol, "::"), (Symbol, "("), (Name, "A"), (Symbol, "->"), (Name, "B"),
+            (Symbol, ")"), (Symbol, ":="), (Name, "c"), (Symbol, "<=>"), (Name, "d"), (Symbol, "...")]);
+
+        assert_eq!(lex("é"), vec![(Other, "é")]);
+    }
+
+    #[test]
+    fn spans() {
+        let tokens = tokenize("x = ```a\nb```\ny");
+
+        assert_eq!(tokens[4], Token {
+            kind: String,
+            text: "```a\nb```",
+            span: Span { start: 4, end: 13, start_line: 1, end_line: 2 }
+        });
+        assert_eq!(tokens[6].span, Span { start: 14, end: 15, start_line: 3, end_line: 3 });
+
+        // tokens cover the entire program
+        let program = "fun f(x): x + \"é\" end # ok";
+        let text: std::string::String = tokenize(program).iter().map(|t| t.text).collect();
+        assert_eq!(text, program);
+    }
+}